@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use zbus::{Connection, fdo, interface, names::BusName, zvariant::OwnedValue};
+
+use super::{
+    DisplayBackend, MutterBackend,
+    mutter::{METHOD_PERSISTENT, METHOD_VERIFY},
+};
+use crate::{ApplyLogicalMonitorTuple, CurrentStateTuple, Result, connection::peer_to_peer};
+
+// In-process `org.gnome.Mutter.DisplayConfig` serving scripted monitors over peer-to-peer
+// connections, so `CurrentState` can be driven end-to-end without a GNOME session.
+
+const PATH: &str = "/org/gnome/Mutter/DisplayConfig";
+const INTERFACE: &str = "org.gnome.Mutter.DisplayConfig";
+
+/// A monitor of the fake compositor: connector, EDID identity and `(width, height, refresh)`
+/// modes, the first of which is preferred
+#[derive(Debug, Clone)]
+pub struct FakeMonitor {
+    pub connector: &'static str,
+    pub vendor: &'static str,
+    pub product: &'static str,
+    pub serial: &'static str,
+    pub is_builtin: bool,
+    pub modes: Vec<(i32, i32, f64)>,
+    pub preferred_scale: f64,
+}
+
+impl FakeMonitor {
+    pub fn builtin(connector: &'static str) -> Self {
+        Self {
+            connector,
+            vendor: "BOE",
+            product: "0x0bca",
+            serial: "0x00000000",
+            is_builtin: true,
+            modes: vec![(2256, 1504, 59.999), (1920, 1080, 60.0)],
+            preferred_scale: 1.5,
+        }
+    }
+
+    pub fn external(connector: &'static str, vendor: &'static str, product: &'static str) -> Self {
+        Self {
+            connector,
+            vendor,
+            product,
+            serial: "0x0000000a",
+            is_builtin: false,
+            modes: vec![(3840, 2160, 60.0), (1920, 1080, 60.0)],
+            preferred_scale: 1.0,
+        }
+    }
+
+    pub fn with_modes(mut self, modes: Vec<(i32, i32, f64)>) -> Self {
+        self.modes = modes;
+        self
+    }
+
+    pub fn mode_id(&self, index: usize) -> String {
+        let (width, height, refresh_rate) = self.modes[index];
+        format!("{width}x{height}@{refresh_rate:.3}")
+    }
+
+    fn display_name(&self) -> String {
+        match self.is_builtin {
+            true => "Built-in display".to_string(),
+            false => format!("{} {}", self.vendor, self.product),
+        }
+    }
+}
+
+/// What the fake compositor currently shows and what it was asked to do
+#[derive(Debug, Default)]
+pub struct FakeState {
+    pub serial: u32,
+    pub monitors: Vec<FakeMonitor>,
+    pub logical_monitors: Vec<ApplyLogicalMonitorTuple>,
+    /// Every configuration applied with method 1 or 2, in order
+    pub applied: Vec<Vec<ApplyLogicalMonitorTuple>>,
+    /// Number of ApplyMonitorsConfig calls with method 0
+    pub verified: usize,
+    /// Number of configurations applied with method 2
+    pub persisted: usize,
+    /// Accept configurations without changing anything, as a misbehaving compositor would
+    pub ignore_apply: bool,
+    /// Refuse every configuration with this reason, as Mutter does for layouts it cannot drive
+    pub reject: Option<String>,
+}
+
+impl FakeState {
+    /// Enable every monitor side by side at its preferred mode
+    fn arrange_side_by_side(&mut self) {
+        let mut x = 0;
+        self.logical_monitors = self
+            .monitors
+            .iter()
+            .enumerate()
+            .map(|(i, monitor)| {
+                let logical = (
+                    x,
+                    0,
+                    monitor.preferred_scale,
+                    0u32,
+                    i == 0,
+                    vec![(
+                        monitor.connector.to_string(),
+                        monitor.mode_id(0),
+                        HashMap::new(),
+                    )],
+                );
+                x += (monitor.modes[0].0 as f64 / monitor.preferred_scale).round() as i32;
+                logical
+            })
+            .collect();
+    }
+
+    fn current_mode(&self, connector: &str) -> Option<&str> {
+        self.logical_monitors
+            .iter()
+            .flat_map(|(_, _, _, _, _, assigned)| assigned)
+            .find(|(assigned, _, _)| assigned == connector)
+            .map(|(_, mode_id, _)| mode_id.as_str())
+    }
+
+    fn to_tuple(&self) -> CurrentStateTuple {
+        let monitors = self
+            .monitors
+            .iter()
+            .map(|monitor| {
+                let current_mode = self.current_mode(monitor.connector);
+                let modes = (0..monitor.modes.len())
+                    .map(|index| {
+                        let (width, height, refresh_rate) = monitor.modes[index];
+                        let id = monitor.mode_id(index);
+                        let mut properties = HashMap::new();
+                        if index == 0 {
+                            properties.insert("is-preferred".to_string(), true.into());
+                        }
+                        if current_mode == Some(id.as_str()) {
+                            properties.insert("is-current".to_string(), true.into());
+                        }
+                        (
+                            id,
+                            width,
+                            height,
+                            refresh_rate,
+                            monitor.preferred_scale,
+                            vec![1.0, monitor.preferred_scale],
+                            properties,
+                        )
+                    })
+                    .collect();
+
+                let properties = HashMap::from([
+                    ("is-builtin".to_string(), monitor.is_builtin.into()),
+                    (
+                        "display-name".to_string(),
+                        OwnedValue::try_from(zbus::zvariant::Value::from(monitor.display_name()))
+                            .unwrap(),
+                    ),
+                ]);
+
+                (connector_info(monitor), modes, properties)
+            })
+            .collect();
+
+        let logical_monitors = self
+            .logical_monitors
+            .iter()
+            .map(|(x, y, scale, transform, primary, assigned)| {
+                let assigned = assigned
+                    .iter()
+                    .filter_map(|(connector, _, _)| {
+                        self.monitors
+                            .iter()
+                            .find(|monitor| monitor.connector == connector)
+                            .map(connector_info)
+                    })
+                    .collect();
+                (
+                    *x,
+                    *y,
+                    *scale,
+                    *transform,
+                    *primary,
+                    assigned,
+                    HashMap::new(),
+                )
+            })
+            .collect();
+
+        (self.serial, monitors, logical_monitors, HashMap::new())
+    }
+}
+
+fn connector_info(monitor: &FakeMonitor) -> (String, String, String, String) {
+    (
+        monitor.connector.to_string(),
+        monitor.vendor.to_string(),
+        monitor.product.to_string(),
+        monitor.serial.to_string(),
+    )
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<FakeState>,
+    /// Server side of every connection handed out, to broadcast `MonitorsChanged`
+    servers: Mutex<Vec<Connection>>,
+}
+
+impl Shared {
+    async fn monitors_changed(&self) -> zbus::Result<()> {
+        let servers = self.servers.lock().unwrap().clone();
+        for server in servers {
+            server
+                .emit_signal(None::<BusName<'_>>, PATH, INTERFACE, "MonitorsChanged", &())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+struct DisplayConfig(Arc<Shared>);
+
+#[interface(name = "org.gnome.Mutter.DisplayConfig")]
+impl DisplayConfig {
+    fn get_current_state(&self) -> CurrentStateTuple {
+        self.0.state.lock().unwrap().to_tuple()
+    }
+
+    async fn apply_monitors_config(
+        &self,
+        serial: u32,
+        method: u32,
+        logical_monitors: Vec<ApplyLogicalMonitorTuple>,
+        _properties: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<()> {
+        {
+            let mut state = self.0.state.lock().unwrap();
+            // Mutter refuses configurations computed from an outdated GetCurrentState
+            if serial != state.serial {
+                return Err(fdo::Error::AccessDenied(
+                    "The requested configuration is based on stale information".into(),
+                ));
+            }
+
+            for (_, _, _, _, _, assigned) in &logical_monitors {
+                for (connector, mode_id, _) in assigned {
+                    let monitor = state
+                        .monitors
+                        .iter()
+                        .find(|monitor| monitor.connector == connector)
+                        .ok_or_else(|| {
+                            fdo::Error::InvalidArgs(format!("Invalid connector '{connector}'"))
+                        })?;
+                    if !(0..monitor.modes.len()).any(|index| monitor.mode_id(index) == *mode_id) {
+                        return Err(fdo::Error::InvalidArgs(format!(
+                            "Invalid mode '{mode_id}' specified"
+                        )));
+                    }
+                }
+            }
+
+            if let Some(reason) = &state.reject {
+                return Err(fdo::Error::InvalidArgs(reason.clone()));
+            }
+
+            if method == METHOD_VERIFY {
+                state.verified += 1;
+                return Ok(());
+            }
+
+            if method == METHOD_PERSISTENT {
+                state.persisted += 1;
+            }
+            state.applied.push(logical_monitors.clone());
+            if !state.ignore_apply {
+                state.logical_monitors = logical_monitors;
+                state.serial += 1;
+            }
+        }
+
+        self.0
+            .monitors_changed()
+            .await
+            .map_err(|error| fdo::Error::Failed(error.to_string()))
+    }
+}
+
+/// Handle on a running fake Mutter
+#[derive(Clone)]
+pub struct FakeMutter(Arc<Shared>);
+
+impl FakeMutter {
+    /// Start with `monitors` connected and enabled side by side
+    pub fn new(monitors: Vec<FakeMonitor>) -> Self {
+        let mut state = FakeState {
+            serial: 1,
+            monitors,
+            ..Default::default()
+        };
+        state.arrange_side_by_side();
+        Self(Arc::new(Shared {
+            state: Mutex::new(state),
+            servers: Mutex::default(),
+        }))
+    }
+
+    /// Open a new peer-to-peer connection and wrap it in a [`MutterBackend`]
+    pub async fn connect(&self) -> Result<Box<dyn DisplayBackend>> {
+        let (server, client) = peer_to_peer(PATH, DisplayConfig(self.0.clone())).await?;
+        self.0.servers.lock().unwrap().push(server);
+
+        Ok(Box::new(MutterBackend::new(&client).await?))
+    }
+
+    pub fn state(&self) -> std::sync::MutexGuard<'_, FakeState> {
+        self.0.state.lock().unwrap()
+    }
+
+    /// Replace the connected monitors, as on hotplug, and announce the change
+    pub async fn set_monitors(&self, monitors: Vec<FakeMonitor>) {
+        {
+            let mut state = self.state();
+            state.monitors = monitors;
+            state.arrange_side_by_side();
+            state.serial += 1;
+        }
+        self.0.monitors_changed().await.unwrap();
+    }
+}
@@ -0,0 +1,704 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures::{StreamExt as _, stream::BoxStream};
+use zbus::{
+    Connection,
+    zvariant::{OwnedValue, Value},
+};
+
+use super::{ApplyMethod, Capabilities, DisplayBackend};
+use crate::{
+    ApplyLogicalMonitorTuple, ConnectorInfo, CurrentLogicalMonitor, CurrentState, Monitor, Result,
+    generated::kscreen_proxy::BackendProxy, structs::Mode,
+};
+
+// KScreen serializes its `KScreen::Config` as nested `a{sv}` maps (the same model `kscreen-doctor`
+// works with). Only the keys we need are interpreted; everything else is passed back untouched.
+// https://invent.kde.org/plasma/libkscreen/-/blob/master/src/configserializer.cpp
+
+type KScreenMap = HashMap<String, OwnedValue>;
+
+/// `KScreen::Output::Type::Panel`
+const OUTPUT_TYPE_PANEL: i64 = 7;
+
+#[derive(Debug, Clone)]
+struct KScreenMode {
+    id: String,
+    width: i32,
+    height: i32,
+    refresh_rate: f64,
+}
+
+#[derive(Debug, Clone)]
+struct KScreenOutput {
+    id: i32,
+    name: String,
+    kind: i64,
+    connected: bool,
+    enabled: bool,
+    primary: bool,
+    x: i32,
+    y: i32,
+    scale: f64,
+    rotation: i64,
+    current_mode_id: String,
+    preferred_modes: Vec<String>,
+    modes: Vec<KScreenMode>,
+}
+
+impl KScreenOutput {
+    fn from_map(map: &KScreenMap) -> Self {
+        let (x, y) = get_map(map, "pos")
+            .map(|pos| (get_i64(&pos, "x"), get_i64(&pos, "y")))
+            .unwrap_or_default();
+
+        // Plasma 5.26+ replaced the `primary` flag with `priority` (1 = primary)
+        let primary = get_bool(map, "primary") || get_i64(map, "priority") == 1;
+
+        Self {
+            id: get_i64(map, "id") as i32,
+            name: get_string(map, "name"),
+            kind: get_i64(map, "type"),
+            connected: get_bool(map, "connected"),
+            enabled: get_bool(map, "enabled"),
+            primary,
+            x: x as i32,
+            y: y as i32,
+            scale: get_f64(map, "scale").unwrap_or(1.0),
+            rotation: get_i64(map, "rotation"),
+            current_mode_id: get_string(map, "currentModeId"),
+            preferred_modes: get_list(map, "preferredModes")
+                .into_iter()
+                .filter_map(as_string)
+                .collect(),
+            modes: get_list(map, "modes")
+                .into_iter()
+                .filter_map(as_map)
+                .map(|mode| {
+                    let (width, height) = get_map(&mode, "size")
+                        .map(|size| (get_i64(&size, "width"), get_i64(&size, "height")))
+                        .unwrap_or_default();
+                    KScreenMode {
+                        id: get_string(&mode, "id"),
+                        width: width as i32,
+                        height: height as i32,
+                        refresh_rate: get_f64(&mode, "refreshRate").unwrap_or_default(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn into_monitor(self, edid: Option<Edid>) -> Monitor {
+        let edid = edid.unwrap_or_default();
+        let modes = self
+            .modes
+            .iter()
+            .map(|mode| Mode {
+                id: mode.id.clone(),
+                width: mode.width,
+                height: mode.height,
+                refresh_rate: mode.refresh_rate,
+                is_current: mode.id == self.current_mode_id,
+                is_preferred: self.preferred_modes.contains(&mode.id),
+                // KWin accepts arbitrary scales, so keep whatever the user has chosen
+                preferred_scale: self.scale,
+                supported_scales: vec![],
+                properties: HashMap::new(),
+            })
+            .collect();
+
+        let display_name = if edid.product.is_empty() {
+            self.name.clone()
+        } else {
+            edid.product.clone()
+        };
+
+        let connector_info = ConnectorInfo {
+            connector: self.name,
+            vendor: edid.vendor,
+            product: edid.product,
+            serial: edid.serial,
+        };
+
+        Monitor {
+            is_builtin: self.kind == OUTPUT_TYPE_PANEL || connector_info.is_builtin_connector(),
+            is_underscanning: false,
+            min_refresh_rate: None,
+            display_name,
+            connector_info,
+            modes,
+            properties: HashMap::new(),
+        }
+    }
+}
+
+/// KDE Plasma's `org.kde.KScreen` backend service
+pub struct KScreenBackend(BackendProxy<'static>);
+
+impl KScreenBackend {
+    pub async fn new(connection: &Connection) -> Result<Self> {
+        Ok(Self(BackendProxy::new(connection).await?))
+    }
+}
+
+#[async_trait]
+impl DisplayBackend for KScreenBackend {
+    fn name(&self) -> &'static str {
+        "KScreen"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            mirroring: true,
+            primary: true,
+            verify: false,
+            persistent: false,
+        }
+    }
+
+    async fn get_current_state(&self) -> Result<CurrentState> {
+        current_state(&self.0).await
+    }
+
+    async fn apply_monitors_config(
+        &self,
+        _serial: u32,
+        method: ApplyMethod,
+        logical_monitors: &[ApplyLogicalMonitorTuple],
+    ) -> Result<()> {
+        // KScreen cannot check a configuration without applying it
+        if method == ApplyMethod::Verify {
+            return Ok(());
+        }
+
+        // KScreen takes its whole configuration map instead of logical monitors
+        let config = build_config(&self.0, logical_monitors).await?;
+        let config = config
+            .iter()
+            .map(|(key, value)| (key.as_str(), &**value))
+            .collect();
+        self.0.set_config(config).await?;
+        Ok(())
+    }
+
+    async fn receive_monitors_changed(&self) -> Result<BoxStream<'static, ()>> {
+        Ok(self.0.receive_config_changed().await?.map(|_| ()).boxed())
+    }
+}
+
+/// Read the KScreen configuration and map it onto the Mutter-style [`CurrentState`]
+async fn current_state(proxy: &BackendProxy<'_>) -> Result<CurrentState> {
+    let config = proxy.get_config().await?;
+    let outputs: Vec<KScreenOutput> = get_list(&config, "outputs")
+        .into_iter()
+        .filter_map(as_map)
+        .map(|map| KScreenOutput::from_map(&map))
+        .filter(|output| output.connected)
+        .collect();
+
+    // Outputs sharing a position are replicas of each other, like a Mutter logical monitor
+    let mut logical_monitors: Vec<CurrentLogicalMonitor> = vec![];
+    let mut monitors = vec![];
+
+    for output in outputs {
+        let edid = match proxy.get_edid(output.id).await {
+            Ok(bytes) => Edid::parse(&bytes),
+            Err(error) => {
+                eprintln!("Failed to read EDID for {}: {error}", output.name);
+                None
+            }
+        };
+
+        let monitor = output.clone().into_monitor(edid);
+
+        if output.enabled {
+            match logical_monitors
+                .iter_mut()
+                .find(|logical| logical.x == output.x && logical.y == output.y)
+            {
+                Some(logical) => {
+                    logical.primary |= output.primary;
+                    logical
+                        .assigned_monitors
+                        .push(monitor.connector_info.clone());
+                }
+                None => logical_monitors.push(CurrentLogicalMonitor {
+                    x: output.x,
+                    y: output.y,
+                    scale: output.scale,
+                    transform: rotation_to_transform(output.rotation),
+                    primary: output.primary,
+                    assigned_monitors: vec![monitor.connector_info.clone()],
+                }),
+            }
+        }
+
+        monitors.push(monitor);
+    }
+
+    Ok(CurrentState {
+        // KScreen has no configuration serial
+        serial: 0,
+        monitors,
+        logical_monitors,
+    })
+}
+
+/// Build the `setConfig` argument: the current KScreen configuration with every output
+/// enabled, positioned and moded according to `logical_monitors`, or disabled if absent.
+async fn build_config(
+    proxy: &BackendProxy<'_>,
+    logical_monitors: &[ApplyLogicalMonitorTuple],
+) -> Result<KScreenMap> {
+    let mut config = proxy.get_config().await?;
+
+    let raw_outputs: Vec<KScreenMap> = get_list(&config, "outputs")
+        .into_iter()
+        .filter_map(as_map)
+        .collect();
+
+    let ids_by_name: HashMap<String, i32> = raw_outputs
+        .iter()
+        .map(KScreenOutput::from_map)
+        .map(|output| (output.name, output.id))
+        .collect();
+
+    let mut next_priority = 2u32;
+    // KScreen needs a primary output: without one, the first enabled output is
+    let mut primary_pending = !logical_monitors
+        .iter()
+        .any(|(_, _, _, _, primary, _)| *primary);
+    let mut outputs = vec![];
+
+    for mut raw in raw_outputs {
+        let output = KScreenOutput::from_map(&raw);
+
+        let assignment = logical_monitors.iter().find_map(|logical| {
+            let (_, _, _, _, _, assigned) = logical;
+            assigned
+                .iter()
+                .position(|(connector, _, _)| *connector == output.name)
+                .map(|index| (logical, index))
+        });
+
+        match assignment {
+            Some(((x, y, scale, transform, primary, assigned), index)) => {
+                let mode_id = &assigned[index].1;
+                let pos = HashMap::from([("x", Value::from(*x)), ("y", Value::from(*y))]);
+
+                raw.insert("enabled".into(), true.into());
+                raw.insert("pos".into(), Value::from(pos).try_into()?);
+                raw.insert(
+                    "currentModeId".into(),
+                    Value::from(mode_id.as_str()).try_into()?,
+                );
+                raw.insert("scale".into(), (*scale).into());
+                raw.insert("rotation".into(), transform_to_rotation(*transform).into());
+
+                // Mirror every further monitor of a logical monitor from the first one
+                if raw.contains_key("replicationSource") {
+                    let source = match index {
+                        0 => 0,
+                        _ => ids_by_name.get(&assigned[0].0).copied().unwrap_or(0),
+                    };
+                    raw.insert("replicationSource".into(), source.into());
+                }
+
+                let is_primary = (*primary && index == 0) || std::mem::take(&mut primary_pending);
+                if raw.contains_key("priority") {
+                    let priority = if is_primary {
+                        1
+                    } else {
+                        next_priority += 1;
+                        next_priority - 1
+                    };
+                    raw.insert("priority".into(), priority.into());
+                } else {
+                    raw.insert("primary".into(), is_primary.into());
+                }
+            }
+            None if output.connected => {
+                raw.insert("enabled".into(), false.into());
+                if raw.contains_key("priority") {
+                    raw.insert("priority".into(), 0u32.into());
+                }
+            }
+            None => {}
+        }
+
+        outputs.push(OwnedValue::from(raw));
+    }
+
+    config.insert("outputs".into(), Value::from(outputs).try_into()?);
+
+    Ok(config)
+}
+
+/// `KScreen::Output::Rotation` flags by Mutter transform: `None`, `Left`, `Inverted` and
+/// `Right` are counterclockwise quarter turns, and `Flipped` to `Flipped270` (Plasma 6) the
+/// same after flipping horizontally
+const ROTATIONS: [i64; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
+
+fn rotation_to_transform(rotation: i64) -> u32 {
+    ROTATIONS
+        .iter()
+        .position(|candidate| *candidate == rotation)
+        .unwrap_or_default() as u32
+}
+
+fn transform_to_rotation(transform: u32) -> i32 {
+    ROTATIONS.get(transform as usize).copied().unwrap_or(1) as i32
+}
+
+/// Identity of a monitor as Mutter reports it, taken from its EDID
+#[derive(Debug, Default)]
+struct Edid {
+    vendor: String,
+    product: String,
+    serial: String,
+}
+
+impl Edid {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 128 || bytes[..8] != [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00] {
+            return None;
+        }
+
+        // Three 5-bit letters of the PNP manufacturer ID
+        let id = u16::from_be_bytes([bytes[8], bytes[9]]);
+        let vendor = [10, 5, 0]
+            .iter()
+            .map(|shift| (b'A' - 1 + ((id >> shift) & 0x1f) as u8) as char)
+            .collect();
+
+        let mut product = None;
+        let mut serial = None;
+        for descriptor in bytes[54..126].chunks(18) {
+            if descriptor[..3] != [0, 0, 0] {
+                continue;
+            }
+            let text = String::from_utf8_lossy(&descriptor[5..])
+                .split('\n')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            match descriptor[3] {
+                0xfc => product = Some(text),
+                0xff => serial = Some(text),
+                _ => {}
+            }
+        }
+
+        let product_code = u16::from_le_bytes([bytes[10], bytes[11]]);
+        let serial_number = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+
+        Some(Self {
+            vendor,
+            product: product.unwrap_or_else(|| format!("0x{product_code:04x}")),
+            serial: serial.unwrap_or_else(|| format!("0x{serial_number:08x}")),
+        })
+    }
+}
+
+/// Strip any `v` wrappers around a value
+fn unwrap_variant<'a>(value: &'a Value<'a>) -> &'a Value<'a> {
+    match value {
+        Value::Value(inner) => unwrap_variant(inner),
+        value => value,
+    }
+}
+
+fn as_map(value: &Value<'_>) -> Option<KScreenMap> {
+    let Value::Dict(dict) = unwrap_variant(value) else {
+        return None;
+    };
+    dict.iter()
+        .map(|(key, value)| {
+            let key = as_string(key)?;
+            let value = unwrap_variant(value).try_to_owned().ok()?;
+            Some((key, value))
+        })
+        .collect()
+}
+
+fn as_string(value: &Value<'_>) -> Option<String> {
+    match unwrap_variant(value) {
+        Value::Str(s) => Some(s.to_string()),
+        _ => None,
+    }
+}
+
+fn get_map(map: &KScreenMap, key: &str) -> Option<KScreenMap> {
+    map.get(key).and_then(|value| as_map(value))
+}
+
+fn get_list<'a>(map: &'a KScreenMap, key: &str) -> Vec<&'a Value<'a>> {
+    match map.get(key).map(|value| unwrap_variant(value)) {
+        Some(Value::Array(array)) => array.iter().map(unwrap_variant).collect(),
+        _ => vec![],
+    }
+}
+
+fn get_string(map: &KScreenMap, key: &str) -> String {
+    map.get(key)
+        .and_then(|value| as_string(value))
+        .unwrap_or_default()
+}
+
+fn get_bool(map: &KScreenMap, key: &str) -> bool {
+    matches!(
+        map.get(key).map(|value| unwrap_variant(value)),
+        Some(Value::Bool(true))
+    )
+}
+
+fn get_i64(map: &KScreenMap, key: &str) -> i64 {
+    match map.get(key).map(|value| unwrap_variant(value)) {
+        Some(Value::U8(v)) => *v as i64,
+        Some(Value::I16(v)) => *v as i64,
+        Some(Value::U16(v)) => *v as i64,
+        Some(Value::I32(v)) => *v as i64,
+        Some(Value::U32(v)) => *v as i64,
+        Some(Value::I64(v)) => *v,
+        Some(Value::U64(v)) => *v as i64,
+        _ => 0,
+    }
+}
+
+fn get_f64(map: &KScreenMap, key: &str) -> Option<f64> {
+    match map.get(key).map(|value| unwrap_variant(value)) {
+        Some(Value::F64(v)) => Some(*v),
+        Some(Value::I32(v)) => Some(*v as f64),
+        Some(Value::U32(v)) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use zbus::interface;
+
+    use super::*;
+    use crate::connection::peer_to_peer;
+
+    /// EDID of an Acer ET430K: manufacturer ACR, product code 0x0693, serial 0x1234abcd
+    #[rustfmt::skip]
+    const ACER_EDID: [u8; 128] = [
+        0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x04, 0x72, 0x93, 0x06, 0xcd, 0xab, 0x34, 0x12,
+        0x2a, 0x1f, 0x01, 0x04, 0xb5, 0x5f, 0x35, 0x78, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4d, 0xd0, 0x00, 0xa0, 0xf0, 0x70, 0x3e, 0x80, 0x30, 0x20,
+        0x35, 0x00, 0x5f, 0x59, 0x21, 0x00, 0x00, 0x1a, 0x00, 0x00, 0x00, 0xff, 0x00, 0x54, 0x31, 0x58,
+        0x41, 0x41, 0x30, 0x30, 0x31, 0x34, 0x32, 0x30, 0x30, 0x0a, 0x00, 0x00, 0x00, 0xfc, 0x00, 0x41,
+        0x63, 0x65, 0x72, 0x20, 0x45, 0x54, 0x34, 0x33, 0x30, 0x4b, 0x0a, 0x20, 0x00, 0x00, 0x00, 0xfd,
+        0x00, 0x17, 0x4c, 0x1e, 0x8c, 0x3c, 0x00, 0x0a, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x01, 0x90,
+    ];
+
+    /// In-process `org.kde.KScreen` holding one configuration, with EDID for output 2 only
+    #[derive(Clone)]
+    struct FakeKScreen(Arc<Mutex<KScreenMap>>);
+
+    impl FakeKScreen {
+        fn config(&self) -> KScreenMap {
+            let config = self.0.lock().unwrap();
+            config
+                .iter()
+                .map(|(key, value)| (key.clone(), value.try_clone().unwrap()))
+                .collect()
+        }
+
+        fn outputs(&self) -> Vec<KScreenOutput> {
+            get_list(&self.config(), "outputs")
+                .into_iter()
+                .filter_map(as_map)
+                .map(|map| KScreenOutput::from_map(&map))
+                .collect()
+        }
+    }
+
+    #[interface(name = "org.kde.kscreen.Backend")]
+    impl FakeKScreen {
+        #[zbus(name = "getConfig")]
+        fn get_config(&self) -> KScreenMap {
+            self.config()
+        }
+
+        #[zbus(name = "getEdid")]
+        fn get_edid(&self, output_id: i32) -> Vec<u8> {
+            match output_id {
+                2 => ACER_EDID.to_vec(),
+                _ => vec![],
+            }
+        }
+
+        #[zbus(name = "setConfig")]
+        fn set_config(&self, config: KScreenMap) -> KScreenMap {
+            *self.0.lock().unwrap() = config;
+            self.config()
+        }
+    }
+
+    /// A KScreen output as Plasma 6 serializes it, with one mode and highest priority first
+    fn output(id: i32, name: &str, kind: i32, (width, height): (i32, i32)) -> Value<'static> {
+        let size = HashMap::from([
+            ("width", Value::from(width)),
+            ("height", Value::from(height)),
+        ]);
+        let mode = HashMap::from([
+            ("id", Value::from("1")),
+            ("size", Value::from(size)),
+            ("refreshRate", Value::from(60.0)),
+        ]);
+        let x = if id == 1 { 0 } else { 1920 };
+        let pos = HashMap::from([("x", Value::from(x)), ("y", Value::from(0))]);
+        Value::from(HashMap::from([
+            ("id", Value::from(id)),
+            ("name", Value::from(name.to_string())),
+            ("type", Value::from(kind)),
+            ("connected", Value::from(true)),
+            ("enabled", Value::from(true)),
+            ("priority", Value::from(id as u32)),
+            ("pos", Value::from(pos)),
+            ("scale", Value::from(1.0)),
+            ("rotation", Value::from(1)),
+            ("currentModeId", Value::from("1")),
+            ("preferredModes", Value::from(vec![Value::from("1")])),
+            ("modes", Value::from(vec![Value::from(mode)])),
+            ("replicationSource", Value::from(0)),
+        ]))
+    }
+
+    async fn fake_kscreen() -> (FakeKScreen, Connection, KScreenBackend) {
+        let outputs = vec![
+            output(1, "eDP-1", OUTPUT_TYPE_PANEL as i32, (1920, 1200)),
+            output(2, "DP-1", 14, (3840, 2160)),
+        ];
+        let config = KScreenMap::from([(
+            "outputs".to_string(),
+            Value::from(outputs).try_into().unwrap(),
+        )]);
+        let fake = FakeKScreen(Arc::new(Mutex::new(config)));
+        let (server, client) = peer_to_peer("/backend", fake.clone()).await.unwrap();
+        let backend = KScreenBackend::new(&client).await.unwrap();
+        (fake, server, backend)
+    }
+
+    #[test]
+    fn parses_edid_identity() {
+        let edid = Edid::parse(&ACER_EDID).unwrap();
+        assert_eq!(edid.vendor, "ACR");
+        assert_eq!(edid.product, "Acer ET430K");
+        assert_eq!(edid.serial, "T1XAA0014200");
+
+        // Without name and serial descriptors, the numeric codes stand in
+        let mut bare = ACER_EDID;
+        bare[72..108].fill(0x10);
+        let edid = Edid::parse(&bare).unwrap();
+        assert_eq!(edid.product, "0x0693");
+        assert_eq!(edid.serial, "0x1234abcd");
+
+        assert!(Edid::parse(&ACER_EDID[..64]).is_none());
+        assert!(Edid::parse(&[0; 128]).is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_outputs_as_monitors() {
+        let (_fake, _server, backend) = fake_kscreen().await;
+        let state = backend.get_current_state().await.unwrap();
+
+        let [laptop, acer] = &state.monitors[..] else {
+            panic!("expected two monitors, got {:#?}", state.monitors);
+        };
+        assert!(laptop.is_builtin);
+        assert_eq!(laptop.display_name, "eDP-1");
+        assert!(!acer.is_builtin);
+        assert_eq!(acer.connector_info.vendor, "ACR");
+        assert_eq!(acer.display_name, "Acer ET430K");
+        assert!(acer.modes[0].is_current && acer.modes[0].is_preferred);
+
+        assert_eq!(state.logical_monitors.len(), 2);
+        assert!(state.logical_monitors[0].primary);
+        assert_eq!(state.logical_monitors[1].x, 1920);
+    }
+
+    #[tokio::test]
+    async fn builds_the_config_from_logical_monitors() {
+        let (fake, _server, backend) = fake_kscreen().await;
+
+        let logical_monitors: Vec<ApplyLogicalMonitorTuple> = vec![
+            (
+                0,
+                0,
+                1.5,
+                0,
+                true,
+                vec![("DP-1".into(), "1".into(), HashMap::new())],
+            ),
+            (
+                2560,
+                0,
+                1.0,
+                5,
+                false,
+                vec![("eDP-1".into(), "1".into(), HashMap::new())],
+            ),
+        ];
+        backend
+            .apply_monitors_config(0, ApplyMethod::Temporary, &logical_monitors)
+            .await
+            .unwrap();
+
+        let [laptop, acer] = &fake.outputs()[..] else {
+            panic!("expected two outputs");
+        };
+        assert!(acer.enabled && acer.primary);
+        assert_eq!((acer.x, acer.y, acer.scale), (0, 0, 1.5));
+        assert!(laptop.enabled && !laptop.primary);
+        assert_eq!((laptop.x, laptop.rotation), (2560, 32));
+
+        // Outputs left out are turned off
+        backend
+            .apply_monitors_config(0, ApplyMethod::Temporary, &logical_monitors[..1])
+            .await
+            .unwrap();
+        let [laptop, acer] = &fake.outputs()[..] else {
+            panic!("expected two outputs");
+        };
+        assert!(!laptop.enabled && !laptop.primary);
+        assert!(acer.enabled && acer.primary);
+
+        // Without a primary logical monitor, the first enabled output becomes primary
+        let mut without_primary = logical_monitors.clone();
+        without_primary[0].4 = false;
+        for (count, laptop_primary) in [(2, true), (1, false)] {
+            backend
+                .apply_monitors_config(0, ApplyMethod::Temporary, &without_primary[..count])
+                .await
+                .unwrap();
+            let [laptop, acer] = &fake.outputs()[..] else {
+                panic!("expected two outputs");
+            };
+            assert_eq!(
+                (laptop.primary, acer.primary),
+                (laptop_primary, !laptop_primary)
+            );
+            assert!(acer.enabled);
+        }
+    }
+
+    #[test]
+    fn converts_rotations_and_transforms() {
+        for (transform, rotation) in [(0, 1), (1, 2), (2, 4), (3, 8), (4, 16), (7, 128)] {
+            assert_eq!(rotation_to_transform(rotation), transform);
+            assert_eq!(transform_to_rotation(transform), rotation as i32);
+        }
+        assert_eq!(rotation_to_transform(0), 0);
+        assert_eq!(rotation_to_transform(3), 0);
+        assert_eq!(transform_to_rotation(8), 1);
+    }
+}
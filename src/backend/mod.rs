@@ -0,0 +1,104 @@
+mod kscreen;
+pub use kscreen::KScreenBackend;
+
+mod mutter;
+pub use mutter::{MuffinBackend, MutterBackend};
+
+mod wlroots;
+pub use wlroots::WlrBackend;
+
+#[cfg(test)]
+pub mod fake_mutter;
+
+use std::fmt;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use crate::{
+    ApplyLogicalMonitorTuple, CurrentState, Error, Result, connect, detection::DesktopEnvironment,
+};
+
+/// What a backend can do beyond reading and applying logical monitors
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    /// Several monitors can show the same logical monitor
+    pub mirroring: bool,
+    /// One logical monitor can be marked as primary
+    pub primary: bool,
+    /// A configuration can be checked without applying it
+    pub verify: bool,
+    /// A configuration can be stored so that it survives logout
+    pub persistent: bool,
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let supported = [
+            (self.mirroring, "mirroring"),
+            (self.primary, "primary"),
+            (self.verify, "verify"),
+            (self.persistent, "persistent"),
+        ]
+        .into_iter()
+        .filter_map(|(supported, name)| supported.then_some(name))
+        .collect::<Vec<_>>();
+
+        match supported.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", supported.join(", ")),
+        }
+    }
+}
+
+/// How a configuration is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyMethod {
+    /// Only check that the compositor would accept the configuration; backends without
+    /// [`Capabilities::verify`] accept anything
+    Verify,
+    /// Until another configuration is applied or the session ends
+    Temporary,
+    /// Also stored by the compositor (Mutter's `~/.config/monitors.xml`) so that it survives
+    /// logout; backends without [`Capabilities::persistent`] apply it temporarily
+    Persistent,
+}
+
+/// A compositor interface that can report and change the monitor configuration
+#[async_trait]
+pub trait DisplayBackend: Send + Sync {
+    /// Human-readable name of the compositor interface
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    async fn get_current_state(&self) -> Result<CurrentState>;
+
+    /// Apply `logical_monitors`; monitors not listed are disabled
+    async fn apply_monitors_config(
+        &self,
+        serial: u32,
+        method: ApplyMethod,
+        logical_monitors: &[ApplyLogicalMonitorTuple],
+    ) -> Result<()>;
+
+    /// Stream that yields whenever the monitor configuration changes
+    async fn receive_monitors_changed(&self) -> Result<BoxStream<'static, ()>>;
+}
+
+/// Connect to the backend of the current desktop environment
+pub async fn connect_backend(max_attempts: usize) -> Result<Box<dyn DisplayBackend>> {
+    Ok(match DesktopEnvironment::detect() {
+        DesktopEnvironment::Gnome => {
+            Box::new(MutterBackend::new(&connect(max_attempts).await?).await?)
+        }
+        DesktopEnvironment::Cinnamon => {
+            Box::new(MuffinBackend::new(&connect(max_attempts).await?).await?)
+        }
+        DesktopEnvironment::Kde => {
+            Box::new(KScreenBackend::new(&connect(max_attempts).await?).await?)
+        }
+        DesktopEnvironment::Wlroots => Box::new(WlrBackend::connect()?),
+        DesktopEnvironment::Unknown(desktop) => return Err(Error::UnsupportedDesktop(desktop)),
+    })
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures::{StreamExt as _, stream::BoxStream};
+use zbus::{Connection, zvariant::Value};
+
+use super::{ApplyMethod, Capabilities, DisplayBackend};
+use crate::{
+    ApplyLogicalMonitorTuple, CurrentState, Error, Result,
+    generated::{cinnamon_proxy, gnome_proxy},
+};
+
+// ApplyMonitorsConfig method (0 = verify, 1 = temporary, 2 = persistent)
+// https://browse.dgit.debian.org/mutter.git/plain/data/dbus-interfaces/org.gnome.Mutter.DisplayConfig.xml
+pub(super) const METHOD_VERIFY: u32 = 0;
+pub(super) const METHOD_TEMPORARY: u32 = 1;
+pub(super) const METHOD_PERSISTENT: u32 = 2;
+
+type MonitorAssignment<'a> = (&'a str, &'a str, HashMap<&'a str, &'a Value<'a>>);
+
+/// Borrow the connectors, mode ids and properties of `logical_monitors` in the shape expected
+/// by the generated `apply_monitors_config` proxy methods
+fn monitor_assignments(
+    logical_monitors: &[ApplyLogicalMonitorTuple],
+) -> Vec<Vec<MonitorAssignment<'_>>> {
+    logical_monitors
+        .iter()
+        .map(|(_, _, _, _, _, monitors)| {
+            monitors
+                .iter()
+                .map(|(connector, mode_id, properties)| {
+                    let properties = properties
+                        .iter()
+                        .map(|(key, value)| (key.as_str(), &**value))
+                        .collect();
+                    (connector.as_str(), mode_id.as_str(), properties)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Mutter refuses a configuration with a D-Bus error saying why, e.g. "Logical monitors not
+/// adjacent" or "Invalid mode '...' specified"
+fn rejection(error: zbus::Error, backend: &'static str) -> Error {
+    match error {
+        zbus::Error::MethodError(name, reason, _) => Error::ConfigurationRejected {
+            backend,
+            reason: reason.unwrap_or_else(|| name.to_string()),
+            error: name.to_string(),
+        },
+        error => error.into(),
+    }
+}
+
+/// Mutter and Muffin expose the same DisplayConfig interface under different names
+macro_rules! display_config_backend {
+    ($(#[$doc:meta])* $backend:ident, $proxy:ty, $name:literal) => {
+        $(#[$doc])*
+        pub struct $backend($proxy);
+
+        impl $backend {
+            pub async fn new(connection: &Connection) -> Result<Self> {
+                Ok(Self(<$proxy>::new(connection).await?))
+            }
+        }
+
+        #[async_trait]
+        impl DisplayBackend for $backend {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn capabilities(&self) -> Capabilities {
+                Capabilities {
+                    mirroring: true,
+                    primary: true,
+                    verify: true,
+                    persistent: true,
+                }
+            }
+
+            async fn get_current_state(&self) -> Result<CurrentState> {
+                Ok(self.0.get_current_state().await?.into())
+            }
+
+            async fn apply_monitors_config(
+                &self,
+                serial: u32,
+                method: ApplyMethod,
+                logical_monitors: &[ApplyLogicalMonitorTuple],
+            ) -> Result<()> {
+                let method = match method {
+                    ApplyMethod::Verify => METHOD_VERIFY,
+                    ApplyMethod::Temporary => METHOD_TEMPORARY,
+                    ApplyMethod::Persistent => METHOD_PERSISTENT,
+                };
+                let assignments = monitor_assignments(logical_monitors);
+                let assignments: Vec<Vec<&MonitorAssignment>> = assignments
+                    .iter()
+                    .map(|monitors| monitors.iter().collect())
+                    .collect();
+                let logical_monitors: Vec<_> = logical_monitors
+                    .iter()
+                    .zip(&assignments)
+                    .map(|((x, y, scale, transform, primary, _), monitors)| {
+                        (*x, *y, *scale, *transform, *primary, monitors.as_slice())
+                    })
+                    .collect();
+                let logical_monitors: Vec<_> = logical_monitors.iter().collect();
+
+                self.0
+                    .apply_monitors_config(
+                        serial,
+                        method,
+                        &logical_monitors,
+                        HashMap::new(),
+                    )
+                    .await
+                    .map_err(|error| rejection(error, $name))
+            }
+
+            async fn receive_monitors_changed(&self) -> Result<BoxStream<'static, ()>> {
+                Ok(self.0.receive_monitors_changed().await?.map(|_| ()).boxed())
+            }
+        }
+    };
+}
+
+display_config_backend!(
+    /// GNOME's `org.gnome.Mutter.DisplayConfig`
+    MutterBackend,
+    gnome_proxy::DisplayConfigProxy<'static>,
+    "Mutter"
+);
+
+display_config_backend!(
+    /// Cinnamon's `org.cinnamon.Muffin.DisplayConfig`
+    MuffinBackend,
+    cinnamon_proxy::DisplayConfigProxy<'static>,
+    "Muffin"
+);
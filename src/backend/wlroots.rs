@@ -0,0 +1,1052 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use async_trait::async_trait;
+use futures::{
+    StreamExt as _,
+    channel::{mpsc, oneshot},
+    stream::BoxStream,
+};
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum, event_created_child,
+    globals::{GlobalListContents, registry_queue_init},
+    protocol::{wl_callback::WlCallback, wl_output::Transform, wl_registry::WlRegistry},
+};
+use wayland_protocols_wlr::output_management::v1::client::{
+    zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1,
+    zwlr_output_configuration_v1::{self, ZwlrOutputConfigurationV1},
+    zwlr_output_head_v1::{self, ZwlrOutputHeadV1},
+    zwlr_output_manager_v1::{self, ZwlrOutputManagerV1},
+    zwlr_output_mode_v1::{self, ZwlrOutputModeV1},
+};
+
+use super::{ApplyMethod, Capabilities, DisplayBackend};
+use crate::{
+    ApplyLogicalMonitorTuple, ConnectorInfo, CurrentLogicalMonitor, CurrentState, Error, Monitor,
+    Result, structs::Mode,
+};
+
+// wlr-output-management, implemented by Sway, Hyprland, river, labwc, niri and other wlroots-style
+// compositors. Events are dispatched on a dedicated thread and published on every `done`.
+// https://wayland.app/protocols/wlr-output-management-unstable-v1
+
+/// Highest protocol version we understand; `zwlr_output_mode_v1` stops at 3.
+const MANAGER_VERSION: u32 = 3;
+
+#[derive(Debug, Clone)]
+struct HeadMode {
+    proxy: ZwlrOutputModeV1,
+    width: i32,
+    height: i32,
+    /// Refresh rate in mHz
+    refresh: i32,
+    preferred: bool,
+}
+
+impl HeadMode {
+    /// Mutter-style mode id, e.g. `3840x2160@59.997`
+    fn id(&self) -> String {
+        format!(
+            "{}x{}@{:.3}",
+            self.width,
+            self.height,
+            self.refresh as f64 / 1000.0
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Head {
+    proxy: ZwlrOutputHeadV1,
+    name: String,
+    description: String,
+    make: String,
+    model: String,
+    serial_number: String,
+    enabled: bool,
+    current_mode: Option<ZwlrOutputModeV1>,
+    x: i32,
+    y: i32,
+    transform: u32,
+    scale: f64,
+    modes: Vec<HeadMode>,
+}
+
+impl Head {
+    fn new(proxy: ZwlrOutputHeadV1) -> Self {
+        Self {
+            proxy,
+            name: String::new(),
+            description: String::new(),
+            make: String::new(),
+            model: String::new(),
+            serial_number: String::new(),
+            enabled: false,
+            current_mode: None,
+            x: 0,
+            y: 0,
+            transform: 0,
+            scale: 1.0,
+            modes: vec![],
+        }
+    }
+
+    fn to_monitor(&self) -> Monitor {
+        let connector_info = ConnectorInfo {
+            connector: self.name.clone(),
+            vendor: self.make.clone(),
+            product: self.model.clone(),
+            serial: self.serial_number.clone(),
+        };
+
+        let display_name = match self.description.is_empty() {
+            true => format!("{} {}", self.make, self.model).trim().to_string(),
+            false => self.description.clone(),
+        };
+
+        let modes = self
+            .modes
+            .iter()
+            .map(|mode| Mode {
+                id: mode.id(),
+                width: mode.width,
+                height: mode.height,
+                refresh_rate: mode.refresh as f64 / 1000.0,
+                is_current: self.current_mode.as_ref() == Some(&mode.proxy),
+                is_preferred: mode.preferred,
+                // The protocol has no notion of a preferred scale, so keep the current one
+                preferred_scale: self.scale,
+                supported_scales: vec![],
+                properties: HashMap::new(),
+            })
+            .collect();
+
+        Monitor {
+            is_builtin: connector_info.is_builtin_connector(),
+            is_underscanning: false,
+            min_refresh_rate: None,
+            display_name,
+            connector_info,
+            modes,
+            properties: HashMap::new(),
+        }
+    }
+}
+
+/// The last complete set of heads announced by the compositor
+#[derive(Debug, Default)]
+struct Published {
+    serial: u32,
+    heads: Vec<Head>,
+    subscribers: Vec<mpsc::UnboundedSender<()>>,
+}
+
+/// Dispatch state, owned by the event thread
+struct WlrState {
+    heads: Vec<Head>,
+    published: Arc<Mutex<Published>>,
+    finished: bool,
+}
+
+impl WlrState {
+    fn head_mut(&mut self, proxy: &ZwlrOutputHeadV1) -> Option<&mut Head> {
+        self.heads.iter_mut().find(|head| head.proxy == *proxy)
+    }
+
+    fn mode_mut(&mut self, proxy: &ZwlrOutputModeV1) -> Option<&mut HeadMode> {
+        self.heads
+            .iter_mut()
+            .flat_map(|head| head.modes.iter_mut())
+            .find(|mode| mode.proxy == *proxy)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ConfigurationOutcome {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+type OutcomeSender = Mutex<Option<oneshot::Sender<ConfigurationOutcome>>>;
+
+type SyncSender = Mutex<Option<oneshot::Sender<()>>>;
+
+pub struct WlrBackend {
+    connection: Connection,
+    queue_handle: QueueHandle<WlrState>,
+    manager: ZwlrOutputManagerV1,
+    published: Arc<Mutex<Published>>,
+}
+
+impl WlrBackend {
+    /// Connect to the compositor at `$WAYLAND_DISPLAY`
+    pub fn connect() -> Result<Self> {
+        Self::from_connection(Connection::connect_to_env()?)
+    }
+
+    /// Bind the output manager on an existing connection and start dispatching its events
+    pub fn from_connection(connection: Connection) -> Result<Self> {
+        let (globals, mut queue): (_, EventQueue<WlrState>) = registry_queue_init(&connection)?;
+        let queue_handle = queue.handle();
+
+        let manager: ZwlrOutputManagerV1 = globals
+            .bind(&queue_handle, 1..=MANAGER_VERSION, ())
+            .map_err(|_| Error::UnsupportedProtocol(ZwlrOutputManagerV1::interface().name))?;
+
+        let published = Arc::new(Mutex::new(Published::default()));
+        let mut state = WlrState {
+            heads: vec![],
+            published: published.clone(),
+            finished: false,
+        };
+
+        // Receive the initial heads and modes, terminated by `done`
+        queue.roundtrip(&mut state)?;
+        queue.roundtrip(&mut state)?;
+
+        thread::spawn(move || {
+            while !state.finished && queue.blocking_dispatch(&mut state).is_ok() {}
+            // The manager was stopped or the compositor went away: end every change stream
+            state.published.lock().unwrap().subscribers.clear();
+        });
+
+        Ok(Self {
+            connection,
+            queue_handle,
+            manager,
+            published,
+        })
+    }
+
+    /// Wait until the compositor has processed everything sent so far
+    async fn sync(&self) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.connection
+            .display()
+            .sync(&self.queue_handle, Mutex::new(Some(sender)));
+        self.connection.flush()?;
+        receiver.await.map_err(|_| Error::WaylandDisconnected)
+    }
+
+    /// Send `logical_monitors` as an output configuration, either as a test or for real
+    async fn configure(
+        &self,
+        serial: u32,
+        logical_monitors: &[ApplyLogicalMonitorTuple],
+        test: bool,
+    ) -> Result<()> {
+        let heads = self.published.lock().unwrap().heads.clone();
+
+        let (sender, receiver) = oneshot::channel();
+        let configuration =
+            self.manager
+                .create_configuration(serial, &self.queue_handle, Mutex::new(Some(sender)));
+
+        for head in &heads {
+            let assignment = logical_monitors.iter().find_map(|logical| {
+                let (x, y, scale, transform, _, assigned) = logical;
+                assigned
+                    .iter()
+                    .find(|(connector, _, _)| *connector == head.name)
+                    .map(|(_, mode_id, _)| (*x, *y, *scale, *transform, mode_id))
+            });
+
+            let Some((x, y, scale, transform, mode_id)) = assignment else {
+                configuration.disable_head(&head.proxy);
+                continue;
+            };
+
+            let configuration_head = configuration.enable_head(&head.proxy, &self.queue_handle, ());
+            if let Some(mode) = head.modes.iter().find(|mode| mode.id() == *mode_id) {
+                configuration_head.set_mode(&mode.proxy);
+            }
+            configuration_head.set_position(x, y);
+            configuration_head
+                .set_transform(Transform::try_from(transform).unwrap_or(Transform::Normal));
+            configuration_head.set_scale(scale);
+        }
+
+        match test {
+            true => configuration.test(),
+            false => configuration.apply(),
+        }
+        self.connection.flush()?;
+
+        let outcome = receiver.await.map_err(|_| Error::WaylandDisconnected);
+        configuration.destroy();
+        self.connection.flush()?;
+
+        match outcome? {
+            ConfigurationOutcome::Succeeded => Ok(()),
+            // The protocol gives no reason
+            ConfigurationOutcome::Failed => Err(Error::ConfigurationRejected {
+                backend: "wlroots",
+                error: "zwlr_output_configuration_v1.failed".to_string(),
+                reason: "the compositor cannot use this configuration".to_string(),
+            }),
+            ConfigurationOutcome::Cancelled => Err(Error::ConfigurationCancelled("wlroots")),
+        }
+    }
+}
+
+#[async_trait]
+impl DisplayBackend for WlrBackend {
+    fn name(&self) -> &'static str {
+        "wlroots"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            // The protocol has no notion of several heads sharing one output
+            mirroring: false,
+            primary: false,
+            verify: true,
+            persistent: false,
+        }
+    }
+
+    async fn get_current_state(&self) -> Result<CurrentState> {
+        self.sync().await?;
+        let published = self.published.lock().unwrap();
+
+        let monitors = published.heads.iter().map(Head::to_monitor).collect();
+
+        // Heads sharing a position show the same content, like a Mutter logical monitor
+        let mut logical_monitors: Vec<CurrentLogicalMonitor> = vec![];
+        for head in published.heads.iter().filter(|head| head.enabled) {
+            let connector_info = head.to_monitor().connector_info;
+            match logical_monitors
+                .iter_mut()
+                .find(|logical| logical.x == head.x && logical.y == head.y)
+            {
+                Some(logical) => logical.assigned_monitors.push(connector_info),
+                None => logical_monitors.push(CurrentLogicalMonitor {
+                    x: head.x,
+                    y: head.y,
+                    scale: head.scale,
+                    transform: head.transform,
+                    // There is no primary output in wlroots; treat the one at the origin as such
+                    primary: head.x == 0 && head.y == 0,
+                    assigned_monitors: vec![connector_info],
+                }),
+            }
+        }
+
+        Ok(CurrentState {
+            serial: published.serial,
+            monitors,
+            logical_monitors,
+        })
+    }
+
+    /// Test `logical_monitors` with the compositor when verifying, apply them otherwise
+    async fn apply_monitors_config(
+        &self,
+        serial: u32,
+        method: ApplyMethod,
+        logical_monitors: &[ApplyLogicalMonitorTuple],
+    ) -> Result<()> {
+        self.configure(serial, logical_monitors, method == ApplyMethod::Verify)
+            .await
+    }
+
+    /// Stream that yields whenever the compositor announces a new output configuration
+    async fn receive_monitors_changed(&self) -> Result<BoxStream<'static, ()>> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.published.lock().unwrap().subscribers.push(sender);
+        Ok(receiver.boxed())
+    }
+}
+
+impl Drop for WlrBackend {
+    /// Ask the compositor to finish the manager, which ends the event thread
+    fn drop(&mut self) {
+        self.manager.stop();
+        let _ = self.connection.flush();
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for WlrState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlCallback, SyncSender> for WlrState {
+    fn event(
+        _: &mut Self,
+        _: &WlCallback,
+        _: <WlCallback as Proxy>::Event,
+        sender: &SyncSender,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let Some(sender) = sender.lock().unwrap().take() {
+            let _ = sender.send(());
+        }
+    }
+}
+
+impl Dispatch<ZwlrOutputManagerV1, ()> for WlrState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrOutputManagerV1,
+        event: zwlr_output_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_output_manager_v1::Event::Head { head } => state.heads.push(Head::new(head)),
+            zwlr_output_manager_v1::Event::Done { serial } => {
+                let mut published = state.published.lock().unwrap();
+                published.serial = serial;
+                published.heads = state.heads.clone();
+                published
+                    .subscribers
+                    .retain(|subscriber| subscriber.unbounded_send(()).is_ok());
+            }
+            zwlr_output_manager_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(WlrState, ZwlrOutputManagerV1, [
+        zwlr_output_manager_v1::EVT_HEAD_OPCODE => (ZwlrOutputHeadV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrOutputHeadV1, ()> for WlrState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrOutputHeadV1,
+        event: zwlr_output_head_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_output_head_v1::Event::Finished = event {
+            state.heads.retain(|head| head.proxy != *proxy);
+            return;
+        }
+
+        let Some(head) = state.head_mut(proxy) else {
+            return;
+        };
+
+        match event {
+            zwlr_output_head_v1::Event::Name { name } => head.name = name,
+            zwlr_output_head_v1::Event::Description { description } => {
+                head.description = description
+            }
+            zwlr_output_head_v1::Event::Make { make } => head.make = make,
+            zwlr_output_head_v1::Event::Model { model } => head.model = model,
+            zwlr_output_head_v1::Event::SerialNumber { serial_number } => {
+                head.serial_number = serial_number
+            }
+            zwlr_output_head_v1::Event::Mode { mode } => head.modes.push(HeadMode {
+                proxy: mode,
+                width: 0,
+                height: 0,
+                refresh: 0,
+                preferred: false,
+            }),
+            zwlr_output_head_v1::Event::Enabled { enabled } => {
+                head.enabled = enabled != 0;
+                if !head.enabled {
+                    head.current_mode = None;
+                }
+            }
+            zwlr_output_head_v1::Event::CurrentMode { mode } => head.current_mode = Some(mode),
+            zwlr_output_head_v1::Event::Position { x, y } => (head.x, head.y) = (x, y),
+            zwlr_output_head_v1::Event::Transform { transform } => {
+                head.transform = match transform {
+                    WEnum::Value(transform) => transform.into(),
+                    WEnum::Unknown(transform) => transform,
+                }
+            }
+            zwlr_output_head_v1::Event::Scale { scale } => head.scale = scale,
+            _ => {}
+        }
+    }
+
+    event_created_child!(WlrState, ZwlrOutputHeadV1, [
+        zwlr_output_head_v1::EVT_MODE_OPCODE => (ZwlrOutputModeV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrOutputModeV1, ()> for WlrState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrOutputModeV1,
+        event: zwlr_output_mode_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_output_mode_v1::Event::Finished = event {
+            for head in &mut state.heads {
+                head.modes.retain(|mode| mode.proxy != *proxy);
+            }
+            return;
+        }
+
+        let Some(mode) = state.mode_mut(proxy) else {
+            return;
+        };
+
+        match event {
+            zwlr_output_mode_v1::Event::Size { width, height } => {
+                (mode.width, mode.height) = (width, height)
+            }
+            zwlr_output_mode_v1::Event::Refresh { refresh } => mode.refresh = refresh,
+            zwlr_output_mode_v1::Event::Preferred => mode.preferred = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrOutputConfigurationV1, OutcomeSender> for WlrState {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrOutputConfigurationV1,
+        event: zwlr_output_configuration_v1::Event,
+        sender: &OutcomeSender,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let outcome = match event {
+            zwlr_output_configuration_v1::Event::Succeeded => ConfigurationOutcome::Succeeded,
+            zwlr_output_configuration_v1::Event::Failed => ConfigurationOutcome::Failed,
+            zwlr_output_configuration_v1::Event::Cancelled => ConfigurationOutcome::Cancelled,
+            _ => return,
+        };
+        if let Some(sender) = sender.lock().unwrap().take() {
+            let _ = sender.send(outcome);
+        }
+    }
+}
+
+impl Dispatch<ZwlrOutputConfigurationHeadV1, ()> for WlrState {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrOutputConfigurationHeadV1,
+        _: <ZwlrOutputConfigurationHeadV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::net::UnixStream,
+        sync::atomic::{AtomicBool, Ordering},
+        thread::JoinHandle,
+        time::Duration,
+    };
+
+    use wayland_protocols_wlr::output_management::v1::server::{
+        zwlr_output_configuration_head_v1::{self as server_configuration_head},
+        zwlr_output_configuration_v1::{self as server_configuration},
+        zwlr_output_head_v1::ZwlrOutputHeadV1 as ServerHead,
+        zwlr_output_manager_v1::{self as server_manager, ZwlrOutputManagerV1 as ServerManager},
+        zwlr_output_mode_v1::ZwlrOutputModeV1 as ServerMode,
+    };
+    use wayland_server::{
+        Client, DataInit, Dispatch, Display, DisplayHandle, GlobalDispatch, New, Resource as _,
+        backend::ClientData,
+    };
+
+    use super::*;
+    use crate::{
+        cli::DisplayMode,
+        current_state::{build_joined_or_individual, build_mirrored},
+    };
+
+    /// An output of the fake compositor: connector, make, model and `(width, height, mHz)` modes,
+    /// the first of which is preferred
+    #[derive(Debug, Clone)]
+    struct FakeHead {
+        name: &'static str,
+        make: &'static str,
+        model: &'static str,
+        modes: Vec<(i32, i32, i32)>,
+        enabled: bool,
+        current_mode: usize,
+        position: (i32, i32),
+        scale: f64,
+    }
+
+    impl FakeHead {
+        fn new(name: &'static str, model: &'static str, modes: Vec<(i32, i32, i32)>) -> Self {
+            Self {
+                name,
+                make: "Fake",
+                model,
+                modes,
+                enabled: false,
+                current_mode: 0,
+                position: (0, 0),
+                scale: 1.0,
+            }
+        }
+
+        fn enabled(mut self) -> Self {
+            self.enabled = true;
+            self
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct PendingHead {
+        head: usize,
+        mode: Option<usize>,
+        position: (i32, i32),
+        scale: f64,
+    }
+
+    #[derive(Debug, Default)]
+    struct PendingConfiguration {
+        serial: u32,
+        enabled: Vec<PendingHead>,
+        disabled: Vec<usize>,
+    }
+
+    type SharedPending = Arc<Mutex<PendingConfiguration>>;
+
+    /// Minimal wlr-output-management compositor serving a single client
+    #[derive(Default)]
+    struct FakeCompositor {
+        heads: Vec<FakeHead>,
+        serial: u32,
+        reject: bool,
+        tested: usize,
+        applied: usize,
+        resources: Vec<(ServerHead, Vec<ServerMode>)>,
+        managers: Vec<ServerManager>,
+    }
+
+    impl FakeCompositor {
+        fn send_head_state(&self, index: usize) {
+            let head = &self.heads[index];
+            for (resource, modes) in self
+                .resources
+                .iter()
+                .filter(|(resource, _)| *resource.data::<usize>().unwrap() == index)
+            {
+                resource.enabled(head.enabled as i32);
+                if head.enabled {
+                    resource.current_mode(&modes[head.current_mode]);
+                    resource.position(head.position.0, head.position.1);
+                    resource.scale(head.scale);
+                }
+            }
+        }
+
+        fn finish(&mut self, pending: &PendingConfiguration, apply: bool) -> bool {
+            if pending.serial != self.serial {
+                return false;
+            }
+            if !apply {
+                self.tested += 1;
+                return true;
+            }
+            self.applied += 1;
+            for &index in &pending.disabled {
+                self.heads[index].enabled = false;
+            }
+            for head in &pending.enabled {
+                let fake = &mut self.heads[head.head];
+                fake.enabled = true;
+                fake.current_mode = head.mode.unwrap_or(fake.current_mode);
+                fake.position = head.position;
+                fake.scale = head.scale;
+            }
+            self.serial += 1;
+            for index in 0..self.heads.len() {
+                self.send_head_state(index);
+            }
+            for manager in &self.managers {
+                manager.done(self.serial);
+            }
+            true
+        }
+    }
+
+    struct TestClient;
+
+    impl ClientData for TestClient {}
+
+    impl GlobalDispatch<ServerManager, ()> for FakeCompositor {
+        fn bind(
+            state: &mut Self,
+            handle: &DisplayHandle,
+            client: &Client,
+            resource: New<ServerManager>,
+            _: &(),
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            let manager = data_init.init(resource, ());
+            for (index, head) in state.heads.iter().enumerate() {
+                let resource = client
+                    .create_resource::<ServerHead, _, Self>(handle, manager.version(), index)
+                    .unwrap();
+                manager.head(&resource);
+                resource.name(head.name.to_string());
+                resource.make(head.make.to_string());
+                resource.model(head.model.to_string());
+                resource.serial_number(format!("0x{index:08x}"));
+
+                let modes = head
+                    .modes
+                    .iter()
+                    .enumerate()
+                    .map(|(mode_index, &(width, height, refresh))| {
+                        let mode = client
+                            .create_resource::<ServerMode, _, Self>(
+                                handle,
+                                manager.version(),
+                                (index, mode_index),
+                            )
+                            .unwrap();
+                        resource.mode(&mode);
+                        mode.size(width, height);
+                        mode.refresh(refresh);
+                        if mode_index == 0 {
+                            mode.preferred();
+                        }
+                        mode
+                    })
+                    .collect();
+
+                state.resources.push((resource, modes));
+                state.send_head_state(index);
+            }
+            manager.done(state.serial);
+            state.managers.push(manager);
+        }
+    }
+
+    impl Dispatch<ServerManager, ()> for FakeCompositor {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            manager: &ServerManager,
+            request: server_manager::Request,
+            _: &(),
+            _: &DisplayHandle,
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            match request {
+                server_manager::Request::CreateConfiguration { id, serial } => {
+                    let pending = PendingConfiguration {
+                        serial,
+                        ..Default::default()
+                    };
+                    data_init.init(id, Arc::new(Mutex::new(pending)));
+                }
+                server_manager::Request::Stop => manager.finished(),
+                _ => {}
+            }
+        }
+    }
+
+    impl Dispatch<server_configuration::ZwlrOutputConfigurationV1, SharedPending> for FakeCompositor {
+        fn request(
+            state: &mut Self,
+            _: &Client,
+            resource: &server_configuration::ZwlrOutputConfigurationV1,
+            request: server_configuration::Request,
+            pending: &SharedPending,
+            _: &DisplayHandle,
+            data_init: &mut DataInit<'_, Self>,
+        ) {
+            let apply = match request {
+                server_configuration::Request::EnableHead { id, head } => {
+                    let head = *head.data::<usize>().unwrap();
+                    let mut shared = pending.lock().unwrap();
+                    shared.enabled.push(PendingHead {
+                        head,
+                        scale: 1.0,
+                        ..Default::default()
+                    });
+                    data_init.init(id, (pending.clone(), shared.enabled.len() - 1));
+                    return;
+                }
+                server_configuration::Request::DisableHead { head } => {
+                    let head = *head.data::<usize>().unwrap();
+                    pending.lock().unwrap().disabled.push(head);
+                    return;
+                }
+                server_configuration::Request::Test => false,
+                server_configuration::Request::Apply => true,
+                _ => return,
+            };
+
+            if state.reject {
+                resource.failed();
+            } else if state.finish(&pending.lock().unwrap(), apply) {
+                resource.succeeded();
+            } else {
+                resource.cancelled();
+            }
+        }
+    }
+
+    impl Dispatch<server_configuration_head::ZwlrOutputConfigurationHeadV1, (SharedPending, usize)>
+        for FakeCompositor
+    {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            _: &server_configuration_head::ZwlrOutputConfigurationHeadV1,
+            request: server_configuration_head::Request,
+            (pending, index): &(SharedPending, usize),
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+            let mut pending = pending.lock().unwrap();
+            let head = &mut pending.enabled[*index];
+            match request {
+                server_configuration_head::Request::SetMode { mode } => {
+                    head.mode = Some(mode.data::<(usize, usize)>().unwrap().1)
+                }
+                server_configuration_head::Request::SetPosition { x, y } => head.position = (x, y),
+                server_configuration_head::Request::SetScale { scale } => head.scale = scale,
+                _ => {}
+            }
+        }
+    }
+
+    impl Dispatch<ServerHead, usize> for FakeCompositor {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            _: &ServerHead,
+            _: <ServerHead as wayland_server::Resource>::Request,
+            _: &usize,
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<ServerMode, (usize, usize)> for FakeCompositor {
+        fn request(
+            _: &mut Self,
+            _: &Client,
+            _: &ServerMode,
+            _: <ServerMode as wayland_server::Resource>::Request,
+            _: &(usize, usize),
+            _: &DisplayHandle,
+            _: &mut DataInit<'_, Self>,
+        ) {
+        }
+    }
+
+    /// Fake compositor running on its own thread until stopped
+    struct RunningCompositor {
+        stop: Arc<AtomicBool>,
+        thread: JoinHandle<FakeCompositor>,
+    }
+
+    impl RunningCompositor {
+        /// Serve `compositor` over a socket pair, returning a client connection to it
+        fn spawn(compositor: FakeCompositor) -> (Self, Connection) {
+            let (server, client) = UnixStream::pair().unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+
+            let thread = thread::spawn({
+                let stop = stop.clone();
+                move || {
+                    let mut compositor = compositor;
+                    let mut display = Display::<FakeCompositor>::new().unwrap();
+                    let mut handle = display.handle();
+                    handle.create_global::<FakeCompositor, ServerManager, ()>(4, ());
+                    handle.insert_client(server, Arc::new(TestClient)).unwrap();
+
+                    while !stop.load(Ordering::Relaxed) {
+                        display.dispatch_clients(&mut compositor).unwrap();
+                        display.flush_clients().unwrap();
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    compositor
+                }
+            });
+
+            let connection = Connection::from_socket(client).unwrap();
+            (Self { stop, thread }, connection)
+        }
+
+        fn stop(self) -> FakeCompositor {
+            self.stop.store(true, Ordering::Relaxed);
+            self.thread.join().unwrap()
+        }
+    }
+
+    fn laptop_and_monitor() -> FakeCompositor {
+        FakeCompositor {
+            heads: vec![
+                FakeHead::new("eDP-1", "Panel", vec![(1920, 1200, 60000)]).enabled(),
+                FakeHead::new(
+                    "DP-1",
+                    "ET430K",
+                    vec![(3840, 2160, 59997), (1920, 1200, 60000)],
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_heads_into_current_state() {
+        let (compositor, connection) = RunningCompositor::spawn(laptop_and_monitor());
+        let backend = WlrBackend::from_connection(connection).unwrap();
+
+        let state = backend.get_current_state().await.unwrap();
+        assert_eq!(state.monitors.len(), 2);
+
+        let internal = &state.monitors[0];
+        assert!(internal.is_builtin);
+        assert_eq!(internal.connector_info.product, "Panel");
+        assert!(internal.modes[0].is_current && internal.modes[0].is_preferred);
+
+        let external = &state.monitors[1];
+        assert!(!external.is_builtin);
+        assert_eq!(external.connector_info.serial, "0x00000001");
+        assert_eq!(external.modes[0].id, "3840x2160@59.997");
+        assert!(external.modes.iter().all(|mode| !mode.is_current));
+
+        assert_eq!(state.logical_monitors.len(), 1);
+        assert!(state.logical_monitors[0].primary);
+        assert_eq!(
+            state.logical_monitors[0].assigned_monitors[0].connector,
+            "eDP-1"
+        );
+
+        compositor.stop();
+    }
+
+    #[tokio::test]
+    async fn tests_then_applies_generated_layouts() {
+        let (compositor, connection) = RunningCompositor::spawn(laptop_and_monitor());
+        let backend = WlrBackend::from_connection(connection).unwrap();
+        let mut changes = backend.receive_monitors_changed().await.unwrap();
+
+        let state = backend.get_current_state().await.unwrap();
+        let joined = build_joined_or_individual(
+            state.monitors.iter().collect(),
+            &DisplayMode::Join,
+            &[],
+            None,
+        )
+        .unwrap();
+        for method in [ApplyMethod::Verify, ApplyMethod::Temporary] {
+            backend
+                .apply_monitors_config(state.serial, method, &joined)
+                .await
+                .unwrap();
+        }
+        changes.next().await.unwrap();
+
+        let state = backend.get_current_state().await.unwrap();
+        assert!(
+            state
+                .verify_applied_config(&joined, backend.capabilities())
+                .unwrap()
+        );
+        assert_eq!(state.logical_monitors.len(), 2);
+
+        let mirrored = build_mirrored(state.monitors.iter().collect(), &[]).unwrap();
+        for method in [ApplyMethod::Verify, ApplyMethod::Temporary] {
+            backend
+                .apply_monitors_config(state.serial, method, &mirrored)
+                .await
+                .unwrap();
+        }
+        changes.next().await.unwrap();
+
+        let state = backend.get_current_state().await.unwrap();
+        assert!(
+            state
+                .verify_applied_config(&mirrored, backend.capabilities())
+                .unwrap()
+        );
+        assert_eq!(state.logical_monitors[0].assigned_monitors.len(), 2);
+
+        let compositor = compositor.stop();
+        assert_eq!((compositor.tested, compositor.applied), (2, 2));
+    }
+
+    #[tokio::test]
+    async fn rejected_configurations_are_not_applied() {
+        let (compositor, connection) = RunningCompositor::spawn(FakeCompositor {
+            reject: true,
+            ..laptop_and_monitor()
+        });
+        let backend = WlrBackend::from_connection(connection).unwrap();
+
+        let state = backend.get_current_state().await.unwrap();
+        let external = build_joined_or_individual(
+            state.monitors.iter().filter(|m| !m.is_builtin).collect(),
+            &DisplayMode::External,
+            &[],
+            None,
+        )
+        .unwrap();
+
+        for method in [ApplyMethod::Verify, ApplyMethod::Temporary] {
+            let error = backend
+                .apply_monitors_config(state.serial, method, &external)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error,
+                Error::ConfigurationRejected {
+                    backend: "wlroots",
+                    ..
+                }
+            ));
+        }
+
+        let compositor = compositor.stop();
+        assert_eq!(compositor.applied, 0);
+        assert!(compositor.heads[0].enabled);
+    }
+
+    #[tokio::test]
+    async fn stale_serial_is_cancelled() {
+        let (compositor, connection) = RunningCompositor::spawn(laptop_and_monitor());
+        let backend = WlrBackend::from_connection(connection).unwrap();
+
+        let state = backend.get_current_state().await.unwrap();
+        let internal = build_joined_or_individual(
+            state.monitors.iter().filter(|m| m.is_builtin).collect(),
+            &DisplayMode::Internal,
+            &[],
+            None,
+        )
+        .unwrap();
+
+        let error = backend
+            .apply_monitors_config(state.serial + 1, ApplyMethod::Temporary, &internal)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::ConfigurationCancelled("wlroots")));
+
+        compositor.stop();
+    }
+}
@@ -0,0 +1,517 @@
+use std::{fmt, str::FromStr};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, MapAccess, Visitor, value::MapAccessDeserializer},
+};
+
+use super::MonitorPattern;
+use crate::{Error, Monitor};
+
+/// What a condition is evaluated against
+pub struct RuleContext<'a> {
+    /// Every connected monitor
+    pub monitors: &'a [Monitor],
+    pub lid_closed: bool,
+}
+
+/// Expression over the set of connected monitors, e.g.
+/// `all(product=Acer, not(product=LG TV))`, `count(external) == 2` or
+/// `all(count(internal) >= 1, lid-closed)`
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// Some connected monitor matches the pattern
+    Monitor(MonitorPattern),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    /// The number of selected monitors compares to `value`
+    Count {
+        selector: MonitorSelector,
+        comparison: Comparison,
+        value: usize,
+    },
+    /// The laptop lid is closed (according to UPower)
+    LidClosed,
+}
+
+#[derive(Debug, Clone)]
+pub enum MonitorSelector {
+    All,
+    Internal,
+    External,
+    Matching(MonitorPattern),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Condition {
+    /// Whether `value` is written as a condition rather than a bare monitor pattern
+    pub fn is_expression(value: &str) -> bool {
+        let value = value.trim_start();
+        value.trim_end() == "lid-closed"
+            || ["all(", "any(", "not(", "count("]
+                .iter()
+                .any(|keyword| value.starts_with(keyword))
+    }
+
+    pub fn evaluate(&self, context: &RuleContext<'_>) -> bool {
+        match self {
+            Self::Monitor(pattern) => context
+                .monitors
+                .iter()
+                .any(|monitor| pattern.matches(monitor)),
+            Self::All(conditions) => conditions
+                .iter()
+                .all(|condition| condition.evaluate(context)),
+            Self::Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.evaluate(context)),
+            Self::Not(condition) => !condition.evaluate(context),
+            Self::Count {
+                selector,
+                comparison,
+                value,
+            } => {
+                let count = context
+                    .monitors
+                    .iter()
+                    .filter(|monitor| selector.selects(monitor))
+                    .count();
+                comparison.compare(count, *value)
+            }
+            Self::LidClosed => context.lid_closed,
+        }
+    }
+
+    /// Whether evaluating needs the lid state
+    pub fn uses_lid(&self) -> bool {
+        match self {
+            Self::LidClosed => true,
+            Self::All(conditions) | Self::Any(conditions) => conditions.iter().any(Self::uses_lid),
+            Self::Not(condition) => condition.uses_lid(),
+            Self::Monitor(_) | Self::Count { .. } => false,
+        }
+    }
+}
+
+impl MonitorSelector {
+    fn selects(&self, monitor: &Monitor) -> bool {
+        match self {
+            Self::All => true,
+            Self::Internal => monitor.is_builtin,
+            Self::External => !monitor.is_builtin,
+            Self::Matching(pattern) => pattern.matches(monitor),
+        }
+    }
+}
+
+impl Comparison {
+    /// Longest operators first, so that `>=` isn't read as `>`
+    const OPERATORS: [(&str, Self); 7] = [
+        ("==", Self::Equal),
+        ("!=", Self::NotEqual),
+        ("<=", Self::LessOrEqual),
+        (">=", Self::GreaterOrEqual),
+        ("<", Self::Less),
+        (">", Self::Greater),
+        ("=", Self::Equal),
+    ];
+
+    fn compare(self, count: usize, value: usize) -> bool {
+        match self {
+            Self::Equal => count == value,
+            Self::NotEqual => count != value,
+            Self::Less => count < value,
+            Self::LessOrEqual => count <= value,
+            Self::Greater => count > value,
+            Self::GreaterOrEqual => count >= value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |conditions: &[Condition]| {
+            conditions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::Monitor(pattern) => write!(f, "{}", quoted(pattern)),
+            Self::All(conditions) => write!(f, "all({})", list(conditions)),
+            Self::Any(conditions) => write!(f, "any({})", list(conditions)),
+            Self::Not(condition) => write!(f, "not({condition})"),
+            Self::Count {
+                selector,
+                comparison,
+                value,
+            } => {
+                let (operator, _) = Comparison::OPERATORS
+                    .iter()
+                    .find(|(_, candidate)| candidate == comparison)
+                    .expect("every comparison has an operator");
+                write!(f, "count({selector}) {operator} {value}")
+            }
+            Self::LidClosed => write!(f, "lid-closed"),
+        }
+    }
+}
+
+impl fmt::Display for MonitorSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => Ok(()),
+            Self::Internal => write!(f, "internal"),
+            Self::External => write!(f, "external"),
+            Self::Matching(pattern) => write!(f, "{}", quoted(pattern)),
+        }
+    }
+}
+
+/// A pattern as written in a condition, in quotes if it holds a comma or quote
+fn quoted(pattern: &MonitorPattern) -> String {
+    let text = pattern.to_string();
+    match text.contains([',', '"']) {
+        true => format!("\"{}\"", text.replace('"', "\\\"")),
+        false => text,
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parser = Parser {
+            expression,
+            rest: expression,
+        };
+        let condition = parser.condition()?;
+        match parser.rest.trim() {
+            "" => Ok(condition),
+            rest => Err(parser.error(&format!("unexpected '{rest}'"))),
+        }
+    }
+}
+
+/// Recursive descent over the text of a condition
+struct Parser<'a> {
+    expression: &'a str,
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::InvalidPattern(format!("{message} in '{}'", self.expression))
+    }
+
+    /// Consume `token` (after whitespace) if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected '{token}'"))),
+        }
+    }
+
+    /// Text up to the next `,` or `)` outside brackets and double quotes, so that regular
+    /// expressions such as `connector~^(DP|HDMI)-` or `serial~^0x7{2,}` stay whole and
+    /// `vendor="Acer, Inc"` can hold commas. The quotes are dropped; `\"` inside them is a quote.
+    fn text(&mut self) -> Result<String, Error> {
+        let mut text = String::new();
+        let mut depth = 0usize;
+        let mut quoted = false;
+        let mut end = self.rest.len();
+        let mut chars = self.rest.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' if quoted => match chars.next() {
+                    Some((_, '"')) => text.push('"'),
+                    Some((_, next)) => text.extend(['\\', next]),
+                    None => text.push(c),
+                },
+                _ if quoted => text.push(c),
+                '(' | '[' | '{' => {
+                    depth += 1;
+                    text.push(c);
+                }
+                ')' | ']' | '}' if depth > 0 => {
+                    depth -= 1;
+                    text.push(c);
+                }
+                ',' | ')' if depth == 0 => {
+                    end = index;
+                    break;
+                }
+                _ => text.push(c),
+            }
+        }
+        if quoted {
+            return Err(self.error("unclosed quote"));
+        }
+        self.rest = &self.rest[end..];
+        Ok(text.trim().to_string())
+    }
+
+    fn condition(&mut self) -> Result<Condition, Error> {
+        if self.eat("all(") {
+            return Ok(Condition::All(self.list()?));
+        }
+        if self.eat("any(") {
+            return Ok(Condition::Any(self.list()?));
+        }
+        if self.eat("not(") {
+            let condition = self.condition()?;
+            self.expect(")")?;
+            return Ok(Condition::Not(Box::new(condition)));
+        }
+        if self.eat("count(") {
+            return self.count();
+        }
+
+        match self.text()?.as_str() {
+            "" => Err(self.error("expected a condition")),
+            "lid-closed" => Ok(Condition::LidClosed),
+            pattern => Ok(Condition::Monitor(pattern.parse()?)),
+        }
+    }
+
+    /// Comma-separated conditions up to the closing parenthesis
+    fn list(&mut self) -> Result<Vec<Condition>, Error> {
+        let mut conditions = vec![self.condition()?];
+        while self.eat(",") {
+            conditions.push(self.condition()?);
+        }
+        self.expect(")")?;
+        Ok(conditions)
+    }
+
+    fn count(&mut self) -> Result<Condition, Error> {
+        let selector = match self.text()?.as_str() {
+            "" => MonitorSelector::All,
+            "internal" => MonitorSelector::Internal,
+            "external" => MonitorSelector::External,
+            pattern => MonitorSelector::Matching(pattern.parse()?),
+        };
+        self.expect(")")?;
+
+        let comparison = Comparison::OPERATORS
+            .iter()
+            .find_map(|(operator, comparison)| self.eat(operator).then_some(*comparison))
+            .ok_or_else(|| self.error("expected a comparison after count(...)"))?;
+
+        self.rest = self.rest.trim_start();
+        let digits = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let (number, rest) = self.rest.split_at(digits);
+        let value = number
+            .parse()
+            .map_err(|_| self.error("expected a number after the comparison"))?;
+        self.rest = rest;
+
+        Ok(Condition::Count {
+            selector,
+            comparison,
+            value,
+        })
+    }
+}
+
+/// Conditions written as tables, e.g. `{ all = ["product=Acer", { not = "product=LG TV" }] }`
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ConditionTable {
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct ConditionVisitor;
+
+        impl<'de> Visitor<'de> for ConditionVisitor {
+            type Value = Condition;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(
+                    "a condition like \"count(external) == 2\" or a table with all, any or not",
+                )
+            }
+
+            fn visit_str<E: de::Error>(
+                self,
+                condition: &str,
+            ) -> std::result::Result<Self::Value, E> {
+                condition.parse().map_err(E::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                Ok(
+                    match ConditionTable::deserialize(MapAccessDeserializer::new(map))? {
+                        ConditionTable::All(conditions) => Condition::All(conditions),
+                        ConditionTable::Any(conditions) => Condition::Any(conditions),
+                        ConditionTable::Not(condition) => Condition::Not(condition),
+                    },
+                )
+            }
+        }
+
+        deserializer.deserialize_any(ConditionVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::ConnectorInfo;
+
+    fn monitor(connector: &str, product: &str, is_builtin: bool) -> Monitor {
+        Monitor {
+            is_builtin,
+            is_underscanning: false,
+            min_refresh_rate: None,
+            display_name: product.to_string(),
+            connector_info: ConnectorInfo {
+                connector: connector.to_string(),
+                vendor: String::new(),
+                product: product.to_string(),
+                serial: String::new(),
+            },
+            modes: vec![],
+            properties: HashMap::new(),
+        }
+    }
+
+    fn evaluate(condition: &str, monitors: &[Monitor], lid_closed: bool) -> bool {
+        let condition: Condition = condition.parse().unwrap();
+        condition.evaluate(&RuleContext {
+            monitors,
+            lid_closed,
+        })
+    }
+
+    #[test]
+    fn evaluates_combinators_and_counts() {
+        let desk = [
+            monitor("eDP-1", "Panel", true),
+            monitor("DP-1", "Acer ET430K", false),
+            monitor("DP-2", "Dell U2720Q", false),
+        ];
+        let tv = [
+            monitor("eDP-1", "Panel", true),
+            monitor("DP-1", "Acer ET430K", false),
+            monitor("HDMI-1", "LG TV", false),
+        ];
+
+        let acer_without_tv = "all(product=Acer, not(product=LG TV))";
+        assert!(evaluate(acer_without_tv, &desk, false));
+        assert!(!evaluate(acer_without_tv, &tv, false));
+
+        assert!(evaluate("any(product=LG TV, connector=DP-2)", &desk, false));
+        assert!(evaluate("count(external) == 2", &desk, false));
+        assert!(!evaluate("count(external)<2", &desk, false));
+        assert!(evaluate("count() = 3", &desk, false));
+        assert!(evaluate("count(product=Acer) >= 1", &tv, false));
+        assert!(evaluate(
+            "all(connector~^(DP|HDMI)-1$, count(connector~^DP-[0-9]{1,2}) == 2)",
+            &desk,
+            false
+        ));
+
+        let docked_closed = "all(count(internal) >= 1, lid-closed)";
+        assert!(evaluate(docked_closed, &desk, true));
+        assert!(!evaluate(docked_closed, &desk, false));
+    }
+
+    #[test]
+    fn prints_parsed_conditions() {
+        let condition: Condition = "all( product=Acer ,not(lid-closed), count(external)>=2 )"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            condition.to_string(),
+            "all(product=Acer, not(lid-closed), count(external) >= 2)"
+        );
+        assert!(condition.uses_lid());
+    }
+
+    #[test]
+    fn quoted_patterns_hold_commas() {
+        let acer = monitor("DP-1", "Acer, Inc", false);
+        assert!(evaluate(
+            "any(product=\"Acer, Inc\", lid-closed)",
+            std::slice::from_ref(&acer),
+            false
+        ));
+        assert!(evaluate(
+            "count(\"product=Acer, Inc\") == 1",
+            &[acer],
+            false
+        ));
+
+        let condition: Condition = r#"not(product="say \"hi\", then")"#.parse().unwrap();
+        assert_eq!(condition.to_string(), r#"not("product=say \"hi\", then")"#);
+        assert_eq!(
+            condition
+                .to_string()
+                .parse::<Condition>()
+                .unwrap()
+                .to_string(),
+            condition.to_string()
+        );
+
+        assert!(matches!(
+            "product=\"Acer".parse::<Condition>(),
+            Err(Error::InvalidPattern(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        for condition in [
+            "all(product=Acer",
+            "not()",
+            "count(external)",
+            "count(external) > two",
+            "any(model=Acer)",
+            "lid-closed)",
+        ] {
+            assert!(
+                matches!(
+                    condition.parse::<Condition>(),
+                    Err(Error::InvalidPattern(_))
+                ),
+                "{condition}"
+            );
+        }
+    }
+}
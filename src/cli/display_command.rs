@@ -1,9 +1,12 @@
-use std::str::FromStr as _;
+use std::path::PathBuf;
 
 use crate::Result;
-use clap::Subcommand;
+use clap::{ArgMatches, Args, Subcommand};
 
-use super::{DisplayMode, DisplayRule, monitor_pattern::MonitorPattern};
+use super::{
+    Align, DisplayMode, DisplayRule, ModeSpec, MonitorSettings, Placement, Refresh, Relation,
+    Selection, Transform, monitor_pattern::MonitorPattern, parse_scale,
+};
 
 #[derive(Debug, Subcommand, Clone)]
 pub enum DisplayCommand {
@@ -14,122 +17,480 @@ pub enum DisplayCommand {
         modes: bool,
     },
 
-    /// Use only the external monitor (if connected)
-    External(MonitorPattern),
+    #[command(flatten)]
+    Rules(Box<RuleCommand>),
+
+    /// Keep a layout that another set-display is waiting to have confirmed (see --confirm)
+    Confirm,
+
+    /// Restore the layout from before the latest display change (undoing twice redoes it)
+    Undo,
+
+    /// List the display changes recorded in $XDG_STATE_HOME/set-display/history.jsonl, latest
+    /// first, or reapply the layout of one of them
+    History {
+        /// Reapply the layout of this entry (1 = latest)
+        #[arg(value_name = "N")]
+        entry: Option<usize>,
+    },
+
+    /// Save the current layout (positions, scales, rotations, primary and modes) as NAME; while
+    /// watching, it is loaded whenever the same monitors are connected
+    Save { name: String },
+
+    /// Apply a layout saved with `save`, finding its monitors by vendor, product and serial on
+    /// whatever connectors they are now
+    Load { name: String },
+
+    /// Rotate the monitors matching PATTERN, keeping the rest of the current layout
+    Rotate {
+        /// Monitors to rotate (e.g., connector=DP-1 or product=Acer)
+        #[arg(value_name = "PATTERN")]
+        pattern: MonitorPattern,
+
+        /// Counterclockwise angle: normal, 90, 180, 270, or flipped, flipped-90, flipped-180,
+        /// flipped-270 to mirror horizontally first
+        #[arg(value_name = "ANGLE")]
+        transform: Transform,
+    },
+
+    /// Make the monitor matching PATTERN primary, keeping the rest of the current layout
+    Primary {
+        /// Monitor to make primary (e.g., connector=DP-1 or product=Acer)
+        #[arg(value_name = "PATTERN")]
+        pattern: MonitorPattern,
+    },
+
+    /// Print the fingerprint of the connected monitors and the saved layout it matches, which
+    /// watching loads instead of running the rules
+    Detect,
+
+    /// Save each configuration of GNOME's monitors.xml as a layout named PREFIX-N, which
+    /// watching then loads whenever the same monitors are connected
+    ImportMonitorsXml {
+        /// Path of monitors.xml (default: $XDG_CONFIG_HOME/monitors.xml)
+        path: Option<PathBuf>,
+
+        /// Prefix of the saved layout names
+        #[arg(long, default_value = "gnome")]
+        prefix: String,
+    },
+
+    /// Write the current layout into GDM's monitors.xml, so that the login screen uses the same
+    /// monitors; configurations for other sets of monitors are kept, the previous file is
+    /// backed up as monitors.xml.bak
+    ExportGdm {
+        /// Path of GDM's monitors.xml (default: /var/lib/gdm3/.config/monitors.xml if
+        /// /var/lib/gdm3 exists, /var/lib/gdm/.config/monitors.xml otherwise)
+        path: Option<PathBuf>,
+    },
+
+    /// Run the rules of a profile from the configuration file, with its options such as `watch`
+    Profile {
+        /// Profile name (default: `default_profile` from the configuration file)
+        name: Option<String>,
+    },
+}
+
+/// Commands that run rules, given on the command line
+#[derive(Debug, Subcommand, Clone)]
+pub enum RuleCommand {
+    /// Use only the external monitors matching the pattern (all of them without one)
+    External(ModeArgs),
 
     /// Use only the internal monitor (if exists)
-    Internal(MonitorPattern),
+    Internal(ModeArgs),
 
     /// Enable internal and external monitors side by side
-    Join(MonitorPattern),
+    Join(ModeArgs),
 
     /// Mirror internal and external monitors (uses the highest resolution common mode)
-    Mirror(MonitorPattern),
+    Mirror(ModeArgs),
 
-    /// Test pattern matching against current monitors
+    /// Explain how rules match the current monitors and which mode they pick, without changing
+    /// anything
+    ///
+    /// Rules are given as for `auto` (`test --external product=Acer --mirror 'product=LG TV'`),
+    /// or come from a profile of the configuration file (`test --profile dock`).
     #[command(arg_required_else_help = true)]
-    Test(MonitorPattern),
+    Test {
+        /// Explain the rules of this profile (default: `default_profile`) instead
+        #[arg(long, value_name = "NAME", num_args = 0..=1, conflicts_with_all = ["external", "internal", "join", "mirror", "default"])]
+        profile: Option<Option<String>>,
+
+        #[command(flatten)]
+        rules: RuleArgs,
+    },
 
     /// Run multiple rules in sequence (first match wins)
+    ///
+    /// Each rule is a monitor pattern (`product=Acer`) or a condition over all connected
+    /// monitors: `all(...)`, `any(...)`, `not(...)`, `count(external|internal|PATTERN) OP N`
+    /// with OP one of `== != < <= > >=`, and `lid-closed`. For example:
+    /// `--external 'all(product=Acer, not(product=LG TV))'`.
     #[command(alias = "rules")]
     Auto {
         /// Optional descriptive name for this rule set
         #[arg(short, long)]
         name: Option<String>,
 
-        /// Use external display when pattern matches
-        #[arg(long, value_name = "PATTERN")]
-        external: Vec<String>,
+        #[command(flatten)]
+        rules: RuleArgs,
+    },
+}
 
-        /// Use internal display when pattern matches
-        #[arg(long, value_name = "PATTERN")]
-        internal: Vec<String>,
+/// Monitors and settings for `external`, `internal`, `join` and `mirror`
+#[derive(Debug, Args, Clone)]
+pub struct ModeArgs {
+    #[command(flatten)]
+    pattern: MonitorPattern,
 
-        /// Use join displays when pattern matches
-        #[arg(long, value_name = "PATTERN")]
-        join: Vec<String>,
+    /// Turn on all the external monitors matching the pattern, or only the best (largest) one;
+    /// the others are turned off
+    #[arg(long, value_enum, default_value_t)]
+    select: Selection,
 
-        /// Use mirrored displays when pattern matches
-        #[arg(long, value_name = "PATTERN")]
-        mirror: Vec<String>,
+    /// Join or mirror only the external monitors, leaving the internal ones off (e.g., with the
+    /// lid closed)
+    #[arg(long)]
+    externals_only: bool,
 
-        /// Default mode if no patterns match
-        #[arg(long, value_enum, default_value = "external")]
-        default: DisplayMode,
-    },
+    /// Put the monitors matching PATTERN first (repeat for the next ones); the others follow
+    /// by connector name
+    #[arg(long, value_name = "PATTERN")]
+    order: Vec<MonitorPattern>,
+
+    /// Mode of the matching monitors instead of their preferred one (e.g., 2560x1440@144 or
+    /// 2560x1440)
+    #[arg(long = "mode", value_name = "WIDTHxHEIGHT[@REFRESH]")]
+    display_mode: Option<ModeSpec>,
+
+    /// Refresh rate of the matching monitors: "max" or a rate in Hz (e.g., 60)
+    #[arg(long)]
+    refresh: Option<Refresh>,
+
+    /// Scale of the matching monitors instead of their preferred one (e.g., 2)
+    #[arg(long, value_parser = parse_scale)]
+    scale: Option<f64>,
+
+    /// Rotation of the matching monitors, counterclockwise: normal, 90, 180, 270, or flipped,
+    /// flipped-90, flipped-180, flipped-270 to mirror them horizontally first
+    #[arg(long)]
+    transform: Option<Transform>,
+
+    /// Place the matching monitors left of the monitor matching PATTERN (join)
+    #[arg(long, value_name = "PATTERN", group = "position")]
+    left_of: Option<MonitorPattern>,
+
+    /// Place the matching monitors right of the monitor matching PATTERN (join)
+    #[arg(long, value_name = "PATTERN", group = "position")]
+    right_of: Option<MonitorPattern>,
+
+    /// Place the matching monitors above the monitor matching PATTERN (join)
+    #[arg(long, value_name = "PATTERN", group = "position")]
+    above: Option<MonitorPattern>,
+
+    /// Place the matching monitors below the monitor matching PATTERN (join)
+    #[arg(long, value_name = "PATTERN", group = "position")]
+    below: Option<MonitorPattern>,
+
+    /// Edges lined up with that monitor: top/left, center or bottom/right
+    #[arg(long, value_enum, default_value_t, requires = "position")]
+    align: Align,
+
+    /// Make the monitor matching PATTERN primary (default: the largest external monitor)
+    #[arg(long, value_name = "PATTERN")]
+    primary: Option<MonitorPattern>,
+}
+
+impl ModeArgs {
+    fn rule(&self, mode: DisplayMode) -> DisplayRule {
+        let settings = MonitorSettings {
+            pattern: self.pattern.clone(),
+            mode: self.display_mode,
+            refresh: self.refresh,
+            scale: self.scale,
+            transform: self.transform,
+            position: self.position(),
+        };
+        DisplayRule {
+            pattern: self.pattern.clone(),
+            monitors: match settings.is_empty() {
+                true => vec![],
+                false => vec![settings],
+            },
+            primary: self.primary.clone(),
+            select: self.select,
+            externals_only: self.externals_only,
+            order: self.order.clone(),
+            ..DisplayRule::always(mode)
+        }
+    }
+
+    fn position(&self) -> Option<Placement> {
+        [
+            (Relation::LeftOf, &self.left_of),
+            (Relation::RightOf, &self.right_of),
+            (Relation::Above, &self.above),
+            (Relation::Below, &self.below),
+        ]
+        .into_iter()
+        .find_map(|(relation, target)| {
+            target.clone().map(|target| Placement {
+                relation,
+                target,
+                align: self.align,
+            })
+        })
+    }
+}
+
+/// Rules given on the command line, for `auto` and `test`
+#[derive(Debug, Args, Clone)]
+pub struct RuleArgs {
+    /// Use external display when pattern or condition matches
+    #[arg(long, value_name = "PATTERN")]
+    external: Vec<String>,
+
+    /// Use internal display when pattern or condition matches
+    #[arg(long, value_name = "PATTERN")]
+    internal: Vec<String>,
+
+    /// Use join displays when pattern or condition matches
+    #[arg(long, value_name = "PATTERN")]
+    join: Vec<String>,
+
+    /// Use mirrored displays when pattern or condition matches
+    #[arg(long, value_name = "PATTERN")]
+    mirror: Vec<String>,
+
+    /// Default mode if no patterns match
+    #[arg(long, value_enum, default_value = "external")]
+    default: DisplayMode,
 }
 
-impl DisplayCommand {
-    pub fn rules(&self) -> Result<Vec<DisplayRule>> {
+impl RuleCommand {
+    /// Rules in order of preference; `matches` are the parsed command line the command came from
+    pub fn rules(&self, matches: &ArgMatches) -> Result<Vec<DisplayRule>> {
         Ok(match self {
-            DisplayCommand::Test(_) => unreachable!(),
-            DisplayCommand::Status { .. } => unreachable!(),
-            DisplayCommand::External(pattern) => vec![DisplayRule {
-                mode: DisplayMode::External,
-                pattern: pattern.clone(),
-            }],
-            DisplayCommand::Internal(pattern) => vec![DisplayRule {
-                mode: DisplayMode::Internal,
-                pattern: pattern.clone(),
-            }],
-            DisplayCommand::Join(pattern) => vec![DisplayRule {
-                mode: DisplayMode::Join,
-                pattern: pattern.clone(),
-            }],
-            DisplayCommand::Mirror(pattern) => vec![DisplayRule {
-                mode: DisplayMode::Mirror,
-                pattern: pattern.clone(),
-            }],
-            DisplayCommand::Auto {
-                external,
-                internal,
-                join,
-                mirror,
-                default,
-                ..
-            } => {
-                let mut rules = Vec::new();
-
-                // Add mirror rules
-                for pattern_str in mirror {
-                    rules.push(DisplayRule {
-                        mode: DisplayMode::Mirror,
-                        pattern: MonitorPattern::from_str(pattern_str)?,
-                    });
-                }
-
-                // Add join rules
-                for pattern_str in join {
-                    rules.push(DisplayRule {
-                        mode: DisplayMode::Join,
-                        pattern: MonitorPattern::from_str(pattern_str)?,
-                    });
-                }
-
-                // Add external rules
-                for pattern_str in external {
-                    rules.push(DisplayRule {
-                        mode: DisplayMode::External,
-                        pattern: MonitorPattern::from_str(pattern_str)?,
-                    });
-                }
-
-                // Add internal rules
-                for pattern_str in internal {
-                    rules.push(DisplayRule {
-                        mode: DisplayMode::Internal,
-                        pattern: MonitorPattern::from_str(pattern_str)?,
-                    });
-                }
-
-                // Add the default rule (always matches)
-                rules.push(DisplayRule {
-                    mode: *default,
-                    pattern: MonitorPattern::default(),
-                });
-
-                rules
-            }
+            RuleCommand::External(args) => vec![args.rule(DisplayMode::External)],
+            RuleCommand::Internal(args) => vec![args.rule(DisplayMode::Internal)],
+            RuleCommand::Join(args) => vec![args.rule(DisplayMode::Join)],
+            RuleCommand::Mirror(args) => vec![args.rule(DisplayMode::Mirror)],
+            RuleCommand::Auto { rules, .. } => rules.rules(matches.subcommand_matches("auto"))?,
+            RuleCommand::Test { rules, .. } => rules.rules(matches.subcommand_matches("test"))?,
         })
     }
 }
+
+impl RuleArgs {
+    /// Rules in the order given on the command line, then the default rule
+    fn rules(&self, matches: Option<&ArgMatches>) -> Result<Vec<DisplayRule>> {
+        // Rules take precedence in the order given on the command line, whatever their mode
+        let mut ordered = vec![];
+        for (id, mode, patterns) in [
+            ("external", DisplayMode::External, &self.external),
+            ("internal", DisplayMode::Internal, &self.internal),
+            ("join", DisplayMode::Join, &self.join),
+            ("mirror", DisplayMode::Mirror, &self.mirror),
+        ] {
+            let indices = matches.and_then(|matches| matches.indices_of(id));
+            for (index, value) in indices.into_iter().flatten().zip(patterns) {
+                ordered.push((index, DisplayRule::parse(mode, value)?));
+            }
+        }
+        ordered.sort_by_key(|(index, _)| *index);
+
+        let mut rules: Vec<DisplayRule> = ordered.into_iter().map(|(_, rule)| rule).collect();
+
+        // Add the default rule (always matches)
+        rules.push(DisplayRule::always(self.default));
+
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory as _, FromArgMatches as _};
+
+    use super::{DisplayCommand, RuleCommand};
+    use crate::cli::Cli;
+
+    fn rules(args: &[&str]) -> Vec<String> {
+        let matches = Cli::command().try_get_matches_from(args).unwrap();
+        let DisplayCommand::Rules(command) = Cli::from_arg_matches(&matches).unwrap().command
+        else {
+            panic!("{args:?} doesn't run rules");
+        };
+        command
+            .rules(&matches)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn auto_rules_follow_command_line_order() {
+        assert_eq!(
+            rules(&[
+                "set-display",
+                "auto",
+                "--external",
+                "product=Acer",
+                "--mirror",
+                "product=LG",
+                "--external",
+                "vendor=DEL",
+                "--default",
+                "internal",
+            ]),
+            [
+                "External when product=Acer",
+                "Mirror when product=LG",
+                "External when vendor=DEL",
+                "Internal (always matches)",
+            ]
+        );
+
+        assert_eq!(
+            rules(&["set-display", "rules", "--join", "DP", "--mirror", "TV"]),
+            [
+                "Join when name=DP",
+                "Mirror when name=TV",
+                "External (always matches)",
+            ]
+        );
+    }
+
+    #[test]
+    fn pattern_flags_accept_regex_and_glob() {
+        assert_eq!(
+            rules(&[
+                "set-display",
+                "external",
+                "--connector",
+                "~^DP-",
+                "--serial",
+                "iglob:0X71*"
+            ]),
+            ["External when connector~^DP-, serial=iglob:0X71*"]
+        );
+
+        // Patterns are only read as conditions when they start like one
+        assert_eq!(
+            rules(&[
+                "set-display",
+                "auto",
+                "--external",
+                "vendor=Acer, Inc",
+                "--mirror",
+                "name=TV (\"living\")",
+                "--join",
+                "not(lid-closed)",
+            ]),
+            [
+                "External when vendor=Acer, Inc",
+                "Mirror when name=TV (\"living\")",
+                "Join when not(lid-closed)",
+                "External (always matches)",
+            ]
+        );
+
+        let error = Cli::command()
+            .try_get_matches_from(["set-display", "external", "--connector", "~^(DP"])
+            .unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn mode_flags_set_the_mode_of_matching_monitors() {
+        assert_eq!(
+            rules(&[
+                "set-display",
+                "external",
+                "--product",
+                "Acer",
+                "--mode",
+                "2560x1440@144",
+                "--scale",
+                "2"
+            ]),
+            ["External when product=Acer; product=Acer: mode 2560x1440@144, scale 2"]
+        );
+        assert_eq!(
+            rules(&["set-display", "join", "--refresh", "max"]),
+            ["Join (always matches); any monitor: refresh max"]
+        );
+
+        assert_eq!(
+            rules(&[
+                "set-display",
+                "join",
+                "--product",
+                "Acer",
+                "--above",
+                "connector=eDP-1",
+                "--align",
+                "center"
+            ]),
+            ["Join when product=Acer; product=Acer: above connector=eDP-1 (center-aligned)"]
+        );
+        assert_eq!(
+            rules(&["set-display", "external", "--select", "best"]),
+            ["External (always matches); best matching monitor only"]
+        );
+        assert_eq!(
+            rules(&[
+                "set-display",
+                "join",
+                "--externals-only",
+                "--order",
+                "product=Dell",
+                "--order",
+                "connector=HDMI-1"
+            ]),
+            [
+                "Join (always matches); external monitors only; ordered by product=Dell, \
+                 connector=HDMI-1"
+            ]
+        );
+
+        let error = Cli::command()
+            .try_get_matches_from(["set-display", "external", "--mode", "2560"])
+            .unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn test_takes_rules_or_a_profile() {
+        assert_eq!(
+            rules(&[
+                "set-display",
+                "test",
+                "--mirror",
+                "TV",
+                "--default",
+                "internal"
+            ]),
+            ["Mirror when name=TV", "Internal (always matches)"]
+        );
+
+        let matches = Cli::command()
+            .try_get_matches_from(["set-display", "test", "--profile"])
+            .unwrap();
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        assert!(matches!(
+            cli.command,
+            DisplayCommand::Rules(command)
+                if matches!(*command, RuleCommand::Test { profile: Some(None), .. })
+        ));
+
+        assert!(
+            Cli::command()
+                .try_get_matches_from(["set-display", "test", "--profile", "dock", "--join", "DP"])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::{fmt, str::FromStr};
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, de};
+
+use crate::Error;
+
+/// How one field of a [`MonitorPattern`](super::MonitorPattern) is matched:
+///
+/// - `text`: exact (connector, vendor) or partial (product, serial, name) match
+/// - `i:text`: the same, ignoring case
+/// - `~regex` or `~*regex` (ignoring case): regular expression found anywhere in the field
+/// - `glob:pattern` or `iglob:pattern` (ignoring case): shell glob matching the whole field
+#[derive(Debug, Clone)]
+pub struct FieldMatcher {
+    /// As written, for printing
+    source: String,
+    /// Boxed: compiled regular expressions and globs are large
+    kind: Box<MatcherKind>,
+}
+
+#[derive(Debug, Clone)]
+enum MatcherKind {
+    Text { text: String, ignore_case: bool },
+    Regex(Regex),
+    Glob(GlobMatcher),
+}
+
+impl FieldMatcher {
+    /// Whether `value` matches; `exact` selects equality over containment for plain text
+    pub fn matches(&self, value: &str, exact: bool) -> bool {
+        match self.kind.as_ref() {
+            MatcherKind::Text {
+                text,
+                ignore_case: false,
+            } => match exact {
+                true => value == text,
+                false => value.contains(text.as_str()),
+            },
+            MatcherKind::Text {
+                text,
+                ignore_case: true,
+            } => {
+                let (value, text) = (value.to_lowercase(), text.to_lowercase());
+                match exact {
+                    true => value == text,
+                    false => value.contains(&text),
+                }
+            }
+            MatcherKind::Regex(regex) => regex.is_match(value),
+            MatcherKind::Glob(glob) => glob.is_match(value),
+        }
+    }
+
+    /// Operator and value as written after the field name, e.g. `=Acer` or `~^DP-`
+    pub fn with_operator(&self) -> String {
+        match *self.kind {
+            MatcherKind::Regex(_) => self.source.clone(),
+            _ => format!("={}", self.source),
+        }
+    }
+}
+
+impl fmt::Display for FieldMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for FieldMatcher {
+    type Err = Error;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid =
+            |error: &dyn fmt::Display| Error::InvalidPattern(format!("{source}: {error}"));
+        let regex = |pattern: &str, ignore_case: bool| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map(MatcherKind::Regex)
+                .map_err(|error| invalid(&error))
+        };
+        let glob = |pattern: &str, ignore_case: bool| {
+            GlobBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map(|glob| MatcherKind::Glob(glob.compile_matcher()))
+                .map_err(|error| invalid(&error))
+        };
+
+        let kind = if let Some(pattern) = source.strip_prefix("~*") {
+            regex(pattern, true)?
+        } else if let Some(pattern) = source.strip_prefix('~') {
+            regex(pattern, false)?
+        } else if let Some(pattern) = source.strip_prefix("glob:") {
+            glob(pattern, false)?
+        } else if let Some(pattern) = source.strip_prefix("iglob:") {
+            glob(pattern, true)?
+        } else if let Some(text) = source.strip_prefix("i:") {
+            MatcherKind::Text {
+                text: text.to_string(),
+                ignore_case: true,
+            }
+        } else {
+            MatcherKind::Text {
+                text: source.to_string(),
+                ignore_case: false,
+            }
+        };
+
+        Ok(Self {
+            source: source.to_string(),
+            kind: Box::new(kind),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldMatcher {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(matcher: &str, value: &str, exact: bool) -> bool {
+        matcher
+            .parse::<FieldMatcher>()
+            .unwrap()
+            .matches(value, exact)
+    }
+
+    #[test]
+    fn matches_each_kind() {
+        assert!(matches("DP-1", "DP-1", true));
+        assert!(!matches("DP", "DP-1", true));
+        assert!(matches("ET430", "Acer ET430K", false));
+        assert!(!matches("et430", "Acer ET430K", false));
+
+        assert!(matches("i:et430", "Acer ET430K", false));
+        assert!(matches("i:acr", "ACR", true));
+
+        assert!(matches("~^DP-", "DP-6", true));
+        assert!(!matches("~^DP-", "eDP-1", true));
+        assert!(matches("~*^lg", "LG TV", true));
+
+        assert!(matches("glob:0x71*", "0x7140025c", false));
+        assert!(!matches("glob:0x71", "0x7140025c", false));
+        assert!(matches("iglob:hdmi-?", "HDMI-1", true));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for matcher in ["~(DP", "~*[", "glob:[DP", "iglob:{a"] {
+            assert!(
+                matches!(
+                    matcher.parse::<FieldMatcher>(),
+                    Err(Error::InvalidPattern(_))
+                ),
+                "{matcher}"
+            );
+        }
+    }
+}
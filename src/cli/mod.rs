@@ -1,9 +1,24 @@
+mod condition;
+pub use condition::{Condition, RuleContext};
 mod display_command;
+mod field_matcher;
+pub use field_matcher::FieldMatcher;
+use std::{fmt, path::PathBuf};
+
 use clap::Parser;
-pub use display_command::DisplayCommand;
+pub use display_command::{DisplayCommand, RuleCommand};
 mod monitor_pattern;
 pub use monitor_pattern::MonitorPattern;
+mod monitor_settings;
+pub use monitor_settings::{ModeSpec, MonitorSettings, Refresh, parse_scale};
+mod placement;
+pub use placement::{Align, Placement, Relation, Tile, arrange};
+mod transform;
+use serde::Deserialize;
 use strum::Display;
+pub use transform::Transform;
+
+use crate::{Monitor, Result, feedback::Urgency};
 
 /// Manage display (monitor) selection in Wayland environments.
 #[derive(Debug, Parser)]
@@ -17,17 +32,43 @@ pub struct Cli {
     #[arg(short, long)]
     pub test: bool,
 
-    /// TODO: (Not yet implemented)
-    /// Optional configuration file with display rules
-    // #[arg(short, long)]
-    // pub config: Option<PathBuf>,
+    /// Restore the previous layout unless the new one is confirmed within SECONDS
+    /// (Enter, or `set-display confirm`)
+    #[arg(long, value_name = "SECONDS")]
+    pub confirm: Option<u64>,
+
+    /// Have the compositor store layouts so that they survive logout (Mutter and Muffin only)
+    #[arg(short, long)]
+    pub persistent: bool,
+
+    /// Speak display changes, failures and reverts through speech-dispatcher
+    #[arg(short, long)]
+    pub speak: bool,
+
+    /// Show desktop notifications for display changes and failures, with a "Revert" action
+    #[arg(short, long)]
+    pub notify: bool,
+
+    /// Urgency of change notifications; failures are always critical
+    #[arg(long, value_name = "LEVEL")]
+    pub urgency: Option<Urgency>,
+
+    /// While watching, run the rules even when a saved layout matches the connected monitors
+    #[arg(long)]
+    pub no_saved_layouts: bool,
+
+    /// Configuration file with display rule profiles; saved layouts go in `layouts` next to it
+    /// [default: $XDG_CONFIG_HOME/set-display/config.toml]
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
 
     /// Display commands to execute, in order of preference
     #[command(subcommand)]
     pub command: DisplayCommand,
 }
 
-#[derive(Debug, Clone, Copy, Display, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, Display, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DisplayMode {
     External,
     Internal,
@@ -35,8 +76,175 @@ pub enum DisplayMode {
     Mirror,
 }
 
+/// Which of the monitors matching a rule's pattern take part
+#[derive(Debug, Clone, Copy, Default, PartialEq, Display, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Selection {
+    /// Every matching monitor
+    #[default]
+    All,
+    /// Only the matching monitor with the largest preferred mode
+    Best,
+}
+
 #[derive(Debug, Clone)]
 pub struct DisplayRule {
     pub mode: DisplayMode,
     pub pattern: MonitorPattern,
+    /// Must also hold for the rule to match
+    pub condition: Option<Condition>,
+    /// Modes and scales instead of the preferred ones, for the monitors they match
+    pub monitors: Vec<MonitorSettings>,
+    /// Primary monitor, when it is used; by default the largest external one
+    pub primary: Option<MonitorPattern>,
+    /// Whether all the monitors matching `pattern` take part, or only the best one
+    pub select: Selection,
+    /// Join or mirror only external monitors, leaving the internal ones off
+    pub externals_only: bool,
+    /// Monitors matching the first pattern come first, and so on; then by connector name
+    pub order: Vec<MonitorPattern>,
+}
+
+impl DisplayRule {
+    /// Rule from a command-line value, either a monitor pattern or a condition like
+    /// `all(product=Acer, not(product=LG TV))`
+    pub fn parse(mode: DisplayMode, value: &str) -> Result<Self> {
+        // Anything else is a pattern, which may hold commas, parentheses and quotes
+        if !Condition::is_expression(value) {
+            return Ok(Self {
+                pattern: value.parse()?,
+                ..Self::always(mode)
+            });
+        }
+        Ok(Self {
+            condition: Some(value.parse()?),
+            ..Self::always(mode)
+        })
+    }
+
+    /// Rule for `mode` that always matches
+    pub fn always(mode: DisplayMode) -> Self {
+        Self {
+            mode,
+            pattern: MonitorPattern::default(),
+            condition: None,
+            monitors: vec![],
+            primary: None,
+            select: Selection::All,
+            externals_only: false,
+            order: vec![],
+        }
+    }
+
+    /// The monitors the pattern is checked against: the internal ones for `Internal`, the
+    /// external ones otherwise
+    pub fn candidates<'a>(
+        &self,
+        monitors: &'a [Monitor],
+    ) -> impl DoubleEndedIterator<Item = &'a Monitor> {
+        let internal = matches!(self.mode, DisplayMode::Internal);
+        monitors
+            .iter()
+            .filter(move |monitor| monitor.is_builtin == internal)
+    }
+
+    /// Monitors this rule turns on: the external ones matching the pattern (internal ones for
+    /// Internal), all or the best of them, and for Join and Mirror also the internal ones unless
+    /// `externals_only`
+    ///
+    /// They come in the compositor's order, unless the rule has an `order` or only uses external
+    /// monitors: then by the first `order` pattern they match, and by connector name.
+    pub fn participants<'a>(&self, monitors: &'a [Monitor]) -> Vec<&'a Monitor> {
+        let matching = self
+            .candidates(monitors)
+            .filter(|monitor| self.pattern.matches(monitor));
+        let selected: Vec<&Monitor> = match self.select {
+            Selection::All => matching.collect(),
+            // The first of the largest
+            Selection::Best => matching
+                .rev()
+                .max_by_key(|monitor| {
+                    monitor
+                        .preferred_mode()
+                        .map_or(0, |mode| mode.width * mode.height)
+                })
+                .into_iter()
+                .collect(),
+        };
+
+        let mut participants: Vec<&Monitor> = match self.mode {
+            DisplayMode::Join | DisplayMode::Mirror => monitors
+                .iter()
+                .filter(|monitor| {
+                    (monitor.is_builtin && !self.externals_only)
+                        || selected
+                            .iter()
+                            .any(|chosen| std::ptr::eq(*chosen, *monitor))
+                })
+                .collect(),
+            DisplayMode::External | DisplayMode::Internal => selected,
+        };
+
+        if !self.order.is_empty() || self.externals_only {
+            participants.sort_by_cached_key(|monitor| {
+                let rank = self
+                    .order
+                    .iter()
+                    .position(|pattern| pattern.matches(monitor))
+                    .unwrap_or(self.order.len());
+                (rank, connector_key(&monitor.connector_info.connector))
+            });
+        }
+        participants
+    }
+
+    pub fn condition_holds(&self, context: &RuleContext<'_>) -> bool {
+        self.condition
+            .as_ref()
+            .is_none_or(|condition| condition.evaluate(context))
+    }
+
+    pub fn uses_lid(&self) -> bool {
+        self.condition.as_ref().is_some_and(Condition::uses_lid)
+    }
+}
+
+/// Sort key putting connectors in natural order: DP-2 before DP-10
+fn connector_key(connector: &str) -> (String, u64) {
+    let prefix = connector.trim_end_matches(|c: char| c.is_ascii_digit());
+    (
+        prefix.to_string(),
+        connector[prefix.len()..].parse().unwrap_or_default(),
+    )
+}
+
+impl fmt::Display for DisplayRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.pattern.is_empty(), &self.condition) {
+            (true, None) => write!(f, "{} (always matches)", self.mode),
+            (false, None) => write!(f, "{} when {}", self.mode, self.pattern),
+            (true, Some(condition)) => write!(f, "{} when {condition}", self.mode),
+            (false, Some(condition)) => {
+                write!(f, "{} when {} and {condition}", self.mode, self.pattern)
+            }
+        }?;
+        if self.select == Selection::Best {
+            write!(f, "; best matching monitor only")?;
+        }
+        if self.externals_only {
+            write!(f, "; external monitors only")?;
+        }
+        if !self.order.is_empty() {
+            let order: Vec<String> = self.order.iter().map(ToString::to_string).collect();
+            write!(f, "; ordered by {}", order.join(", "))?;
+        }
+        for settings in &self.monitors {
+            write!(f, "; {settings}")?;
+        }
+        if let Some(primary) = &self.primary {
+            write!(f, "; primary {primary}")?;
+        }
+        Ok(())
+    }
 }
@@ -1,30 +1,39 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 use clap::Args;
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, MapAccess, Visitor, value::MapAccessDeserializer},
+};
 
+use super::FieldMatcher;
 use crate::{Error, Monitor};
 
+const FIELDS: [&str; 5] = ["connector", "vendor", "product", "serial", "name"];
+
+/// Fields that must all match. Each field takes plain text, `i:text` to ignore case, `~regex`
+/// (`~*regex` to ignore case) or `glob:pattern` (`iglob:pattern` to ignore case).
 #[derive(Debug, Args, Clone, Default)]
 pub struct MonitorPattern {
-    /// Exact match by connector name (e.g., DP-6, HDMI-1)
+    /// Exact match by connector name (e.g., DP-6, HDMI-1, "~^DP-")
     #[arg(long)]
-    connector: Option<String>,
+    connector: Option<FieldMatcher>,
 
-    /// Exact match by vendor code (e.g., ACR, DEL)
+    /// Exact match by vendor code (e.g., ACR, DEL, "i:acr")
     #[arg(long)]
-    vendor: Option<String>,
+    vendor: Option<FieldMatcher>,
 
     /// Partial or exact match by product name (e.g., "ET430K" or "Acer ET430K")
     #[arg(long)]
-    product: Option<String>,
+    product: Option<FieldMatcher>,
 
-    /// Partial or exact match by serial number (e.g., "0x714" or "0x7140025c")
+    /// Partial or exact match by serial number (e.g., "0x714" or "glob:0x71*")
     #[arg(long)]
-    serial: Option<String>,
+    serial: Option<FieldMatcher>,
 
     /// Partial or exact match by display name (e.g., "Acer" or "Acer Technologies 42")
     #[arg(long)]
-    name: Option<String>,
+    name: Option<FieldMatcher>,
 }
 
 impl MonitorPattern {
@@ -37,28 +46,66 @@ impl MonitorPattern {
     }
 
     pub fn matches(&self, monitor: &Monitor) -> bool {
-        // If no patterns are specified, this is a default rule that always matches
+        // If no patterns are specified, this is a default rule that always matches;
+        // otherwise each specified pattern must match
+        self.field_matches(monitor)
+            .iter()
+            .all(|field_match| field_match.matched)
+    }
+
+    /// How each specified field compares with `monitor`
+    pub fn field_matches<'a>(&'a self, monitor: &'a Monitor) -> Vec<FieldMatch<'a>> {
+        let info = &monitor.connector_info;
+        [
+            ("connector", &self.connector, &info.connector, true),
+            ("vendor", &self.vendor, &info.vendor, true),
+            ("product", &self.product, &info.product, false),
+            ("serial", &self.serial, &info.serial, false),
+            ("name", &self.name, &monitor.display_name, false),
+        ]
+        .into_iter()
+        .filter_map(|(field, matcher, value, exact)| {
+            matcher.as_ref().map(|matcher| FieldMatch {
+                field,
+                matcher,
+                value,
+                matched: matcher.matches(value, exact),
+            })
+        })
+        .collect()
+    }
+}
+
+/// One field of a pattern compared with a monitor
+pub struct FieldMatch<'a> {
+    pub field: &'static str,
+    pub matcher: &'a FieldMatcher,
+    pub value: &'a str,
+    pub matched: bool,
+}
+
+impl fmt::Display for MonitorPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.is_empty() {
-            return true;
+            return write!(f, "any monitor");
         }
 
-        // Check each specified pattern - all must match
-        (match &self.connector {
-            None => true,
-            Some(pattern) => monitor.connector_info.connector == *pattern,
-        }) && (match &self.vendor {
-            None => true,
-            Some(pattern) => monitor.connector_info.vendor == *pattern,
-        }) && (match &self.product {
-            None => true,
-            Some(pattern) => monitor.connector_info.product.contains(pattern),
-        }) && (match &self.serial {
-            None => true,
-            Some(pattern) => monitor.connector_info.serial.contains(pattern),
-        }) && (match &self.name {
-            None => true,
-            Some(pattern) => monitor.display_name.contains(pattern),
+        let fields = [
+            ("connector", &self.connector),
+            ("vendor", &self.vendor),
+            ("product", &self.product),
+            ("serial", &self.serial),
+            ("name", &self.name),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| {
+            value
+                .as_ref()
+                .map(|value| format!("{field}{}", value.with_operator()))
         })
+        .collect::<Vec<_>>();
+
+        write!(f, "{}", fields.join(", "))
     }
 }
 
@@ -66,17 +113,26 @@ impl FromStr for MonitorPattern {
     type Err = Error;
 
     fn from_str(pattern: &str) -> std::result::Result<Self, Self::Err> {
-        // Parse patterns like "connector=DP-6", "product=Acer", etc.
-        let parts: Vec<&str> = pattern.splitn(2, '=').collect();
-        if parts.len() != 2 {
+        // Parse patterns like "connector=DP-6", "product=Acer", "connector~^DP-", etc.
+        // A bare value (including a bare `~regex`) matches the display name; `~` only follows a
+        // field name, so that a name such as "Dell~2" stays whole
+        let Some(split) = pattern.find(['=', '~']).filter(|&split| {
+            split > 0
+                && (pattern[split..].starts_with('=') || FIELDS.contains(&pattern[..split].trim()))
+        }) else {
             return Ok(Self {
-                name: Some(pattern.to_string()),
+                name: Some(pattern.trim().parse()?),
                 ..Default::default()
             });
-        }
+        };
 
-        let field = parts[0].trim();
-        let value = parts[1].trim().to_string();
+        let field = pattern[..split].trim();
+        // `field~regex` is shorthand for `field=~regex`
+        let value = match pattern[split..].strip_prefix('=') {
+            Some(value) => value.trim(),
+            None => pattern[split..].trim(),
+        }
+        .parse()?;
 
         Ok(match field {
             "connector" => Self {
@@ -99,7 +155,108 @@ impl FromStr for MonitorPattern {
                 name: Some(value),
                 ..Default::default()
             },
-            pattern => return Err(Error::InvalidPattern(pattern.to_string())),
+            field => {
+                return Err(Error::InvalidPattern(format!(
+                    "unknown field '{field}' (expected one of {})",
+                    FIELDS.join(", ")
+                )));
+            }
         })
     }
 }
+
+/// Fields of a pattern written as a table, e.g. `{ vendor = "GSM", product = "LG TV" }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatternFields {
+    connector: Option<FieldMatcher>,
+    vendor: Option<FieldMatcher>,
+    product: Option<FieldMatcher>,
+    serial: Option<FieldMatcher>,
+    name: Option<FieldMatcher>,
+}
+
+impl<'de> Deserialize<'de> for MonitorPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct PatternVisitor;
+
+        impl<'de> Visitor<'de> for PatternVisitor {
+            type Value = MonitorPattern;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a pattern like \"product=Acer\" or a table of pattern fields")
+            }
+
+            fn visit_str<E: de::Error>(self, pattern: &str) -> std::result::Result<Self::Value, E> {
+                pattern.parse().map_err(E::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let fields = PatternFields::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(MonitorPattern {
+                    connector: fields.connector,
+                    vendor: fields.vendor,
+                    product: fields.product,
+                    serial: fields.serial,
+                    name: fields.name,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(PatternVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_operators_and_prints_them_back() {
+        for pattern in [
+            "connector~^DP-",
+            "connector~*^dp-",
+            "serial=glob:0x71*",
+            "vendor=i:acr",
+            "product=LG TV",
+        ] {
+            assert_eq!(
+                pattern.parse::<MonitorPattern>().unwrap().to_string(),
+                pattern
+            );
+        }
+        assert_eq!(
+            "connector=~^DP-"
+                .parse::<MonitorPattern>()
+                .unwrap()
+                .to_string(),
+            "connector~^DP-"
+        );
+        assert_eq!(
+            "~*acer".parse::<MonitorPattern>().unwrap().to_string(),
+            "name~*acer"
+        );
+
+        assert!(matches!(
+            "connector~^(DP".parse::<MonitorPattern>(),
+            Err(Error::InvalidPattern(_))
+        ));
+        assert!(matches!(
+            "model=Acer".parse::<MonitorPattern>(),
+            Err(Error::InvalidPattern(_))
+        ));
+
+        // `~` after anything but a field name is part of a bare name
+        assert_eq!(
+            "Dell~2".parse::<MonitorPattern>().unwrap().to_string(),
+            "name=Dell~2"
+        );
+        assert_eq!(
+            "name=Dell~2".parse::<MonitorPattern>().unwrap().to_string(),
+            "name=Dell~2"
+        );
+    }
+}
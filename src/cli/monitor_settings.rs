@@ -0,0 +1,517 @@
+use std::{fmt, str::FromStr};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, Visitor},
+};
+
+use super::{MonitorPattern, Placement, Transform};
+use crate::{Error, Mode, Monitor, Result};
+
+/// Refresh rates closer than this (in Hz) are the same: Mutter reports 60 Hz as 59.999
+const REFRESH_TOLERANCE: f64 = 0.5;
+
+/// Scales closer than this are the same: Mutter reports 1.75 as 1.7475727796554565
+const SCALE_TOLERANCE: f64 = 0.01;
+
+/// How many alternatives an error suggests
+const ALTERNATIVES: usize = 3;
+
+/// Mode, refresh rate, scale and rotation for the monitors matching `pattern`, instead of their
+/// preferred mode and scale, unrotated
+///
+/// ```toml
+/// [[profiles.dock.rules.monitors]]
+/// match = "product=Acer"
+/// mode = "2560x1440@144"
+/// scale = 2.0
+/// transform = "90"
+/// position = { below = "product=LG", align = "center" }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorSettings {
+    /// Monitors these settings apply to; omitted, every monitor
+    #[serde(default, rename = "match")]
+    pub pattern: MonitorPattern,
+    pub mode: Option<ModeSpec>,
+    pub refresh: Option<Refresh>,
+    #[serde(default, deserialize_with = "deserialize_scale")]
+    pub scale: Option<f64>,
+    pub transform: Option<Transform>,
+    /// Where the monitors go when joined, instead of right of the previous one
+    pub position: Option<Placement>,
+}
+
+/// A mode as `WIDTHxHEIGHT` or `WIDTHxHEIGHT@REFRESH`, e.g. `2560x1440@144`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeSpec {
+    pub width: i32,
+    pub height: i32,
+    pub refresh: Option<f64>,
+}
+
+/// Refresh rate: the highest available, or the one closest to a rate in Hz
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refresh {
+    Max,
+    Rate(f64),
+}
+
+impl MonitorSettings {
+    pub fn is_empty(&self) -> bool {
+        self.mode.is_none()
+            && self.refresh.is_none()
+            && self.scale.is_none()
+            && self.transform.is_none()
+            && self.position.is_none()
+    }
+
+    /// Settings for `monitor`: each field from the first of `settings` that matches the
+    /// monitor and sets it
+    pub fn for_monitor(settings: &[Self], monitor: &Monitor) -> Self {
+        let mut merged = Self::default();
+        for settings in settings.iter().filter(|s| s.pattern.matches(monitor)) {
+            merged.mode = merged.mode.or(settings.mode);
+            merged.refresh = merged.refresh.or(settings.refresh);
+            merged.scale = merged.scale.or(settings.scale);
+            merged.transform = merged.transform.or(settings.transform);
+            if merged.position.is_none() {
+                merged.position = settings.position.clone();
+            }
+        }
+        merged
+    }
+
+    /// The mode of `monitor` these settings ask for, by default its preferred mode
+    pub fn choose_mode<'a>(&self, monitor: &'a Monitor) -> Result<&'a Mode> {
+        let preferred = monitor
+            .preferred_mode()
+            .ok_or_else(|| Error::NoModes(monitor.connector_info.connector.clone()))?;
+        let (width, height) = match self.mode {
+            Some(spec) => (spec.width, spec.height),
+            None => (preferred.width, preferred.height),
+        };
+        let refresh = match self.mode.and_then(|spec| spec.refresh) {
+            Some(rate) => Some(Refresh::Rate(rate)),
+            None => self.refresh,
+        };
+        if self.mode.is_none() && refresh.is_none() {
+            return Ok(preferred);
+        }
+
+        let sized = || {
+            monitor
+                .modes
+                .iter()
+                .filter(move |mode| mode.width == width && mode.height == height)
+        };
+        let chosen = match refresh {
+            // The preferred refresh rate when it has that size, or else the highest
+            None => sized()
+                .find(|mode| mode.is_preferred)
+                .or_else(|| sized().max_by(|a, b| a.refresh_rate.total_cmp(&b.refresh_rate))),
+            Some(Refresh::Max) => sized().max_by(|a, b| a.refresh_rate.total_cmp(&b.refresh_rate)),
+            Some(Refresh::Rate(rate)) => sized()
+                .filter(|mode| (mode.refresh_rate - rate).abs() <= REFRESH_TOLERANCE)
+                .min_by(|a, b| {
+                    (a.refresh_rate - rate)
+                        .abs()
+                        .total_cmp(&(b.refresh_rate - rate).abs())
+                }),
+        };
+
+        chosen.ok_or_else(|| {
+            let requested = ModeSpec {
+                width,
+                height,
+                refresh: match refresh {
+                    Some(Refresh::Rate(rate)) => Some(rate),
+                    _ => None,
+                },
+            };
+            let mut closest: Vec<&Mode> = monitor.modes.iter().collect();
+            closest.sort_by(|a, b| {
+                let distance = |mode: &Mode| {
+                    let size = (mode.width - width).abs() + (mode.height - height).abs();
+                    let rate = requested
+                        .refresh
+                        .map_or(0.0, |rate| mode.refresh_rate - rate);
+                    (size, rate.abs())
+                };
+                let (a, b) = (distance(a), distance(b));
+                a.0.cmp(&b.0).then(a.1.total_cmp(&b.1))
+            });
+            Error::ModeUnavailable {
+                connector: monitor.connector_info.connector.clone(),
+                mode: requested.to_string(),
+                closest: Some(
+                    closest
+                        .iter()
+                        .take(ALTERNATIVES)
+                        .map(|mode| {
+                            format!("{}x{}@{:.3}", mode.width, mode.height, mode.refresh_rate)
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            }
+        })
+    }
+
+    /// The scale these settings ask for, as `mode` supports it, by default its preferred scale
+    pub fn choose_scale(&self, monitor: &Monitor, mode: &Mode) -> Result<f64> {
+        let Some(scale) = self.scale else {
+            return Ok(mode.preferred_scale);
+        };
+        // Compositors that don't list scales take any
+        if mode.supported_scales.is_empty() {
+            return Ok(scale);
+        }
+
+        let mut closest = mode.supported_scales.clone();
+        closest.sort_by(|a, b| (a - scale).abs().total_cmp(&(b - scale).abs()));
+        match closest.first() {
+            Some(supported) if (supported - scale).abs() <= SCALE_TOLERANCE => Ok(*supported),
+            _ => Err(Error::ScaleNotSupported {
+                connector: monitor.connector_info.connector.clone(),
+                mode: mode.id.clone(),
+                scale,
+                closest: closest
+                    .iter()
+                    .take(ALTERNATIVES)
+                    .map(|scale| format!("{scale:.2}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for MonitorSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut settings = vec![];
+        if let Some(mode) = &self.mode {
+            settings.push(format!("mode {mode}"));
+        }
+        if let Some(refresh) = &self.refresh {
+            settings.push(format!("refresh {refresh}"));
+        }
+        if let Some(scale) = &self.scale {
+            settings.push(format!("scale {scale}"));
+        }
+        if let Some(transform) = &self.transform {
+            settings.push(format!("transform {transform}"));
+        }
+        if let Some(position) = &self.position {
+            settings.push(position.to_string());
+        }
+        write!(f, "{}: {}", self.pattern, settings.join(", "))
+    }
+}
+
+impl fmt::Display for ModeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if let Some(refresh) = self.refresh {
+            write!(f, "@{refresh}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ModeSpec {
+    type Err = Error;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            Error::InvalidMonitorSetting(format!(
+                "mode '{source}' (expected WIDTHxHEIGHT or WIDTHxHEIGHT@REFRESH, e.g. 2560x1440@144)"
+            ))
+        };
+        let (size, refresh) = match source.trim().split_once('@') {
+            Some((size, refresh)) => {
+                let rate = refresh.parse().ok().filter(|rate| is_positive(*rate));
+                (size, Some(rate.ok_or_else(invalid)?))
+            }
+            None => (source.trim(), None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let dimension = |value: &str| value.parse().ok().filter(|value: &i32| *value > 0);
+        Ok(Self {
+            width: dimension(width).ok_or_else(invalid)?,
+            height: dimension(height).ok_or_else(invalid)?,
+            refresh,
+        })
+    }
+}
+
+impl fmt::Display for Refresh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refresh::Max => write!(f, "max"),
+            Refresh::Rate(rate) => write!(f, "{rate}"),
+        }
+    }
+}
+
+impl Refresh {
+    /// A rate in Hz, if finite and positive
+    fn rate(rate: f64) -> Option<Self> {
+        is_positive(rate).then_some(Refresh::Rate(rate))
+    }
+}
+
+/// Whether a rate or scale is a number above zero
+fn is_positive(value: f64) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+impl FromStr for Refresh {
+    type Err = Error;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        match source.trim() {
+            "max" => Ok(Refresh::Max),
+            rate => rate.parse().ok().and_then(Refresh::rate).ok_or_else(|| {
+                Error::InvalidMonitorSetting(format!(
+                    "refresh '{source}' (expected \"max\" or a rate in Hz such as 60)"
+                ))
+            }),
+        }
+    }
+}
+
+/// A scale as given on the command line: a positive, finite number
+pub fn parse_scale(source: &str) -> Result<f64> {
+    source
+        .trim()
+        .parse()
+        .ok()
+        .filter(|scale| is_positive(*scale))
+        .ok_or_else(|| invalid_scale(source))
+}
+
+fn invalid_scale(scale: impl fmt::Display) -> Error {
+    Error::InvalidMonitorSetting(format!(
+        "scale '{scale}' (expected a positive number such as 1.5)"
+    ))
+}
+
+fn deserialize_scale<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<f64>, D::Error> {
+    match f64::deserialize(deserializer)? {
+        scale if is_positive(scale) => Ok(Some(scale)),
+        scale => Err(de::Error::custom(invalid_scale(scale))),
+    }
+}
+
+impl<'de> Deserialize<'de> for ModeSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Refresh {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RefreshVisitor;
+
+        impl Visitor<'_> for RefreshVisitor {
+            type Value = Refresh;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("\"max\" or a refresh rate in Hz")
+            }
+
+            fn visit_str<E: de::Error>(self, refresh: &str) -> std::result::Result<Refresh, E> {
+                refresh.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, rate: i64) -> std::result::Result<Refresh, E> {
+                self.visit_f64(rate as f64)
+            }
+
+            fn visit_f64<E: de::Error>(self, rate: f64) -> std::result::Result<Refresh, E> {
+                Refresh::rate(rate).ok_or_else(|| {
+                    E::invalid_value(de::Unexpected::Float(rate), &"a positive refresh rate")
+                })
+            }
+        }
+
+        deserializer.deserialize_any(RefreshVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::ConnectorInfo;
+
+    fn monitor(modes: &[(i32, i32, f64)]) -> Monitor {
+        Monitor {
+            is_builtin: false,
+            is_underscanning: false,
+            min_refresh_rate: None,
+            display_name: "Acer ET430K".to_string(),
+            connector_info: ConnectorInfo {
+                connector: "DP-1".to_string(),
+                vendor: "ACR".to_string(),
+                product: "ET430K".to_string(),
+                serial: "0x0000000a".to_string(),
+            },
+            modes: modes
+                .iter()
+                .enumerate()
+                .map(|(i, &(width, height, refresh_rate))| Mode {
+                    id: format!("{width}x{height}@{refresh_rate:.3}"),
+                    width,
+                    height,
+                    refresh_rate,
+                    is_current: i == 0,
+                    is_preferred: i == 0,
+                    preferred_scale: 1.0,
+                    supported_scales: vec![1.0, 1.2475727796554565, 1.5, 2.0],
+                    properties: HashMap::new(),
+                })
+                .collect(),
+            properties: HashMap::new(),
+        }
+    }
+
+    fn settings(mode: Option<&str>, refresh: Option<&str>, scale: Option<f64>) -> MonitorSettings {
+        MonitorSettings {
+            pattern: MonitorPattern::default(),
+            mode: mode.map(|mode| mode.parse().unwrap()),
+            refresh: refresh.map(|refresh| refresh.parse().unwrap()),
+            scale,
+            transform: None,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn chooses_modes_by_size_and_refresh_rate() {
+        let monitor = monitor(&[
+            (3840, 2160, 60.0),
+            (2560, 1440, 143.998),
+            (2560, 1440, 59.951),
+            (1920, 1080, 60.0),
+        ]);
+        let chosen = |settings: MonitorSettings| settings.choose_mode(&monitor).unwrap().id.clone();
+
+        assert_eq!(chosen(settings(None, None, None)), "3840x2160@60.000");
+        assert_eq!(
+            chosen(settings(Some("2560x1440@144"), None, None)),
+            "2560x1440@143.998"
+        );
+        assert_eq!(
+            chosen(settings(Some("2560x1440"), Some("60"), None)),
+            "2560x1440@59.951"
+        );
+        assert_eq!(
+            chosen(settings(Some("2560x1440"), Some("max"), None)),
+            "2560x1440@143.998"
+        );
+        assert_eq!(
+            chosen(settings(None, Some("max"), None)),
+            "3840x2160@60.000"
+        );
+
+        let error = settings(Some("2560x1440@75"), None, None)
+            .choose_mode(&monitor)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "✗ Monitor DP-1 has no mode 2560x1440@75; the closest are 2560x1440@59.951, \
+             2560x1440@143.998, 1920x1080@60.000"
+        );
+    }
+
+    #[test]
+    fn chooses_supported_scales() {
+        let monitor = monitor(&[(3840, 2160, 60.0)]);
+        let mode = &monitor.modes[0];
+        let scale = |scale| settings(None, None, Some(scale)).choose_scale(&monitor, mode);
+
+        assert_eq!(scale(2.0).unwrap(), 2.0);
+        assert_eq!(scale(1.25).unwrap(), 1.2475727796554565);
+        assert_eq!(
+            scale(1.75).unwrap_err().to_string(),
+            "✗ DP-1 does not support scale 1.75 at 3840x2160@60.000; the closest are 1.50, 2.00, \
+             1.25"
+        );
+    }
+
+    #[test]
+    fn rejects_non_positive_values() {
+        assert_eq!(parse_scale(" 1.5").unwrap(), 1.5);
+        for scale in ["0", "-2", "inf", "NaN", "two"] {
+            assert!(parse_scale(scale).is_err(), "{scale}");
+        }
+
+        let parse = |content| toml::from_str::<MonitorSettings>(content).map(|s| s.scale);
+        assert_eq!(parse("scale = 2").unwrap(), Some(2.0));
+        assert_eq!(parse("").unwrap(), None);
+        assert!(parse("scale = 0.0").is_err());
+        assert!(parse("scale = -1").is_err());
+        assert!(parse("scale = nan").is_err());
+
+        assert_eq!(
+            "3840x2160@60".parse::<ModeSpec>().unwrap().refresh,
+            Some(60.0)
+        );
+        for mode in [
+            "3840x2160@NaN",
+            "3840x2160@inf",
+            "3840x2160@0",
+            "3840x2160@-5",
+            "0x2160",
+            "3840x-2160",
+        ] {
+            assert!(mode.parse::<ModeSpec>().is_err(), "{mode}");
+        }
+
+        let refresh = |content| toml::from_str::<MonitorSettings>(content).map(|s| s.refresh);
+        assert_eq!(refresh("refresh = 60").unwrap(), Some(Refresh::Rate(60.0)));
+        assert_eq!(
+            refresh("refresh = 59.95").unwrap(),
+            Some(Refresh::Rate(59.95))
+        );
+        assert!(refresh("refresh = 0").is_err());
+        assert!(refresh("refresh = -inf").is_err());
+        assert!("inf".parse::<Refresh>().is_err());
+    }
+
+    #[test]
+    fn merges_settings_of_matching_patterns() {
+        let monitor = monitor(&[(3840, 2160, 60.0)]);
+        let acer = MonitorSettings {
+            pattern: "product=ET430K".parse().unwrap(),
+            ..settings(None, None, Some(2.0))
+        };
+        let lg = MonitorSettings {
+            pattern: "product=LG TV".parse().unwrap(),
+            ..settings(Some("1920x1080"), None, None)
+        };
+        let everyone = settings(Some("2560x1440"), Some("max"), Some(1.0));
+
+        let merged = MonitorSettings::for_monitor(&[acer, lg, everyone], &monitor);
+        assert_eq!(merged.scale, Some(2.0));
+        assert_eq!(merged.mode, Some("2560x1440".parse().unwrap()));
+        assert_eq!(merged.refresh, Some(Refresh::Max));
+
+        assert!(matches!(
+            "2560x".parse::<ModeSpec>(),
+            Err(Error::InvalidMonitorSetting(_))
+        ));
+        assert!(matches!(
+            "fast".parse::<Refresh>(),
+            Err(Error::InvalidMonitorSetting(_))
+        ));
+    }
+}
@@ -0,0 +1,371 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+use super::MonitorPattern;
+use crate::{Error, Monitor, Result};
+
+/// Where a monitor goes relative to another one, e.g. `{ below = "product=Acer", align = "center" }`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "PlacementFields")]
+pub struct Placement {
+    pub relation: Relation,
+    /// The monitor this one is placed next to
+    pub target: MonitorPattern,
+    pub align: Align,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Relation {
+    LeftOf,
+    RightOf,
+    Above,
+    Below,
+}
+
+/// Which edges line up: top, center or bottom for monitors placed left or right of another,
+/// left, center or right for monitors placed above or below
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    #[value(alias = "top", alias = "left")]
+    #[serde(alias = "top", alias = "left")]
+    Start,
+    Center,
+    #[value(alias = "bottom", alias = "right")]
+    #[serde(alias = "bottom", alias = "right")]
+    End,
+}
+
+/// A placement written as a table: one of the relations, and optionally `align`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct PlacementFields {
+    left_of: Option<MonitorPattern>,
+    right_of: Option<MonitorPattern>,
+    above: Option<MonitorPattern>,
+    below: Option<MonitorPattern>,
+    #[serde(default)]
+    align: Align,
+}
+
+impl TryFrom<PlacementFields> for Placement {
+    type Error = String;
+
+    fn try_from(fields: PlacementFields) -> std::result::Result<Self, Self::Error> {
+        let mut relations = [
+            (Relation::LeftOf, fields.left_of),
+            (Relation::RightOf, fields.right_of),
+            (Relation::Above, fields.above),
+            (Relation::Below, fields.below),
+        ]
+        .into_iter()
+        .filter_map(|(relation, target)| target.map(|target| (relation, target)));
+        match (relations.next(), relations.next()) {
+            (Some((relation, target)), None) => Ok(Self {
+                relation,
+                target,
+                align: fields.align,
+            }),
+            _ => Err("a position needs exactly one of left-of, right-of, above or below".into()),
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (relation, edges) = match self.relation {
+            Relation::LeftOf => ("left of", ["top", "bottom"]),
+            Relation::RightOf => ("right of", ["top", "bottom"]),
+            Relation::Above => ("above", ["left", "right"]),
+            Relation::Below => ("below", ["left", "right"]),
+        };
+        let align = match self.align {
+            Align::Start => edges[0],
+            Align::Center => "center",
+            Align::End => edges[1],
+        };
+        write!(f, "{relation} {} ({align}-aligned)", self.target)
+    }
+}
+
+/// A monitor to lay out, with its size in logical pixels
+pub struct Tile<'a> {
+    pub monitor: &'a Monitor,
+    pub width: i32,
+    pub height: i32,
+    pub placement: Option<&'a Placement>,
+}
+
+impl Tile<'_> {
+    fn connector(&self) -> &str {
+        &self.monitor.connector_info.connector
+    }
+}
+
+/// Positions of `tiles` in logical pixels
+///
+/// Tiles without a placement go side by side from the left, in order; the others go next to
+/// the first other tile their target matches. The layout is then moved to start at (0, 0) and
+/// checked the way Mutter does: no two monitors may overlap, and each must touch another.
+pub fn arrange(tiles: &[Tile<'_>]) -> Result<Vec<(i32, i32)>> {
+    let mut positions: Vec<Option<(i32, i32)>> = vec![None; tiles.len()];
+
+    let mut x = 0;
+    for (position, tile) in positions.iter_mut().zip(tiles) {
+        if tile.placement.is_none() {
+            *position = Some((x, 0));
+            x += tile.width;
+        }
+    }
+
+    let targets = tiles
+        .iter()
+        .enumerate()
+        .map(|(i, tile)| {
+            let Some(placement) = tile.placement else {
+                return Ok(None);
+            };
+            tiles
+                .iter()
+                .enumerate()
+                .position(|(j, other)| j != i && placement.target.matches(other.monitor))
+                .map(Some)
+                .ok_or_else(|| Error::PlacementTargetMissing {
+                    connector: tile.connector().to_string(),
+                    target: placement.target.to_string(),
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Place whatever can be placed until everything is, or nothing more can be
+    while positions.iter().any(Option::is_none) {
+        let mut progress = false;
+        for (i, tile) in tiles.iter().enumerate() {
+            let (Some(placement), Some(target), None) = (tile.placement, targets[i], positions[i])
+            else {
+                continue;
+            };
+            let Some(anchor) = positions[target] else {
+                continue;
+            };
+            positions[i] = Some(place(tile, placement, &tiles[target], anchor));
+            progress = true;
+        }
+        if !progress {
+            let circle = tiles
+                .iter()
+                .zip(&positions)
+                .filter(|(_, position)| position.is_none())
+                .map(|(tile, _)| tile.connector())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(Error::PlacementCycle(circle));
+        }
+    }
+
+    let positions: Vec<(i32, i32)> = positions.into_iter().flatten().collect();
+    let left = positions.iter().map(|(x, _)| *x).min().unwrap_or_default();
+    let top = positions.iter().map(|(_, y)| *y).min().unwrap_or_default();
+    let positions: Vec<(i32, i32)> = positions
+        .into_iter()
+        .map(|(x, y)| (x - left, y - top))
+        .collect();
+
+    check(tiles, &positions)?;
+    Ok(positions)
+}
+
+/// Position of `tile` placed next to `target`, which is at `anchor`
+fn place(
+    tile: &Tile<'_>,
+    placement: &Placement,
+    target: &Tile<'_>,
+    anchor: (i32, i32),
+) -> (i32, i32) {
+    let align = |start: i32, target_size: i32, size: i32| match placement.align {
+        Align::Start => start,
+        Align::Center => start + (target_size - size) / 2,
+        Align::End => start + target_size - size,
+    };
+    let (x, y) = anchor;
+    match placement.relation {
+        Relation::LeftOf => (x - tile.width, align(y, target.height, tile.height)),
+        Relation::RightOf => (x + target.width, align(y, target.height, tile.height)),
+        Relation::Above => (align(x, target.width, tile.width), y - tile.height),
+        Relation::Below => (align(x, target.width, tile.width), y + target.height),
+    }
+}
+
+/// Refuse layouts Mutter would refuse: overlapping monitors, or monitors touching no other
+fn check(tiles: &[Tile<'_>], positions: &[(i32, i32)]) -> Result<()> {
+    let rectangles: Vec<_> = tiles
+        .iter()
+        .zip(positions)
+        .map(|(tile, &(x, y))| (x, y, x + tile.width, y + tile.height))
+        .collect();
+
+    for (i, a) in rectangles.iter().enumerate() {
+        for (j, b) in rectangles.iter().enumerate().skip(i + 1) {
+            if a.0 < b.2 && b.0 < a.2 && a.1 < b.3 && b.1 < a.3 {
+                return Err(Error::LayoutOverlap {
+                    first: tiles[i].connector().to_string(),
+                    second: tiles[j].connector().to_string(),
+                });
+            }
+        }
+    }
+
+    if rectangles.len() < 2 {
+        return Ok(());
+    }
+    for (i, a) in rectangles.iter().enumerate() {
+        let touches = rectangles.iter().enumerate().any(|(j, b)| {
+            let side_by_side = (a.2 == b.0 || b.2 == a.0) && a.1 < b.3 && b.1 < a.3;
+            let stacked = (a.3 == b.1 || b.3 == a.1) && a.0 < b.2 && b.0 < a.2;
+            j != i && (side_by_side || stacked)
+        });
+        if !touches {
+            return Err(Error::LayoutGap(tiles[i].connector().to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::ConnectorInfo;
+
+    fn monitor(connector: &str) -> Monitor {
+        Monitor {
+            is_builtin: connector.starts_with("eDP"),
+            is_underscanning: false,
+            min_refresh_rate: None,
+            display_name: connector.to_string(),
+            connector_info: ConnectorInfo {
+                connector: connector.to_string(),
+                vendor: "ACR".to_string(),
+                product: "ET430K".to_string(),
+                serial: "0x0000000a".to_string(),
+            },
+            modes: vec![],
+            properties: HashMap::new(),
+        }
+    }
+
+    fn placement(relation: Relation, target: &str, align: Align) -> Placement {
+        Placement {
+            relation,
+            target: format!("connector={target}").parse().unwrap(),
+            align,
+        }
+    }
+
+    fn arrange_two(placement: Option<&Placement>) -> Result<Vec<(i32, i32)>> {
+        let (big, laptop) = (monitor("DP-1"), monitor("eDP-1"));
+        arrange(&[
+            Tile {
+                monitor: &big,
+                width: 3840,
+                height: 2160,
+                placement: None,
+            },
+            Tile {
+                monitor: &laptop,
+                width: 1504,
+                height: 1003,
+                placement,
+            },
+        ])
+    }
+
+    #[test]
+    fn places_monitors_next_to_their_targets() {
+        assert_eq!(arrange_two(None).unwrap(), [(0, 0), (3840, 0)]);
+
+        let below = placement(Relation::Below, "DP-1", Align::Center);
+        assert_eq!(arrange_two(Some(&below)).unwrap(), [(0, 0), (1168, 2160)]);
+
+        let left = placement(Relation::LeftOf, "DP-1", Align::End);
+        assert_eq!(arrange_two(Some(&left)).unwrap(), [(1504, 0), (0, 1157)]);
+
+        let above = placement(Relation::Above, "DP-1", Align::Start);
+        assert_eq!(arrange_two(Some(&above)).unwrap(), [(0, 1003), (0, 0)]);
+    }
+
+    #[test]
+    fn refuses_missing_targets_and_circles() {
+        let missing = placement(Relation::Below, "HDMI-1", Align::Start);
+        assert!(matches!(
+            arrange_two(Some(&missing)),
+            Err(Error::PlacementTargetMissing { .. })
+        ));
+
+        let (a, b) = (monitor("DP-1"), monitor("DP-2"));
+        let (right_of_b, right_of_a) = (
+            placement(Relation::RightOf, "DP-2", Align::Start),
+            placement(Relation::RightOf, "DP-1", Align::Start),
+        );
+        let tile = |monitor, placement| Tile {
+            monitor,
+            width: 1920,
+            height: 1080,
+            placement: Some(placement),
+        };
+        assert!(matches!(
+            arrange(&[tile(&a, &right_of_b), tile(&b, &right_of_a)]),
+            Err(Error::PlacementCycle(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_overlaps_and_gaps() {
+        let (a, b, c) = (monitor("DP-1"), monitor("DP-2"), monitor("eDP-1"));
+        let tile = |monitor, placement| Tile {
+            monitor,
+            width: 1920,
+            height: 1080,
+            placement,
+        };
+
+        // Two monitors below the same one, both left-aligned
+        let below = placement(Relation::Below, "DP-1", Align::Start);
+        assert!(matches!(
+            arrange(&[
+                tile(&a, None),
+                tile(&b, Some(&below)),
+                tile(&c, Some(&below))
+            ]),
+            Err(Error::LayoutOverlap { .. })
+        ));
+
+        // Touching only at a corner
+        let tiles = [tile(&a, None), tile(&b, None)];
+        assert!(matches!(
+            check(&tiles, &[(0, 0), (1920, 1080)]),
+            Err(Error::LayoutGap(_))
+        ));
+        assert!(check(&tiles, &[(0, 0), (1920, 540)]).is_ok());
+    }
+
+    #[test]
+    fn parses_placements_from_tables() {
+        #[derive(Deserialize)]
+        struct Settings {
+            position: Placement,
+        }
+        let parse = |content| toml::from_str::<Settings>(content).map(|s| s.position);
+
+        let below = parse("position = { below = \"product=Acer\", align = \"center\" }").unwrap();
+        assert_eq!(below.to_string(), "below product=Acer (center-aligned)");
+        let left = parse("position = { left-of = \"DP-1\", align = \"bottom\" }").unwrap();
+        assert_eq!(left.to_string(), "left of name=DP-1 (bottom-aligned)");
+
+        assert!(parse("position = { below = \"DP-1\", above = \"DP-2\" }").is_err());
+        assert!(parse("position = { align = \"top\" }").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fmt, str::FromStr};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, Visitor},
+};
+
+use crate::{Error, Result};
+
+/// Rotation, counterclockwise as in Mutter and wlroots, optionally after flipping horizontally
+///
+/// Written `normal`, `90`, `180`, `270`, `flipped`, `flipped-90`, `flipped-180` or
+/// `flipped-270`; the D-Bus and Wayland value is the position in that list.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+const TRANSFORMS: [(Transform, &str); 8] = [
+    (Transform::Normal, "normal"),
+    (Transform::Rotate90, "90"),
+    (Transform::Rotate180, "180"),
+    (Transform::Rotate270, "270"),
+    (Transform::Flipped, "flipped"),
+    (Transform::Flipped90, "flipped-90"),
+    (Transform::Flipped180, "flipped-180"),
+    (Transform::Flipped270, "flipped-270"),
+];
+
+impl Transform {
+    /// Whether the monitor is on its side, so that its width and height trade places
+    pub fn is_sideways(self) -> bool {
+        matches!(
+            self,
+            Self::Rotate90 | Self::Rotate270 | Self::Flipped90 | Self::Flipped270
+        )
+    }
+
+    /// Size of a `width` by `height` mode shown with this transform
+    pub fn apply(self, width: i32, height: i32) -> (i32, i32) {
+        match self.is_sideways() {
+            true => (height, width),
+            false => (width, height),
+        }
+    }
+
+    /// A transform as the compositor reports it, in words, e.g. "flipped, rotated 90°"
+    pub fn describe(value: u32) -> String {
+        let Ok(transform) = Self::try_from(value) else {
+            return format!("unknown ({value})");
+        };
+        let description = match (value >= 4, (value % 4) * 90) {
+            (false, 0) => "normal".to_string(),
+            (true, 0) => "flipped".to_string(),
+            (false, angle) => format!("rotated {angle}°"),
+            (true, angle) => format!("flipped, rotated {angle}°"),
+        };
+        match transform.is_sideways() {
+            true => format!("{description} (portrait)"),
+            false => description,
+        }
+    }
+}
+
+impl From<Transform> for u32 {
+    fn from(transform: Transform) -> Self {
+        TRANSFORMS
+            .iter()
+            .position(|(candidate, _)| *candidate == transform)
+            .unwrap_or_default() as u32
+    }
+}
+
+impl TryFrom<u32> for Transform {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        TRANSFORMS
+            .get(value as usize)
+            .map(|(transform, _)| *transform)
+            .ok_or_else(|| Error::InvalidMonitorSetting(format!("transform {value}")))
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = TRANSFORMS[u32::from(*self) as usize];
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Transform {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let name = match source.trim() {
+            "0" => "normal",
+            name => name,
+        };
+        TRANSFORMS
+            .iter()
+            .find(|(_, candidate)| *candidate == name)
+            .map(|(transform, _)| *transform)
+            .ok_or_else(|| {
+                Error::InvalidMonitorSetting(format!(
+                    "transform '{source}' (expected normal, 90, 180, 270, flipped, flipped-90, \
+                     flipped-180 or flipped-270)"
+                ))
+            })
+    }
+}
+
+impl<'de> Deserialize<'de> for Transform {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct TransformVisitor;
+
+        impl Visitor<'_> for TransformVisitor {
+            type Value = Transform;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "an angle (0, 90, 180, 270) or a name such as \"flipped-90\""
+                )
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<Transform, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> std::result::Result<Transform, E> {
+                self.visit_str(&value.to_string())
+            }
+        }
+
+        deserializer.deserialize_any(TransformVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_describes_transforms() {
+        for (transform, name) in TRANSFORMS {
+            assert_eq!(name.parse::<Transform>().unwrap(), transform);
+            assert_eq!(transform.to_string(), name);
+            assert_eq!(
+                Transform::try_from(u32::from(transform)).unwrap(),
+                transform
+            );
+        }
+        assert_eq!("0".parse::<Transform>().unwrap(), Transform::Normal);
+        assert!("45".parse::<Transform>().is_err());
+
+        assert_eq!(Transform::describe(0), "normal");
+        assert_eq!(Transform::describe(1), "rotated 90° (portrait)");
+        assert_eq!(Transform::describe(6), "flipped, rotated 180°");
+        assert_eq!(Transform::describe(9), "unknown (9)");
+        assert_eq!(Transform::Rotate270.apply(3840, 2160), (2160, 3840));
+    }
+}
@@ -0,0 +1,550 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use futures::{StreamExt as _, stream::BoxStream};
+use inotify::{Inotify, WatchMask};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    Error, Result,
+    cli::{Condition, DisplayMode, DisplayRule, MonitorPattern, MonitorSettings, Selection},
+    feedback::Urgency,
+};
+
+const CONFIG_FILE: &str = "set-display/config.toml";
+
+/// Profiles of display rules, read from `$XDG_CONFIG_HOME/set-display/config.toml`
+///
+/// ```toml
+/// default_profile = "dock"
+///
+/// [profiles.dock]
+/// watch = true
+/// default = "external"
+///
+/// [[profiles.dock.rules]]
+/// mode = "mirror"
+/// match = 'product=LG TV'
+///
+/// [[profiles.dock.rules]]
+/// mode = "external"
+/// match = { product = "Acer" }
+///
+/// [[profiles.dock.rules.monitors]]
+/// match = { product = "Acer" }
+/// mode = "2560x1440@144"
+/// scale = 2.0
+///
+/// [[profiles.dock.rules]]
+/// mode = "internal"
+/// when = { all = ["count(internal) >= 1", { not = "lid-closed" }] }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Profile used when none is given on the command line
+    default_profile: Option<Spanned<String>>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Spanned<Profile>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Keep running and apply the rules whenever the monitors change (same as `--watch`)
+    #[serde(default)]
+    pub watch: bool,
+    /// Preview changes without applying them (same as `--test`)
+    #[serde(default)]
+    pub test: bool,
+    /// Speak display changes through speech-dispatcher (same as `--speak`)
+    #[serde(default)]
+    pub speak: bool,
+    /// Show desktop notifications for display changes and failures (same as `--notify`)
+    #[serde(default)]
+    pub notify: bool,
+    /// Urgency of change notifications (same as `--urgency`)
+    pub urgency: Option<Urgency>,
+    /// While watching, load the saved layout matching the connected monitors instead of running
+    /// the rules (default: true; `false` is the same as `--no-saved-layouts`)
+    pub saved_layouts: Option<bool>,
+    /// Restore the previous layout unless the new one is confirmed within this many seconds
+    /// (same as `--confirm`)
+    pub confirm: Option<u64>,
+    /// Have the compositor store layouts so that they survive logout (same as `--persistent`)
+    #[serde(default)]
+    pub persistent: bool,
+    /// Mode used when no rule matches
+    pub default: Option<DisplayMode>,
+    /// Rules in order of preference (first match wins)
+    #[serde(default)]
+    pub rules: Vec<ConfigRule>,
+    /// Modes and scales for every rule of the profile; those of a rule take precedence
+    #[serde(default)]
+    pub monitors: Vec<MonitorSettings>,
+    /// Primary monitor of every rule that doesn't name its own (default: the largest external)
+    pub primary: Option<MonitorPattern>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigRule {
+    pub mode: DisplayMode,
+    /// Monitors this rule applies to; omitted, the rule always matches
+    #[serde(default, rename = "match")]
+    pub pattern: MonitorPattern,
+    /// Condition over all connected monitors that must also hold
+    pub when: Option<Condition>,
+    /// Modes and scales instead of the preferred ones when this rule applies
+    #[serde(default)]
+    pub monitors: Vec<MonitorSettings>,
+    /// Primary monitor when this rule applies
+    pub primary: Option<MonitorPattern>,
+    /// Whether all the monitors matching `match` take part ("all"), or only the best ("best")
+    #[serde(default)]
+    pub select: Selection,
+    /// Join or mirror only external monitors, leaving the internal ones off
+    #[serde(default)]
+    pub externals_only: bool,
+    /// Order of the monitors, by the first pattern they match and then by connector name
+    #[serde(default)]
+    pub order: Vec<MonitorPattern>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/set-display/config.toml`, falling back to `~/.config`
+    pub fn default_path() -> PathBuf {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config")
+            })
+            .join(CONFIG_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|error| Error::ConfigRead {
+            path: path.into(),
+            error,
+        })?;
+        Self::parse(path, &content)
+    }
+
+    /// Parse and validate `content`, reporting errors at their line in `path`
+    pub fn parse(path: &Path, content: &str) -> Result<Self> {
+        let invalid = |span: Option<Range<usize>>, message: &str| Error::InvalidConfig {
+            path: path.into(),
+            line: span.map_or(0, |span| line_of(content, span.start)),
+            message: message.trim().to_string(),
+        };
+
+        let config: Self =
+            toml::from_str(content).map_err(|error| invalid(error.span(), error.message()))?;
+
+        if let Some(name) = &config.default_profile
+            && !config.profiles.contains_key(name.get_ref())
+        {
+            return Err(invalid(
+                Some(name.span()),
+                &format!("default_profile '{}' is not defined", name.get_ref()),
+            ));
+        }
+
+        for (name, profile) in &config.profiles {
+            if profile.get_ref().rules.is_empty() && profile.get_ref().default.is_none() {
+                return Err(invalid(
+                    Some(profile.span()),
+                    &format!("profile '{name}' has neither rules nor a default mode"),
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The profile called `name`, or the default profile
+    pub fn profile(&self, name: Option<&str>) -> Result<&Profile> {
+        let name = match name {
+            Some(name) => name,
+            None => self
+                .default_profile
+                .as_ref()
+                .map(|name| name.get_ref().as_str())
+                .ok_or(Error::NoDefaultProfile)?,
+        };
+        self.profiles
+            .get(name)
+            .map(Spanned::get_ref)
+            .ok_or_else(|| Error::UnknownProfile(name.to_string()))
+    }
+}
+
+/// Stream of the rules of profile `name` (or the default profile), reloaded whenever `path` is
+/// written. Invalid configurations are reported and skipped, so the previous rules stay in effect.
+/// Options only take effect on start, so changes to those of `started` are reported as such.
+pub fn watch_rules(
+    path: PathBuf,
+    name: Option<String>,
+    started: Profile,
+) -> Result<BoxStream<'static, Vec<DisplayRule>>> {
+    let watch_error = |error| Error::ConfigWatch {
+        path: path.clone(),
+        error,
+    };
+
+    // Watch the directory: editors usually replace the file instead of writing to it
+    let directory = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let file_name = path.file_name().map(ToOwned::to_owned);
+
+    let inotify = Inotify::init().map_err(watch_error)?;
+    inotify
+        .watches()
+        .add(&directory, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+        .map_err(watch_error)?;
+    let events = inotify
+        .into_event_stream([0u8; 4096])
+        .map_err(watch_error)?;
+
+    Ok(events
+        .filter_map(move |event| {
+            let reload = match event {
+                Ok(event) => event.name == file_name,
+                Err(error) => {
+                    eprintln!("Failed to watch {}: {error}", path.display());
+                    false
+                }
+            };
+            let rules = reload.then(|| {
+                Config::load(&path)
+                    .and_then(|config| {
+                        let profile = config.profile(name.as_deref())?;
+                        let changed = profile.changed_options(&started);
+                        if !changed.is_empty() {
+                            eprintln!(
+                                "Changed options of {} take effect on restart: {}. Only the rules \
+                                 are reloaded.",
+                                path.display(),
+                                changed.join(", ")
+                            );
+                        }
+                        Ok(profile.rules())
+                    })
+                    .inspect_err(|error| {
+                        eprintln!("{error}");
+                        eprintln!("Keeping the previous rules.");
+                    })
+                    .ok()
+            });
+            async move { rules.flatten() }
+        })
+        .boxed())
+}
+
+impl Profile {
+    pub fn rules(&self) -> Vec<DisplayRule> {
+        let mut rules: Vec<DisplayRule> = self
+            .rules
+            .iter()
+            .map(|rule| DisplayRule {
+                pattern: rule.pattern.clone(),
+                condition: rule.when.clone(),
+                monitors: [&rule.monitors[..], &self.monitors[..]].concat(),
+                primary: rule.primary.clone().or_else(|| self.primary.clone()),
+                select: rule.select,
+                externals_only: rule.externals_only,
+                order: rule.order.clone(),
+                ..DisplayRule::always(rule.mode)
+            })
+            .collect();
+
+        // Add the default rule (always matches)
+        if let Some(mode) = self.default {
+            rules.push(DisplayRule {
+                monitors: self.monitors.clone(),
+                primary: self.primary.clone(),
+                ..DisplayRule::always(mode)
+            });
+        }
+
+        rules
+    }
+
+    /// Keys of the options that differ from those of `other`
+    fn changed_options(&self, other: &Profile) -> Vec<&'static str> {
+        [
+            ("watch", self.watch != other.watch),
+            ("test", self.test != other.test),
+            ("speak", self.speak != other.speak),
+            ("notify", self.notify != other.notify),
+            ("urgency", self.urgency != other.urgency),
+            ("saved_layouts", self.saved_layouts != other.saved_layouts),
+            ("confirm", self.confirm != other.confirm),
+            ("persistent", self.persistent != other.persistent),
+        ]
+        .into_iter()
+        .filter_map(|(key, changed)| changed.then_some(key))
+        .collect()
+    }
+}
+
+/// 1-based line number of the byte at `offset`
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Config> {
+        Config::parse(Path::new("config.toml"), content)
+    }
+
+    fn error_line(content: &str) -> usize {
+        match parse(content) {
+            Err(Error::InvalidConfig { line, .. }) => line,
+            result => panic!("expected an invalid configuration, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_profiles_in_order() {
+        let config = parse(
+            r#"
+default_profile = "dock"
+
+[profiles.dock]
+watch = true
+saved_layouts = false
+default = "internal"
+
+[[profiles.dock.rules]]
+mode = "external"
+match = "product=Acer"
+
+[[profiles.dock.rules]]
+mode = "mirror"
+match = { product = "LG TV", vendor = "GSM" }
+
+[profiles.laptop]
+default = "internal"
+"#,
+        )
+        .unwrap();
+
+        let dock = config.profile(None).unwrap();
+        assert!(dock.watch);
+        assert!(!dock.test);
+        assert_eq!(dock.saved_layouts, Some(false));
+
+        let rules = dock.rules();
+        let modes: Vec<_> = rules.iter().map(|rule| rule.mode.to_string()).collect();
+        assert_eq!(modes, ["External", "Mirror", "Internal"]);
+        assert!(!rules[1].pattern.is_empty());
+        assert!(rules[2].pattern.is_empty());
+
+        assert_eq!(config.profile(Some("laptop")).unwrap().rules().len(), 1);
+        assert!(matches!(
+            config.profile(Some("office")),
+            Err(Error::UnknownProfile(_))
+        ));
+    }
+
+    #[test]
+    fn parses_rule_conditions() {
+        let config = parse(
+            r#"
+[profiles.dock]
+[[profiles.dock.rules]]
+mode = "internal"
+when = { all = ["count(internal) >= 1", { not = "lid-closed" }] }
+
+[[profiles.dock.rules]]
+mode = "join"
+match = "vendor=DEL"
+when = "count(external) == 2"
+"#,
+        )
+        .unwrap();
+
+        let rules: Vec<_> = config
+            .profile(Some("dock"))
+            .unwrap()
+            .rules()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                "Internal when all(count(internal) >= 1, not(lid-closed))",
+                "Join when vendor=DEL and count(external) == 2",
+            ]
+        );
+
+        assert_eq!(
+            error_line(
+                "[profiles.dock]\n[[profiles.dock.rules]]\nmode = \"join\"\nwhen = { some = [] }\n"
+            ),
+            4
+        );
+    }
+
+    #[test]
+    fn parses_monitor_settings_of_rules_and_profiles() {
+        let config = parse(
+            r#"
+[profiles.dock]
+default = "join"
+primary = "connector=eDP-1"
+
+[[profiles.dock.monitors]]
+refresh = "max"
+
+[[profiles.dock.rules]]
+mode = "external"
+match = "product=Acer"
+
+[[profiles.dock.rules.monitors]]
+match = "product=Acer"
+mode = "2560x1440@144"
+scale = 2.0
+transform = 270
+"#,
+        )
+        .unwrap();
+
+        let rules: Vec<_> = config
+            .profile(Some("dock"))
+            .unwrap()
+            .rules()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                "External when product=Acer; product=Acer: mode 2560x1440@144, scale 2, \
+                 transform 270; any monitor: refresh max; primary connector=eDP-1",
+                "Join (always matches); any monitor: refresh max; primary connector=eDP-1",
+            ]
+        );
+
+        assert_eq!(
+            error_line(
+                "[profiles.dock]\ndefault = \"join\"\n[[profiles.dock.monitors]]\nmode = \"wide\"\n"
+            ),
+            4
+        );
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        // Syntax error
+        assert_eq!(error_line("[profiles.dock]\nwatch = \n"), 2);
+
+        // Unknown mode
+        assert_eq!(
+            error_line("[profiles.dock]\n\n[[profiles.dock.rules]]\nmode = \"sideways\"\n"),
+            4
+        );
+
+        // Unknown pattern field
+        assert_eq!(
+            error_line(
+                "[profiles.dock]\n[[profiles.dock.rules]]\nmode = \"external\"\nmatch = \"model=Acer\"\n"
+            ),
+            4
+        );
+
+        // Undefined default profile
+        assert_eq!(
+            error_line("\ndefault_profile = \"office\"\n[profiles.dock]\ndefault = \"external\"\n"),
+            2
+        );
+
+        // Empty profile
+        assert_eq!(
+            error_line("[profiles.dock]\n[profiles.laptop]\nwatch = true\n"),
+            1
+        );
+    }
+
+    #[test]
+    fn requires_a_profile_name_without_default() {
+        let config = parse("[profiles.dock]\ndefault = \"external\"\n").unwrap();
+        assert!(matches!(config.profile(None), Err(Error::NoDefaultProfile)));
+    }
+
+    #[test]
+    fn lists_changed_options() {
+        let config = parse(
+            "[profiles.dock]\ndefault = \"external\"\n\
+             [profiles.quiet]\ndefault = \"mirror\"\nnotify = true\nconfirm = 15\n",
+        )
+        .unwrap();
+        let dock = config.profile(Some("dock")).unwrap();
+        let quiet = config.profile(Some("quiet")).unwrap();
+        assert_eq!(quiet.changed_options(dock), ["notify", "confirm"]);
+        assert!(dock.changed_options(&Profile::default()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn watch_rules_reloads_valid_configurations_only() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+        fs::write(&path, "[profiles.dock]\ndefault = \"external\"\n").unwrap();
+
+        let started = parse("[profiles.dock]\ndefault = \"external\"\n").unwrap();
+        let started = started.profile(Some("dock")).unwrap().clone();
+        let mut reloads = watch_rules(path.clone(), Some("dock".into()), started).unwrap();
+        let next = async |reloads: &mut BoxStream<'static, Vec<DisplayRule>>| {
+            tokio::time::timeout(std::time::Duration::from_secs(5), reloads.next())
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        fs::write(&path, "[profiles.dock]\ndefault = \"mirror\"\n").unwrap();
+        let rules = next(&mut reloads).await;
+        assert!(matches!(
+            rules[..],
+            [DisplayRule {
+                mode: DisplayMode::Mirror,
+                ..
+            }]
+        ));
+
+        // Broken, then fixed by replacing the file as editors do
+        fs::write(&path, "[profiles.dock]\ndefault = \"sideways\"\n").unwrap();
+        let replacement = directory.path().join("config.toml.new");
+        fs::write(&replacement, "[profiles.dock]\ndefault = \"join\"\n").unwrap();
+        fs::rename(&replacement, &path).unwrap();
+        let rules = next(&mut reloads).await;
+        assert!(matches!(
+            rules[..],
+            [DisplayRule {
+                mode: DisplayMode::Join,
+                ..
+            }]
+        ));
+
+        // Other files in the directory are ignored
+        fs::write(directory.path().join("notes.txt"), "").unwrap();
+        fs::write(&path, "[profiles.dock]\ndefault = \"internal\"\n").unwrap();
+        let rules = next(&mut reloads).await;
+        assert!(matches!(
+            rules[..],
+            [DisplayRule {
+                mode: DisplayMode::Internal,
+                ..
+            }]
+        ));
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    io::{self, BufRead as _, IsTerminal as _},
+    sync::{Arc, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use tokio::{
+    sync::{Notify, mpsc},
+    time::sleep,
+};
+use zbus::{connection, interface, proxy};
+
+use crate::{Error, Result};
+
+pub const BUS_NAME: &str = "io.github.dra11y.SetDisplay";
+pub const OBJECT_PATH: &str = "/io/github/dra11y/SetDisplay";
+
+/// Receives `set-display confirm` (or any other D-Bus caller) while a layout awaits confirmation
+pub struct ConfirmService(pub mpsc::UnboundedSender<()>);
+
+#[interface(name = "io.github.dra11y.SetDisplay1")]
+impl ConfirmService {
+    /// Keep the layout that was just applied
+    async fn confirm(&self) {
+        let _ = self.0.send(());
+    }
+}
+
+#[proxy(
+    interface = "io.github.dra11y.SetDisplay1",
+    default_service = "io.github.dra11y.SetDisplay",
+    default_path = "/io/github/dra11y/SetDisplay"
+)]
+pub trait Confirm {
+    fn confirm(&self) -> zbus::Result<()>;
+}
+
+/// Wait up to `timeout` for the user to keep the new layout: Enter on the terminal, or a D-Bus
+/// call such as `set-display confirm`. Returns whether it was confirmed in time.
+pub async fn wait_for_confirmation(timeout: Duration) -> bool {
+    let (sender, mut confirmations) = mpsc::unbounded_channel();
+
+    // The bus name only exists while waiting, so `confirm` fails when there is nothing to confirm
+    let service = connection::Builder::session()
+        .and_then(|builder| builder.name(BUS_NAME))
+        .and_then(|builder| builder.serve_at(OBJECT_PATH, ConfirmService(sender)));
+    let _connection = match service {
+        Ok(builder) => builder
+            .build()
+            .await
+            .inspect_err(|error| eprintln!("`set-display confirm` is unavailable: {error}"))
+            .ok(),
+        Err(error) => {
+            eprintln!("`set-display confirm` is unavailable: {error}");
+            None
+        }
+    };
+
+    let keypresses = keypresses();
+    let prompt = match keypresses {
+        Some(_) => "Press Enter or run `set-display confirm`",
+        None => "Run `set-display confirm`",
+    };
+    println!(
+        "{prompt} within {} seconds to keep this layout.",
+        timeout.as_secs()
+    );
+
+    tokio::select! {
+        Some(()) = confirmations.recv() => true,
+        _ = async { keypresses.unwrap().notified().await }, if keypresses.is_some() => true,
+        _ = sleep(timeout) => false,
+    }
+}
+
+/// Ask the running `set-display` to keep the layout it is waiting on
+pub async fn confirm() -> Result<()> {
+    let connection = zbus::Connection::session().await?;
+    let proxy = ConfirmProxy::new(&connection).await?;
+    match proxy.confirm().await {
+        Ok(()) => Ok(()),
+        Err(zbus::Error::MethodError(name, _, _))
+            if name == "org.freedesktop.DBus.Error.ServiceUnknown"
+                || name == "org.freedesktop.DBus.Error.NameHasNoOwner" =>
+        {
+            Err(Error::NothingToConfirm)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Lines typed on the terminal, or `None` when stdin isn't one. A detached thread reads stdin
+/// so that a pending read never holds up exiting; lines typed while nobody waits are dropped.
+fn keypresses() -> Option<&'static Notify> {
+    static KEYPRESSES: OnceLock<Option<Arc<Notify>>> = OnceLock::new();
+    KEYPRESSES
+        .get_or_init(|| {
+            if !io::stdin().is_terminal() {
+                return None;
+            }
+            let notify = Arc::new(Notify::new());
+            let reader = notify.clone();
+            thread::spawn(move || {
+                for _ in io::stdin()
+                    .lock()
+                    .lines()
+                    .map_while(std::result::Result::ok)
+                {
+                    reader.notify_waiters();
+                }
+            });
+            Some(notify)
+        })
+        .as_deref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::peer_to_peer;
+
+    #[tokio::test]
+    async fn confirm_calls_reach_the_waiting_service() {
+        let (sender, mut confirmations) = mpsc::unbounded_channel();
+        let (_server, client) = peer_to_peer(OBJECT_PATH, ConfirmService(sender))
+            .await
+            .unwrap();
+
+        let proxy = ConfirmProxy::builder(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        proxy.confirm().await.unwrap();
+        assert_eq!(confirmations.recv().await, Some(()));
+    }
+}
@@ -22,3 +22,23 @@ pub async fn connect(max_attempts: usize) -> Result<Connection> {
         }
     }
 }
+
+/// A server serving `object` at `path`, and a client connected to it peer to peer
+#[cfg(test)]
+pub async fn peer_to_peer<I: zbus::object_server::Interface>(
+    path: &str,
+    object: I,
+) -> zbus::Result<(Connection, Connection)> {
+    use std::os::unix::net::UnixStream;
+    use zbus::{Guid, connection::Builder};
+
+    let (server_stream, client_stream) = UnixStream::pair()?;
+    futures::try_join!(
+        Builder::async_io_unix_stream(server_stream)
+            .server(Guid::generate())?
+            .p2p()
+            .serve_at(path, object)?
+            .build(),
+        Builder::async_io_unix_stream(client_stream).p2p().build(),
+    )
+}
@@ -1,64 +1,45 @@
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, fmt, path::Path, time::Duration};
 
-use crate::detection::DesktopEnvironment;
 use crate::{
     Error, Monitor, Result,
-    cli::{DisplayMode, DisplayRule},
-    connect,
+    backend::{ApplyMethod, Capabilities, DisplayBackend},
+    cli::{
+        DisplayMode, DisplayRule, ModeSpec, MonitorPattern, MonitorSettings, Refresh, RuleContext,
+        Tile, Transform, arrange,
+    },
+    confirmation,
+    feedback::Feedback,
+    journal::{Change, Trigger},
     printable_monitor::convert_for_printing,
+    saved_layout::{Fingerprint, SavedLayout},
     structs::{ApplyLogicalMonitorTuple, ConnectorInfo, CurrentLogicalMonitor},
 };
-use futures::StreamExt as _;
+use futures::{StreamExt as _, stream::BoxStream};
 use tokio::time::sleep;
-use zbus::Connection;
-use zbus::proxy::SignalStream;
 use zbus::zvariant::OwnedValue;
 
-use crate::generated;
-
-const WATCHING: &str = "\nWatching for monitor configuration changes... (Press Ctrl+C to exit)\n";
-
-pub enum DisplayConfigProxy<'a> {
-    Gnome(generated::gnome_proxy::DisplayConfigProxy<'a>),
-    Cinnamon(generated::cinnamon_proxy::DisplayConfigProxy<'a>),
+/// How layouts are applied
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApplyOptions {
+    /// Print the layout instead of applying it
+    pub dry_run: bool,
+    /// Restore the previous layout unless the new one is confirmed within this time
+    pub confirm: Option<Duration>,
+    /// Have the compositor store the layout so that it survives logout
+    pub persistent: bool,
 }
 
-impl<'a> DisplayConfigProxy<'a> {
-    pub async fn new(conn: &'a Connection) -> Result<Self> {
-        match DesktopEnvironment::detect() {
-            DesktopEnvironment::Gnome => Ok(Self::Gnome(
-                generated::gnome_proxy::DisplayConfigProxy::new(conn).await?,
-            )),
-            DesktopEnvironment::Cinnamon => Ok(Self::Cinnamon(
-                generated::cinnamon_proxy::DisplayConfigProxy::new(conn).await?,
-            )),
-            DesktopEnvironment::Unknown(desktop) => Err(Error::UnsupportedDesktop(desktop)),
-        }
-    }
-
-    pub async fn get_current_state(&self) -> Result<CurrentStateTuple> {
-        match self {
-            Self::Gnome(p) => p.get_current_state().await.map_err(Into::into),
-            Self::Cinnamon(p) => p.get_current_state().await.map_err(Into::into),
-        }
-    }
-
-    pub async fn receive_monitors_changed(&self) -> Result<SignalStream<'static>> {
-        match self {
-            DisplayConfigProxy::Gnome(p) => p
-                .receive_monitors_changed()
-                .await
-                .map(|s| s.into_inner())
-                .map_err(Into::into),
-            DisplayConfigProxy::Cinnamon(p) => p
-                .receive_monitors_changed()
-                .await
-                .map(|s| s.into_inner())
-                .map_err(Into::into),
+impl ApplyOptions {
+    fn method(&self) -> ApplyMethod {
+        match self.persistent {
+            true => ApplyMethod::Persistent,
+            false => ApplyMethod::Temporary,
         }
     }
 }
 
+const WATCHING: &str = "\nWatching for monitor configuration changes... (Press Ctrl+C to exit)\n";
+
 #[derive(Debug, Clone)]
 pub struct CurrentState {
     pub serial: u32,
@@ -68,7 +49,7 @@ pub struct CurrentState {
 }
 
 impl CurrentState {
-    pub async fn current(max_attempts: usize) -> Result<Self> {
+    pub async fn current(backend: &dyn DisplayBackend, max_attempts: usize) -> Result<Self> {
         let mut attempt = 0;
         loop {
             attempt += 1;
@@ -79,28 +60,10 @@ impl CurrentState {
                 }
             }
 
-            let connection = match connect(10).await {
-                Ok(connection) => connection,
+            match backend.get_current_state().await {
+                Ok(state) => return Ok(state),
                 Err(error) => {
-                    eprintln!("Attempt {attempt}: Failed to connect to DBus: {error}");
-                    continue;
-                }
-            };
-
-            let proxy = match DisplayConfigProxy::new(&connection).await {
-                Ok(proxy) => proxy,
-                Err(error) => {
-                    eprintln!(
-                        "Attempt {attempt}: Failed to connect to DisplayConfigProxy: {error}"
-                    );
-                    continue;
-                }
-            };
-
-            match proxy.get_current_state().await {
-                Ok(state) => return Ok(state.into()),
-                Err(error) => {
-                    eprintln!("Attempt {attempt}: DBus Proxy Error: {error}");
+                    eprintln!("Attempt {attempt}: {} Error: {error}", backend.name());
                     continue;
                 }
             }
@@ -154,15 +117,16 @@ impl CurrentState {
         println!("  {}. Position: ({}, {})", i + 1, logical.x, logical.y,);
         println!("     Scale: {}", logical.scale);
         println!("     Primary: {}", logical.primary);
-        println!("     Transform: {}", logical.transform);
+        println!("     Transform: {}", Transform::describe(logical.transform));
         println!("     Assigned Monitors:");
         for (i, connector_info) in logical.assigned_monitors.iter().enumerate() {
             self.print_connector_info(Some(i), connector_info);
         }
     }
 
-    pub async fn print_status(&self, show_modes: bool) -> Result<()> {
+    pub async fn print_status(&self, backend: &dyn DisplayBackend, show_modes: bool) -> Result<()> {
         println!("=== Current Monitor Status ===");
+        println!("Backend: {} ({})\n", backend.name(), backend.capabilities());
 
         let (internal_monitors, external_monitors): (Vec<_>, Vec<_>) =
             self.monitors.iter().partition(Monitor::is_builtin);
@@ -185,100 +149,322 @@ impl CurrentState {
         Ok(())
     }
 
-    pub async fn enable_monitors(mode: &DisplayMode, attempt: usize, dry_run: bool) -> Result<()> {
-        let state = Self::current(10).await?;
-
-        // Partition monitors into internal and external
-        let (internal_monitors, external_monitors): (Vec<_>, Vec<_>) =
-            state.monitors.iter().partition(|m| m.is_builtin);
-
-        let monitors_to_use = match mode {
-            DisplayMode::External => {
-                if external_monitors.is_empty() {
-                    eprintln!("No external monitors available.");
-                    return Ok(());
-                }
-                external_monitors
-            }
-            DisplayMode::Internal => {
-                if internal_monitors.is_empty() {
-                    eprintln!("No internal monitors available.");
-                    return Ok(());
-                }
-                internal_monitors
-            }
-            DisplayMode::Join | DisplayMode::Mirror => {
-                if state.monitors.is_empty() {
-                    eprintln!("No monitors to configure.");
-                    return Ok(());
-                }
-                state.monitors.iter().collect()
+    pub async fn enable_monitors(
+        backend: &dyn DisplayBackend,
+        rule: &DisplayRule,
+        trigger: Trigger,
+        options: ApplyOptions,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        let state = Self::current(backend, 10).await?;
+        let mode = &rule.mode;
+
+        // The monitors the rule selects; all others are turned off
+        let monitors_to_use = rule.participants(&state.monitors);
+        if monitors_to_use.is_empty() {
+            match mode {
+                DisplayMode::External => eprintln!("No external monitors available."),
+                DisplayMode::Internal => eprintln!("No internal monitors available."),
+                DisplayMode::Join | DisplayMode::Mirror => eprintln!("No monitors to configure."),
             }
-        };
+            return Ok(());
+        }
 
         // Generate logical monitor configurations
 
         let logical_monitors: Vec<ApplyLogicalMonitorTuple> = match mode {
-            DisplayMode::Mirror => build_mirrored(monitors_to_use),
-            _ => build_joined_or_individual(monitors_to_use, mode),
+            DisplayMode::Mirror => build_mirrored(monitors_to_use, &rule.monitors),
+            _ => build_joined_or_individual(
+                monitors_to_use,
+                mode,
+                &rule.monitors,
+                rule.primary.as_ref(),
+            ),
         }?;
 
         if logical_monitors.is_empty() {
             return Err(Error::NoMonitorsAvailable(*mode));
         }
 
-        if dry_run {
+        let change = Change {
+            trigger,
+            rule: Some(rule),
+        };
+        state
+            .apply_layout(backend, &logical_monitors, change, options, feedback)
+            .await
+    }
+
+    /// Apply an earlier layout, such as one from the journal, as long as its monitors and modes
+    /// are still available
+    pub async fn restore_layout(
+        backend: &dyn DisplayBackend,
+        layout: &[ApplyLogicalMonitorTuple],
+        trigger: Trigger,
+        options: ApplyOptions,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        let state = Self::current(backend, 10).await?;
+
+        for (_, _, _, _, _, assigned) in layout {
+            for (connector, mode_id, _) in assigned {
+                let monitor = state
+                    .monitors
+                    .iter()
+                    .find(|monitor| monitor.connector_info.connector == *connector)
+                    .ok_or_else(|| Error::MonitorNotConnected(connector.clone()))?;
+                if !monitor.modes.iter().any(|mode| mode.id == *mode_id) {
+                    return Err(Error::ModeUnavailable {
+                        connector: connector.clone(),
+                        mode: mode_id.clone(),
+                        closest: None,
+                    });
+                }
+            }
+        }
+
+        let change = Change {
+            trigger,
+            rule: None,
+        };
+        state
+            .apply_layout(backend, layout, change, options, feedback)
+            .await
+    }
+
+    /// Ask the compositor whether it would accept `logical_monitors`, without applying them
+    ///
+    /// Returns whether the backend could check at all.
+    async fn verify_layout(
+        &self,
+        backend: &dyn DisplayBackend,
+        logical_monitors: &[ApplyLogicalMonitorTuple],
+    ) -> Result<bool> {
+        if !backend.capabilities().verify {
+            return Ok(false);
+        }
+        backend
+            .apply_monitors_config(self.serial, ApplyMethod::Verify, logical_monitors)
+            .await?;
+        Ok(true)
+    }
+
+    /// Apply `logical_monitors` in place of this state, verify them, announce and record the
+    /// change, and restore this state unless the change is confirmed in time
+    async fn apply_layout(
+        &self,
+        backend: &dyn DisplayBackend,
+        logical_monitors: &[ApplyLogicalMonitorTuple],
+        change: Change<'_>,
+        options: ApplyOptions,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        if options.dry_run {
             println!("[TEST MODE] The following configuration would have been applied:");
             for (i, logical) in logical_monitors.iter().enumerate() {
-                let print_monitor = convert_for_printing(logical, &state.monitors);
+                let print_monitor = convert_for_printing(logical, &self.monitors);
                 print_monitor.print(i);
             }
+            match self.verify_layout(backend, logical_monitors).await? {
+                true => println!("✓ {} would accept this configuration.", backend.name()),
+                false => println!(
+                    "{} cannot check configurations without applying them.",
+                    backend.name()
+                ),
+            }
             return Ok(());
         }
 
-        let dbus_config = DesktopEnvironment::detect().dbus_config()?;
+        let mirrored = logical_monitors
+            .iter()
+            .any(|(_, _, _, _, _, assigned)| assigned.len() > 1);
+        if mirrored && !backend.capabilities().mirroring {
+            eprintln!(
+                "{} cannot mirror monitors; they will overlap at the same position.",
+                backend.name()
+            );
+        }
+        let primary_elsewhere = logical_monitors
+            .iter()
+            .any(|(x, y, _, _, primary, _)| *primary && (*x, *y) != (0, 0));
+        if primary_elsewhere && !backend.capabilities().primary {
+            eprintln!(
+                "{} has no primary monitor; the one at the top left acts as such.",
+                backend.name()
+            );
+        }
+        if options.persistent && !backend.capabilities().persistent {
+            eprintln!(
+                "{} cannot store layouts; this one will not survive logout.",
+                backend.name()
+            );
+        }
 
-        let config_properties = HashMap::<String, OwnedValue>::new();
+        // Find out why a layout would be refused before anything changes
+        self.verify_layout(backend, logical_monitors).await?;
 
-        // Parameters for ApplyMonitorsConfig
-        // https://browse.dgit.debian.org/mutter.git/plain/data/dbus-interfaces/org.gnome.Mutter.DisplayConfig.xml
-        let params = (
-            state.serial,             // serial
-            1u32,                     // method (1 = temporary, 2 = persistent)
-            logical_monitors.clone(), // logical monitor configs
-            config_properties,        // properties
-        );
+        println!("Applying configuration through {}...", backend.name());
+        backend
+            .apply_monitors_config(self.serial, options.method(), logical_monitors)
+            .await?;
 
-        println!("Connecting to DBus (attempt {attempt})...");
-        let connection = connect(10).await?;
+        let updated_state = CurrentState::current(backend, 10).await?;
+        match updated_state.verify_applied_config(logical_monitors, backend.capabilities()) {
+            Ok(true) => {
+                println!("✓ Monitor configuration successfully applied.");
+                feedback
+                    .applied(change, logical_monitors, &self.monitors, self.layout())
+                    .await;
+            }
+            Ok(false) => return Err(Error::FailedVerification(backend.name())),
+            Err(error) => return Err(error),
+        }
 
-        println!("Calling DBus method:\n{dbus_config:#?}");
+        // Give the user a chance to keep the layout before restoring the previous one
+        let Some(timeout) = options.confirm else {
+            return Ok(());
+        };
+        feedback.confirm_requested(timeout).await;
+        if confirmation::wait_for_confirmation(timeout).await {
+            println!("✓ Layout kept.");
+            return Ok(());
+        }
 
-        let message = connection
-            .call_method(
-                Some(dbus_config.service),
-                dbus_config.path,
-                Some(dbus_config.interface),
-                dbus_config.method,
-                &params,
-            )
+        println!("Not confirmed, restoring the previous layout...");
+        let current = Self::current(backend, 10).await?;
+        backend
+            .apply_monitors_config(current.serial, options.method(), &self.layout())
             .await?;
+        let revert = Change {
+            trigger: Trigger::Revert,
+            rule: None,
+        };
+        feedback.record(revert, logical_monitors, &self.layout());
+        feedback.reverted().await;
+        Err(Error::NotConfirmed(timeout.as_secs()))
+    }
 
-        let updated_state = CurrentState::current(10).await?;
-        match updated_state.verify_applied_config(&logical_monitors) {
-            Ok(true) => {
-                println!("✓ Monitor configuration successfully applied.");
-                Ok(())
+    /// The current logical monitors, as arguments to apply them again
+    pub fn layout(&self) -> Vec<ApplyLogicalMonitorTuple> {
+        self.logical_monitors
+            .iter()
+            .map(|logical| {
+                let assigned = logical
+                    .assigned_monitors
+                    .iter()
+                    .filter_map(|info| {
+                        let mode = self
+                            .monitors
+                            .iter()
+                            .find(|monitor| monitor.connector_info.connector == info.connector)?
+                            .modes
+                            .iter()
+                            .find(|mode| mode.is_current)?;
+                        Some((info.connector.clone(), mode.id.clone(), HashMap::new()))
+                    })
+                    .collect();
+                (
+                    logical.x,
+                    logical.y,
+                    logical.scale,
+                    logical.transform,
+                    logical.primary,
+                    assigned,
+                )
+            })
+            .collect()
+    }
+
+    /// The current layout with the monitors matching `pattern` rotated to `transform`; monitors
+    /// right of or below them move along, so that they still touch
+    pub fn rotated(
+        &self,
+        pattern: &MonitorPattern,
+        transform: Transform,
+    ) -> Result<Vec<ApplyLogicalMonitorTuple>> {
+        let mut layout = self.layout();
+        let mut rotated = false;
+
+        for i in 0..layout.len() {
+            let (x, y, scale, current, _, assigned) = &layout[i];
+            let monitors: Vec<&Monitor> = assigned
+                .iter()
+                .filter_map(|(connector, _, _)| {
+                    self.monitors
+                        .iter()
+                        .find(|monitor| monitor.connector_info.connector == *connector)
+                })
+                .collect();
+            if !monitors.iter().any(|monitor| pattern.matches(monitor)) {
+                continue;
             }
-            Ok(false) => Err(Error::FailedVerification(message)),
-            Err(error) => Err(error),
+            rotated = true;
+
+            // Logical size before and after, from the current mode of the first monitor
+            let Some(mode) = monitors
+                .first()
+                .and_then(|monitor| monitor.modes.iter().find(|mode| mode.id == assigned[0].1))
+            else {
+                continue;
+            };
+            let size = (
+                (mode.width as f64 / scale).round() as i32,
+                (mode.height as f64 / scale).round() as i32,
+            );
+            let current = Transform::try_from(*current).unwrap_or_default();
+            let (width, height) = current.apply(size.0, size.1);
+            let (new_width, new_height) = transform.apply(size.0, size.1);
+            let (right, bottom) = (x + width, y + height);
+
+            for (j, other) in layout.iter_mut().enumerate() {
+                if j == i {
+                    other.3 = transform.into();
+                    continue;
+                }
+                if other.0 >= right {
+                    other.0 += new_width - width;
+                }
+                if other.1 >= bottom {
+                    other.1 += new_height - height;
+                }
+            }
+        }
+
+        match rotated {
+            true => Ok(layout),
+            false => Err(Error::NoMatchingMonitor(pattern.to_string())),
         }
     }
 
+    /// The current layout with the first logical monitor showing a monitor matching `pattern`
+    /// as the primary one
+    pub fn with_primary(&self, pattern: &MonitorPattern) -> Result<Vec<ApplyLogicalMonitorTuple>> {
+        let primary = self
+            .logical_monitors
+            .iter()
+            .position(|logical| {
+                logical.assigned_monitors.iter().any(|info| {
+                    self.monitors
+                        .iter()
+                        .find(|monitor| monitor.connector_info.connector == info.connector)
+                        .is_some_and(|monitor| pattern.matches(monitor))
+                })
+            })
+            .ok_or_else(|| Error::NoMatchingMonitor(pattern.to_string()))?;
+
+        let mut layout = self.layout();
+        for (i, logical) in layout.iter_mut().enumerate() {
+            logical.4 = i == primary;
+        }
+        Ok(layout)
+    }
+
     pub async fn determine_and_execute_mode(
+        backend: &dyn DisplayBackend,
         rules: &[DisplayRule],
-        attempt: usize,
-        dry_run: bool,
+        trigger: Trigger,
+        options: ApplyOptions,
+        feedback: &Feedback,
     ) -> Result<()> {
         let mut inner_attempt = 0;
         loop {
@@ -288,8 +474,8 @@ impl CurrentState {
                 sleep(Duration::from_secs(1)).await;
             }
 
-            let mode = match Self::determine_mode(rules).await {
-                Ok(mode) => mode,
+            let rule = match Self::determine_rule(backend, rules).await {
+                Ok(rule) => rule,
                 Err(Error::NoMonitorsMatch(_)) => {
                     eprintln!("No monitors match rules, returning OK.");
                     return Ok(());
@@ -298,25 +484,73 @@ impl CurrentState {
                     if inner_attempt < 3 {
                         continue;
                     }
+                    feedback.failed(&error).await;
                     return Err(error);
                 }
             };
 
-            println!("Determined mode: {mode:?}");
+            println!("Determined mode: {:?}", rule.mode);
 
-            match Self::enable_monitors(&mode, attempt, dry_run).await {
+            match Self::enable_monitors(backend, &rule, trigger, options, feedback).await {
                 Ok(_) => return Ok(()),
+                // Trying again would bring back the layout the user just rejected
+                Err(error @ Error::NotConfirmed(_)) => return Err(error),
+                // ...or have the compositor refuse the same layout again
+                Err(error @ Error::ConfigurationRejected { .. }) => {
+                    feedback.failed(&error).await;
+                    return Err(error);
+                }
                 Err(error) => {
                     if inner_attempt < 3 {
                         continue;
                     }
+                    feedback.failed(&error).await;
                     return Err(error);
                 }
             }
         }
     }
 
-    pub async fn watch_and_execute(rules: &[DisplayRule], dry_run: bool) -> Result<()> {
+    /// Load the layout saved in `layouts` for the connected monitors, if there is one, or else
+    /// apply `rules`
+    pub async fn load_or_execute_mode(
+        backend: &dyn DisplayBackend,
+        rules: &[DisplayRule],
+        layouts: Option<&Path>,
+        trigger: Trigger,
+        options: ApplyOptions,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        if let Some(directory) = layouts {
+            let state = Self::current(backend, 10).await?;
+            match SavedLayout::matching(directory, &Fingerprint::of(&state.monitors)) {
+                Ok(Some((name, saved))) => {
+                    println!("Loading saved layout '{name}' for the connected monitors...");
+                    let layout = saved.resolve(&name, &state)?;
+                    return Self::restore_layout(backend, &layout, trigger, options, feedback)
+                        .await;
+                }
+                Ok(None) => (),
+                Err(error) => eprintln!("{error}"),
+            }
+        }
+        Self::determine_and_execute_mode(backend, rules, trigger, options, feedback).await
+    }
+
+    /// Apply `rules` (or a saved layout from `layouts`, see [`Self::load_or_execute_mode`]) now
+    /// and whenever the monitors change, reconnecting through `connect_backend` whenever the
+    /// backend goes away. Rule sets from `reloads` replace `rules` and are applied right away;
+    /// layouts from `reverts` are restored as they are.
+    pub async fn watch_and_execute(
+        connect_backend: impl AsyncFn() -> Result<Box<dyn DisplayBackend>>,
+        rules: &[DisplayRule],
+        layouts: Option<&Path>,
+        mut reloads: BoxStream<'_, Vec<DisplayRule>>,
+        mut reverts: BoxStream<'_, Vec<ApplyLogicalMonitorTuple>>,
+        options: ApplyOptions,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        let mut rules = rules.to_vec();
         let mut attempt = 0;
         'outer: loop {
             attempt += 1;
@@ -325,26 +559,17 @@ impl CurrentState {
             }
             eprintln!("Watch attempt: {attempt}");
 
-            // let current_state = Self::current(10).await?;
-
-            let connection = match connect(10).await {
-                Ok(connection) => connection,
-                Err(error) => {
-                    eprintln!("Watch attempt {attempt}: Failed to connect to DBus: {error}");
-                    continue;
-                }
-            };
-
-            let proxy = match DisplayConfigProxy::new(&connection).await {
-                Ok(proxy) => proxy,
+            let backend = match connect_backend().await {
+                Ok(backend) => backend,
                 Err(error) => {
-                    eprintln!("Failed to connect to proxy: {error}");
+                    eprintln!("Watch attempt {attempt}: Failed to connect to backend: {error}");
                     continue;
                 }
             };
+            let backend = backend.as_ref();
 
             // Create a stream to receive the MonitorsChanged signal
-            let mut stream = match proxy.receive_monitors_changed().await {
+            let mut stream = match backend.receive_monitors_changed().await {
                 Ok(stream) => stream,
                 Err(error) => {
                     eprintln!("Failed to get monitor stream: {error}");
@@ -353,8 +578,18 @@ impl CurrentState {
             };
 
             // Execute the selected mode
-            match Self::determine_and_execute_mode(rules, attempt, dry_run).await {
+            match Self::load_or_execute_mode(
+                backend,
+                &rules,
+                layouts,
+                Trigger::Watch,
+                options,
+                feedback,
+            )
+            .await
+            {
                 Ok(_) => (),
+                Err(error @ Error::NotConfirmed(_)) => eprintln!("{error}"),
                 Err(Error::ZBus(error)) => {
                     eprintln!("ZBus error: {error}, retrying...");
                     continue 'outer;
@@ -368,24 +603,60 @@ impl CurrentState {
 
             println!("{}", WATCHING);
 
-            let mut monitors = Self::current(10).await?.monitors.clone();
+            let mut monitors = Self::current(backend, 10).await?.monitors.clone();
 
-            // Poll for signal events
-            while (stream.next().await).is_some() {
-                // Get the updated state
-                let updated_state: CurrentState = proxy.get_current_state().await?.into();
+            // Poll for signal events and configuration reloads
+            loop {
+                let trigger = tokio::select! {
+                    changed = stream.next() => {
+                        if changed.is_none() {
+                            break;
+                        }
 
-                if updated_state.monitors == monitors {
-                    continue;
-                }
+                        // Get the updated state
+                        let updated_state = backend.get_current_state().await?;
+
+                        if updated_state.monitors == monitors {
+                            continue;
+                        }
+
+                        println!("Monitor configuration changed!");
 
-                println!("Monitor configuration changed!");
+                        monitors = updated_state.monitors.clone();
+                        Trigger::MonitorsChanged
+                    }
+                    Some(reloaded) = reloads.next() => {
+                        println!("Configuration reloaded, applying the new rules...");
+                        rules = reloaded;
+                        Trigger::ConfigReloaded
+                    }
+                    Some(layout) = reverts.next() => {
+                        println!("Reverting to the previous layout...");
+                        let current = Self::current(backend, 10).await?;
+                        match backend.apply_monitors_config(current.serial, options.method(), &layout).await {
+                            Ok(()) => {
+                                let revert = Change { trigger: Trigger::Revert, rule: None };
+                                feedback.record(revert, &current.layout(), &layout);
+                            }
+                            Err(error) => eprintln!("Failed to revert the layout: {error}"),
+                        }
 
-                monitors = updated_state.monitors.clone();
+                        // Keep the reverted layout until the monitors or rules change again
+                        monitors = Self::current(backend, 10).await?.monitors;
+                        println!("{}", WATCHING);
+                        continue;
+                    }
+                };
 
                 // Execute the selected mode
-                match Self::determine_and_execute_mode(rules, attempt, dry_run).await {
+                match Self::load_or_execute_mode(
+                    backend, &rules, layouts, trigger, options, feedback,
+                )
+                .await
+                {
                     Ok(_) => (),
+                    // Keep the restored layout until the monitors or rules change again
+                    Err(error @ Error::NotConfirmed(_)) => eprintln!("{error}"),
                     Err(error) => {
                         eprintln!("Failed to apply CHANGED display configuration: {error}");
                         eprintln!("Restarting outer loop...");
@@ -393,156 +664,248 @@ impl CurrentState {
                     }
                 }
 
+                // Don't react to the change we just made
+                monitors = Self::current(backend, 10).await?.monitors;
+
                 println!("{}", WATCHING);
             }
         }
     }
 
-    async fn determine_mode(rules: &[DisplayRule]) -> Result<DisplayMode> {
-        let state = Self::current(10).await?;
+    async fn determine_rule(
+        backend: &dyn DisplayBackend,
+        rules: &[DisplayRule],
+    ) -> Result<DisplayRule> {
+        let state = Self::current(backend, 10).await?;
+        let lid_closed = Self::lid_closed(rules).await;
+        state.choose_rule(rules, lid_closed)
+    }
 
-        if state.monitors.is_empty() {
-            return Err(Error::NoMonitorsAvailable(DisplayMode::Internal));
-        }
+    /// Print how each of `rules` matches the connected monitors and which mode they pick
+    pub async fn explain_rules(backend: &dyn DisplayBackend, rules: &[DisplayRule]) -> Result<()> {
+        let state = Self::current(backend, 10).await?;
+        let lid_closed = Self::lid_closed(rules).await;
 
-        // Check each external monitor against the rules
-        let (internal_monitors, external_monitors): (Vec<_>, Vec<_>) =
-            state.monitors.iter().partition(|m| m.is_builtin);
+        println!("=== Rule Explanation ===");
+        println!("Backend: {} ({})\n", backend.name(), backend.capabilities());
+        print!("{}", state.explain(rules, lid_closed));
+        Ok(())
+    }
 
-        // Default to external if no rules provided and monitors are available
-        if rules.is_empty() {
-            if external_monitors.is_empty() {
-                return Ok(DisplayMode::Internal);
+    /// Per-rule, per-monitor and per-field account of why each rule does or doesn't match
+    pub fn explain(&self, rules: &[DisplayRule], lid_closed: bool) -> String {
+        let mut out = String::new();
+        let context = RuleContext {
+            monitors: &self.monitors,
+            lid_closed,
+        };
+
+        out += "Monitors:\n";
+        for (i, monitor) in self.monitors.iter().enumerate() {
+            let kind = if monitor.is_builtin {
+                "internal"
             } else {
-                return Ok(DisplayMode::External);
-            }
+                "external"
+            };
+            out += &format!(
+                "  {}. {}: {} ({kind})\n",
+                i + 1,
+                monitor.connector_info.connector,
+                monitor.display_name
+            );
+        }
+        if rules.iter().any(DisplayRule::uses_lid) {
+            out += &format!("Lid: {}\n", if lid_closed { "closed" } else { "open" });
         }
 
-        // For single-rule commands (External, Internal, etc.), handle appropriately
-        if rules.len() == 1 {
-            let rule = &rules[0];
-
-            // Check if we need to match against external or internal monitors based on the mode
-            let monitors_to_check = match rule.mode {
-                DisplayMode::External => &external_monitors,
-                DisplayMode::Internal => &internal_monitors,
-                // For modes requiring both types, check all monitors
-                _ => &state.monitors.iter().collect::<Vec<_>>(),
-            };
+        let mut first_match = None;
+        for (i, rule) in rules.iter().enumerate() {
+            out += &format!("\nRule {}: {rule}\n", i + 1);
 
-            if monitors_to_check.is_empty() {
-                return Err(Error::NoMonitorsAvailable(rule.mode));
+            if !rule.pattern.is_empty() {
+                // The mode decides which monitors the pattern is checked against
+                let checked = rule.candidates(&self.monitors);
+                let mut rows = vec![[
+                    "Monitor".to_string(),
+                    "Field".to_string(),
+                    "Pattern".to_string(),
+                    "Value".to_string(),
+                    "Match".to_string(),
+                ]];
+                for monitor in checked {
+                    for field in rule.pattern.field_matches(monitor) {
+                        rows.push([
+                            monitor.connector_info.connector.clone(),
+                            field.field.to_string(),
+                            field.matcher.with_operator(),
+                            format!("{:?}", field.value),
+                            if field.matched { "✓" } else { "✗" }.to_string(),
+                        ]);
+                    }
+                }
+                out += &table(&rows);
             }
 
-            // If pattern is not empty, check if any monitor matches the pattern
-            if !rule.pattern.is_empty() {
-                let has_match = monitors_to_check
-                    .iter()
-                    .any(|monitor| rule.pattern.matches(monitor));
+            if let Some(condition) = &rule.condition {
+                let holds = condition.evaluate(&context);
+                out += &format!(
+                    "  Condition {condition}: {}\n",
+                    if holds { "holds" } else { "does not hold" }
+                );
+            }
 
-                if !has_match {
-                    return Err(Error::NoMonitorsMatch(rules.to_vec()));
+            out += &match (
+                self.check_rule(rule, &context, rules.len() == 1),
+                first_match,
+            ) {
+                (Ok(()), None) => {
+                    first_match = Some(i + 1);
+                    let used: Vec<&str> = rule
+                        .participants(&self.monitors)
+                        .iter()
+                        .map(|monitor| monitor.connector_info.connector.as_str())
+                        .collect();
+                    format!("  ✓ Matches\n  Monitors used: {}\n", used.join(", "))
                 }
+                (Ok(()), Some(first)) => format!("  ✓ Would match, but rule {first} comes first\n"),
+                (Err(miss), _) => format!("  ✗ Does not match: {miss}\n"),
+            };
+        }
+
+        out += &match self.choose_rule(rules, lid_closed) {
+            Ok(chosen) => match first_match {
+                Some(rule) => format!("\nResult: {} (rule {rule})\n", chosen.mode),
+                None => format!("\nResult: {}\n", chosen.mode),
+            },
+            Err(Error::NoMonitorsMatch(_)) => {
+                "\nResult: no rule matches, the configuration would not change\n".to_string()
             }
+            Err(error) => format!("\nResult: {error}\n"),
+        };
+        out
+    }
 
-            // For modes requiring both monitor types, make sure both exist
-            match rule.mode {
-                DisplayMode::Join | DisplayMode::Mirror => {
-                    let len = state.monitors.len();
-                    if len < 2 {
-                        return Err(Error::InsufficientMonitorsAvailable {
-                            available: len,
-                            required: 2,
-                            mode: rule.mode,
-                        });
-                    }
-                }
-                _ => {}
+    /// Whether the lid is closed; only asks UPower when a rule needs it
+    async fn lid_closed(rules: &[DisplayRule]) -> bool {
+        if !rules.iter().any(DisplayRule::uses_lid) {
+            return false;
+        }
+        crate::detection::lid_is_closed()
+            .await
+            .unwrap_or_else(|error| {
+                eprintln!("Failed to read the lid state, assuming it is open: {error}");
+                false
+            })
+    }
+
+    /// The first of `rules` that matches the connected monitors
+    fn choose_rule(&self, rules: &[DisplayRule], lid_closed: bool) -> Result<DisplayRule> {
+        if self.monitors.is_empty() {
+            return Err(Error::NoMonitorsAvailable(DisplayMode::Internal));
+        }
+
+        // Default to external if no rules provided and monitors are available
+        if rules.is_empty() {
+            if self.monitors.iter().all(|monitor| monitor.is_builtin) {
+                return Ok(DisplayRule::always(DisplayMode::Internal));
+            } else {
+                return Ok(DisplayRule::always(DisplayMode::External));
             }
+        }
 
-            return Ok(rule.mode);
+        let context = RuleContext {
+            monitors: &self.monitors,
+            lid_closed,
+        };
+
+        // For single-rule commands (External, Internal, etc.), report why the rule doesn't apply
+        if let [rule] = rules {
+            return match self.check_rule(rule, &context, true) {
+                Ok(()) => Ok(rule.clone()),
+                Err(RuleMiss::NoMonitors(mode)) => Err(Error::NoMonitorsAvailable(mode)),
+                Err(
+                    RuleMiss::TooFewMonitors { available }
+                    | RuleMiss::NeedsTwoExternals { available },
+                ) => Err(Error::InsufficientMonitorsAvailable {
+                    available,
+                    required: 2,
+                    mode: rule.mode,
+                }),
+                Err(_) => Err(Error::NoMonitorsMatch(rules.to_vec())),
+            };
         }
 
         // For multi-rule commands (Auto), go through rules in order
-        for rule in rules {
-            // For non-empty patterns, ensure we have a matching monitor
-            if !rule.pattern.is_empty() {
-                // For modes requiring specific monitor types, check appropriate collection
-                let monitors_to_check = match rule.mode {
-                    DisplayMode::External => &external_monitors,
-                    DisplayMode::Internal => &internal_monitors,
-                    _ => &state.monitors.iter().collect::<Vec<_>>(),
-                };
+        rules
+            .iter()
+            .find(|rule| self.check_rule(rule, &context, false).is_ok())
+            .cloned()
+            .ok_or_else(|| Error::NoMonitorsMatch(rules.to_vec()))
+    }
 
-                if monitors_to_check.is_empty() {
-                    // Skip this rule - no monitors of required type available
-                    continue;
-                }
+    /// Whether `rule` applies to the connected monitors, or why not. A `single` rule only needs
+    /// two monitors of any kind for Join and Mirror; among several rules, those modes need both
+    /// an internal and an external monitor. Either way, rules for external monitors only need
+    /// two of them.
+    fn check_rule(
+        &self,
+        rule: &DisplayRule,
+        context: &RuleContext<'_>,
+        single: bool,
+    ) -> std::result::Result<(), RuleMiss> {
+        // Modes requiring both types only need some monitor here; the participants are checked below
+        let available = match rule.mode {
+            DisplayMode::External | DisplayMode::Internal => {
+                rule.candidates(&self.monitors).next().is_some()
+            }
+            DisplayMode::Join | DisplayMode::Mirror => !self.monitors.is_empty(),
+        };
+        if !available {
+            return Err(RuleMiss::NoMonitors(rule.mode));
+        }
 
-                let has_match = monitors_to_check
-                    .iter()
-                    .any(|monitor| rule.pattern.matches(monitor));
-
-                if has_match {
-                    // For modes requiring both monitor types, ensure both exist
-                    match rule.mode {
-                        DisplayMode::Join | DisplayMode::Mirror => {
-                            if external_monitors.is_empty() || internal_monitors.is_empty() {
-                                // Skip this rule - can't use join/mirror without both types
-                                continue;
-                            }
-                        }
-                        _ => {}
-                    }
+        // If pattern is not empty, check if any monitor it applies to matches it
+        if !rule.pattern.is_empty()
+            && !rule
+                .candidates(&self.monitors)
+                .any(|monitor| rule.pattern.matches(monitor))
+        {
+            return Err(RuleMiss::NoPatternMatch);
+        }
 
-                    return Ok(rule.mode);
-                }
+        if !rule.condition_holds(context) {
+            return Err(RuleMiss::ConditionFailed);
+        }
 
-                // Continue to the next rule if no match
-                continue;
+        // For modes requiring both monitor types, make sure both take part, or at least two
+        // external monitors without the internal ones
+        if matches!(rule.mode, DisplayMode::Join | DisplayMode::Mirror) {
+            let participants = rule.participants(&self.monitors);
+            let both = participants.iter().any(|monitor| monitor.is_builtin)
+                && participants.iter().any(|monitor| !monitor.is_builtin);
+            if rule.externals_only && participants.len() < 2 {
+                return Err(RuleMiss::NeedsTwoExternals {
+                    available: participants.len(),
+                });
             }
-
-            // For empty patterns (default rules), check if the mode is valid with current monitors
-            match rule.mode {
-                DisplayMode::External => {
-                    if external_monitors.is_empty() {
-                        // Skip this rule - we can't use external mode without external monitors
-                        continue;
-                    }
-                    return Ok(DisplayMode::External);
-                }
-                DisplayMode::Internal => {
-                    if !internal_monitors.is_empty() {
-                        return Ok(DisplayMode::Internal);
-                    }
-                    // Skip if no internal monitor
-                    continue;
-                }
-                DisplayMode::Join => {
-                    if external_monitors.is_empty() || internal_monitors.is_empty() {
-                        // Need both internal and external for join mode
-                        continue;
-                    }
-                    return Ok(DisplayMode::Join);
-                }
-                DisplayMode::Mirror => {
-                    if external_monitors.is_empty() || internal_monitors.is_empty() {
-                        // Need both internal and external for mirror mode
-                        continue;
-                    }
-                    return Ok(DisplayMode::Mirror);
-                }
+            if single && participants.len() < 2 {
+                return Err(RuleMiss::TooFewMonitors {
+                    available: participants.len(),
+                });
+            }
+            if !single && !rule.externals_only && !both {
+                return Err(RuleMiss::NeedsInternalAndExternal);
             }
         }
 
-        // No rules matched
-        Err(Error::NoMonitorsMatch(rules.to_vec()))
+        Ok(())
     }
 
     /// Verify if the applied configuration matches what we intended to apply
     pub fn verify_applied_config(
         &self,
         intended_logical_monitors: &[ApplyLogicalMonitorTuple],
+        capabilities: Capabilities,
     ) -> Result<bool> {
         // If count doesn't match, configuration definitely didn't apply correctly
         if self.logical_monitors.len() != intended_logical_monitors.len() {
@@ -567,12 +930,12 @@ impl CurrentState {
 
             // Try to find a matching logical monitor in the current configuration
             let found_match = self.logical_monitors.iter().any(|current| {
-                // Check position, scale, transform, and primary status
+                // Check position, scale, transform, and primary status where there is one
                 if current.x != *intended_x
                     || current.y != *intended_y
                     || (current.scale - *intended_scale).abs() > 0.001
                     || current.transform != *intended_transform
-                    || current.primary != *intended_primary
+                    || (capabilities.primary && current.primary != *intended_primary)
                 {
                     return false;
                 }
@@ -632,6 +995,70 @@ impl CurrentState {
     }
 }
 
+/// Rows of cells, left-aligned in columns and indented
+fn table(rows: &[[String; 5]]) -> String {
+    let widths: Vec<usize> = (0..5)
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            format!("  {}\n", cells.join("  ").trim_end())
+        })
+        .collect()
+}
+
+/// Why a rule does not apply to the connected monitors
+#[derive(Debug, Clone, Copy)]
+enum RuleMiss {
+    /// No monitor of the kind the mode uses
+    NoMonitors(DisplayMode),
+    NoPatternMatch,
+    ConditionFailed,
+    TooFewMonitors {
+        available: usize,
+    },
+    NeedsInternalAndExternal,
+    NeedsTwoExternals {
+        available: usize,
+    },
+}
+
+impl fmt::Display for RuleMiss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoMonitors(DisplayMode::External) => {
+                write!(f, "no external monitor is connected")
+            }
+            Self::NoMonitors(DisplayMode::Internal) => {
+                write!(f, "no internal monitor is connected")
+            }
+            Self::NoMonitors(_) => write!(f, "no monitor is connected"),
+            Self::NoPatternMatch => write!(f, "no monitor matches the pattern"),
+            Self::ConditionFailed => write!(f, "the condition does not hold"),
+            Self::TooFewMonitors { available } => {
+                write!(f, "needs 2 monitors, {available} connected")
+            }
+            Self::NeedsInternalAndExternal => {
+                write!(f, "needs both an internal and an external monitor")
+            }
+            Self::NeedsTwoExternals { available } => {
+                write!(f, "needs 2 external monitors, {available} taking part")
+            }
+        }
+    }
+}
+
 pub type CurrentStateTuple = (
     u32,
     Vec<(
@@ -674,54 +1101,81 @@ impl From<CurrentStateTuple> for CurrentState {
     }
 }
 
-fn build_joined_or_individual(
+pub fn build_joined_or_individual(
     monitors_to_use: Vec<&Monitor>,
     mode: &DisplayMode,
+    settings: &[MonitorSettings],
+    primary: Option<&MonitorPattern>,
 ) -> Result<Vec<ApplyLogicalMonitorTuple>> {
-    // For join mode (side by side), use previous logic with scaling fix
-    let mut current_x = 0;
-
-    let mut logical_monitors = vec![];
-
-    for (i, monitor) in monitors_to_use.iter().enumerate() {
-        // Find best mode for monitor
-        let Some(mode) = monitor
-            .modes
-            .iter()
-            .find(|m| m.is_preferred)
-            .or_else(|| monitor.modes.first())
-        else {
+    // Preferred mode and scale, unless the rule asks for others
+    let mut chosen = vec![];
+    for monitor in &monitors_to_use {
+        if monitor.modes.is_empty() {
             continue;
-        };
-
-        // Create monitor assignment tuple with the expected format
-
-        let monitor_assignment = (
-            monitor.connector_info.connector.clone(), // connector
-            mode.id.clone(),                          // mode_id
-            HashMap::<String, OwnedValue>::new(),     // properties
-        );
-
-        // Calculate logical width considering the scale factor
-        let logical_width = (mode.width as f64 / mode.preferred_scale).round() as i32;
-        // Create logical monitor config
-
-        let logical_monitor = (
-            current_x,                // x
-            0,                        // y
-            mode.preferred_scale,     // scale
-            0u32,                     // transform (0 = normal)
-            i == 0,                   // primary (first monitor is primary)
-            vec![monitor_assignment], // monitors (without properties for logical monitor)
-        );
+        }
+        let settings = MonitorSettings::for_monitor(settings, monitor);
+        let mode = settings.choose_mode(monitor)?;
+        let scale = settings.choose_scale(monitor, mode)?;
+        chosen.push((*monitor, settings, mode, scale));
+    }
 
-        // Add to configurations
+    // The monitor asked for, or else the largest external one, or else the first
+    let primary = primary
+        .and_then(|pattern| {
+            chosen
+                .iter()
+                .position(|(monitor, ..)| pattern.matches(monitor))
+        })
+        .or_else(|| {
+            chosen
+                .iter()
+                .enumerate()
+                .filter(|(_, (monitor, ..))| !monitor.is_builtin)
+                .rev()
+                .max_by_key(|(_, (_, _, mode, _))| mode.width * mode.height)
+                .map(|(i, _)| i)
+        })
+        .unwrap_or_default();
 
-        logical_monitors.push(logical_monitor);
+    // Side by side from the left, unless placed relative to another monitor, with sizes in
+    // logical pixels considering the scale factor and rotation
+    let tiles: Vec<Tile> = chosen
+        .iter()
+        .map(|(monitor, settings, mode, scale)| {
+            let (width, height) = settings.transform.unwrap_or_default().apply(
+                (mode.width as f64 / scale).round() as i32,
+                (mode.height as f64 / scale).round() as i32,
+            );
+            Tile {
+                monitor,
+                width,
+                height,
+                placement: settings.position.as_ref(),
+            }
+        })
+        .collect();
+    let positions = arrange(&tiles)?;
 
-        // Update position for next monitor using logical width
-        current_x += logical_width;
-    }
+    let logical_monitors: Vec<ApplyLogicalMonitorTuple> = chosen
+        .iter()
+        .zip(positions)
+        .enumerate()
+        .map(|(i, ((monitor, settings, mode, scale), (x, y)))| {
+            let monitor_assignment = (
+                monitor.connector_info.connector.clone(), // connector
+                mode.id.clone(),                          // mode_id
+                HashMap::<String, OwnedValue>::new(),     // properties
+            );
+            (
+                x,                                             // x
+                y,                                             // y
+                *scale,                                        // scale
+                settings.transform.unwrap_or_default().into(), // transform
+                i == primary,                                  // primary
+                vec![monitor_assignment], // monitors (without properties for logical monitor)
+            )
+        })
+        .collect();
 
     if logical_monitors.is_empty() {
         return Err(Error::NoMonitorsAvailable(*mode));
@@ -730,7 +1184,10 @@ fn build_joined_or_individual(
     Ok(logical_monitors)
 }
 
-fn build_mirrored(monitors_to_use: Vec<&Monitor>) -> Result<Vec<ApplyLogicalMonitorTuple>> {
+pub fn build_mirrored(
+    monitors_to_use: Vec<&Monitor>,
+    settings: &[MonitorSettings],
+) -> Result<Vec<ApplyLogicalMonitorTuple>> {
     // For mirror mode, create a single logical monitor with all physical monitors
 
     // Find a reference monitor - prefer external monitors as they typically have better resolution
@@ -739,80 +1196,105 @@ fn build_mirrored(monitors_to_use: Vec<&Monitor>) -> Result<Vec<ApplyLogicalMoni
         .find(|m| !m.is_builtin)
         .or_else(|| monitors_to_use.first())
         .ok_or(Error::NoMonitorsAvailable(DisplayMode::Mirror))?;
+    let settings: Vec<MonitorSettings> = monitors_to_use
+        .iter()
+        .map(|monitor| MonitorSettings::for_monitor(settings, monitor))
+        .collect();
 
-    // Collect all resolutions that every monitor supports
-    let mut common_resolutions: Vec<(i32, i32)> = Vec::new();
-
-    // Start with all resolutions from the reference monitor
-    for mode in &reference_monitor.modes {
-        let resolution = (mode.width, mode.height);
-
-        // Check if all monitors support this resolution
-        let all_support = monitors_to_use.iter().all(|monitor| {
-            monitor
-                .modes
-                .iter()
-                .any(|m| m.width == resolution.0 && m.height == resolution.1)
-        });
-
-        if all_support {
-            common_resolutions.push(resolution);
+    // A resolution asked for takes the place of the highest common one
+    let (common_width, common_height) = match settings.iter().find_map(|s| s.mode) {
+        Some(requested) => {
+            println!("Using the requested resolution for mirroring: {requested}");
+            (requested.width, requested.height)
         }
-    }
+        None => {
+            // Collect all resolutions that every monitor supports
+            let mut common_resolutions: Vec<(i32, i32)> = Vec::new();
+
+            // Start with all resolutions from the reference monitor
+            for mode in &reference_monitor.modes {
+                let resolution = (mode.width, mode.height);
+
+                // Check if all monitors support this resolution
+                let all_support = monitors_to_use.iter().all(|monitor| {
+                    monitor
+                        .modes
+                        .iter()
+                        .any(|m| m.width == resolution.0 && m.height == resolution.1)
+                });
 
-    // Sort resolutions by total pixels (highest resolution first)
-    common_resolutions.sort_by(|a, b| {
-        let a_pixels = a.0 * a.1;
-        let b_pixels = b.0 * b.1;
-        b_pixels.cmp(&a_pixels) // Descending order
-    });
+                if all_support {
+                    common_resolutions.push(resolution);
+                }
+            }
 
-    // If no common resolutions found, we can't mirror
-    if common_resolutions.is_empty() {
-        return Err(Error::NoCommonResolutionsAvailable);
-    }
+            // Sort resolutions by total pixels (highest resolution first)
+            common_resolutions.sort_by(|a, b| {
+                let a_pixels = a.0 * a.1;
+                let b_pixels = b.0 * b.1;
+                b_pixels.cmp(&a_pixels) // Descending order
+            });
 
-    // Use the highest resolution that all monitors support
-    let (common_width, common_height) = common_resolutions[0];
+            // If no common resolutions found, we can't mirror
+            if common_resolutions.is_empty() {
+                return Err(Error::NoCommonResolutionsAvailable);
+            }
 
-    println!(
-        "Using highest common resolution for mirroring: {}x{}",
-        common_width, common_height
-    );
+            // Use the highest resolution that all monitors support
+            let (common_width, common_height) = common_resolutions[0];
+            println!(
+                "Using highest common resolution for mirroring: {}x{}",
+                common_width, common_height
+            );
+            (common_width, common_height)
+        }
+    };
 
     // Create monitor assignments for all monitors with the same resolution
     let assigned_monitors = monitors_to_use
         .iter()
-        .map(|monitor| {
-            // Find the mode with matching resolution
+        .zip(&settings)
+        .map(|(monitor, settings)| {
             // If multiple modes with same resolution exist (different refresh rates),
-            // prefer the one with highest refresh rate
-            let mode = monitor
-                .modes
-                .iter()
-                .filter(|m| m.width == common_width && m.height == common_height)
-                .max_by(|a, b| {
-                    a.refresh_rate
-                        .partial_cmp(&b.refresh_rate)
-                        .unwrap_or(std::cmp::Ordering::Equal)
-                })
-                .expect("Monitor should have the common resolution mode");
+            // prefer the one with highest refresh rate unless another one is asked for
+            let common = MonitorSettings {
+                mode: Some(ModeSpec {
+                    width: common_width,
+                    height: common_height,
+                    refresh: settings
+                        .mode
+                        .filter(|mode| (mode.width, mode.height) == (common_width, common_height))
+                        .and_then(|mode| mode.refresh),
+                }),
+                refresh: settings.refresh.or(Some(Refresh::Max)),
+                ..settings.clone()
+            };
+            let mode = common.choose_mode(monitor)?;
 
-            (
+            Ok((
                 monitor.connector_info.connector.clone(), // connector
                 mode.id.clone(),                          // mode_id
                 HashMap::<String, OwnedValue>::new(),     // properties
-            )
+            ))
         })
-        .collect();
+        .collect::<Result<Vec<_>>>()?;
 
     // Get the scale from reference monitor's mode with this resolution - prefer 1.0 scale if possible
-    let scale = reference_monitor
+    let reference_mode = reference_monitor
         .modes
         .iter()
-        .find(|m| m.width == common_width && m.height == common_height)
-        .map(|m| m.preferred_scale.max(1.0))
-        .unwrap_or(1.0);
+        .find(|m| m.width == common_width && m.height == common_height);
+    let scale = match (settings.iter().find(|s| s.scale.is_some()), reference_mode) {
+        (Some(settings), Some(mode)) => settings.choose_scale(reference_monitor, mode)?,
+        (None, Some(mode)) => mode.preferred_scale.max(1.0),
+        (_, None) => 1.0,
+    };
+
+    // Mirrored monitors share one rotation
+    let transform = settings
+        .iter()
+        .find_map(|s| s.transform)
+        .unwrap_or_default();
 
     // Create a single logical monitor for all physical monitors
 
@@ -820,8 +1302,1006 @@ fn build_mirrored(monitors_to_use: Vec<&Monitor>) -> Result<Vec<ApplyLogicalMoni
         0,                 // x
         0,                 // y
         scale,             // scale
-        0u32,              // transform (0 = normal)
+        transform.into(),  // transform
         true,              // primary
         assigned_monitors, // all monitors assigned to same logical monitor
     )])
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{channel::mpsc, stream};
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::{
+        backend::fake_mutter::{FakeMonitor, FakeMutter},
+        cli::{Align, Placement, Relation, Selection},
+        feedback::{FakeSpeechDispatcher, SpeechDispatcher},
+        journal::Journal,
+    };
+
+    fn laptop() -> FakeMonitor {
+        FakeMonitor::builtin("eDP-1")
+    }
+
+    fn acer() -> FakeMonitor {
+        FakeMonitor::external("DP-1", "ACR", "ET430K")
+    }
+
+    fn tv() -> FakeMonitor {
+        FakeMonitor::external("HDMI-1", "GSM", "LG TV")
+            .with_modes(vec![(3840, 2160, 30.0), (1920, 1080, 60.0)])
+    }
+
+    fn rule(mode: DisplayMode, pattern: &str) -> DisplayRule {
+        match pattern {
+            "" => DisplayRule::always(mode),
+            pattern => DisplayRule::parse(mode, pattern).unwrap(),
+        }
+    }
+
+    /// Connectors of each logical monitor the fake compositor currently shows
+    fn connectors(fake: &FakeMutter) -> Vec<Vec<String>> {
+        fake.state()
+            .logical_monitors
+            .iter()
+            .map(|(_, _, _, _, _, assigned)| {
+                assigned
+                    .iter()
+                    .map(|(connector, _, _)| connector.clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Wait until `count` configurations were applied and the watcher has settled down
+    async fn wait_for_applied(fake: &FakeMutter, count: usize) {
+        while fake.state().applied.len() < count {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    #[tokio::test]
+    async fn determine_mode_without_rules_prefers_external() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let mode = CurrentState::determine_rule(backend.as_ref(), &[])
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::External));
+
+        fake.set_monitors(vec![laptop()]).await;
+        let mode = CurrentState::determine_rule(backend.as_ref(), &[])
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::Internal));
+    }
+
+    #[tokio::test]
+    async fn determine_mode_follows_auto_rules_in_order() {
+        let rules = [
+            rule(DisplayMode::Mirror, "product=LG TV"),
+            rule(DisplayMode::External, "vendor=ACR"),
+            rule(DisplayMode::Internal, ""),
+        ];
+
+        let fake = FakeMutter::new(vec![laptop(), acer(), tv()]);
+        let backend = fake.connect().await.unwrap();
+        let mode = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::Mirror));
+
+        fake.set_monitors(vec![laptop(), acer()]).await;
+        let mode = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::External));
+
+        fake.set_monitors(vec![laptop()]).await;
+        let mode = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::Internal));
+    }
+
+    #[tokio::test]
+    async fn external_monitors_are_joined_without_the_internal_one() {
+        let dell = FakeMonitor::external("DP-10", "DEL", "U2720Q");
+        let externals = DisplayRule {
+            externals_only: true,
+            ..DisplayRule::always(DisplayMode::Join)
+        };
+        let rules = [externals.clone(), rule(DisplayMode::Internal, "")];
+        let fake = FakeMutter::new(vec![laptop(), dell, acer()]);
+        let backend = fake.connect().await.unwrap();
+        let enable = async |rule: &DisplayRule| {
+            CurrentState::enable_monitors(
+                backend.as_ref(),
+                rule,
+                Trigger::Command,
+                ApplyOptions::default(),
+                &Feedback::default(),
+            )
+            .await
+            .unwrap();
+            connectors(&fake)
+        };
+
+        // By connector name, DP-1 before DP-10, and the laptop panel off
+        let chosen = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap();
+        assert!(chosen.externals_only);
+        assert_eq!(enable(&chosen).await, [["DP-1"], ["DP-10"]]);
+        assert_eq!(fake.state().logical_monitors[1].0, 3840);
+
+        // By pattern
+        let ordered = DisplayRule {
+            order: vec!["product=U2720Q".parse().unwrap()],
+            ..externals
+        };
+        assert_eq!(enable(&ordered).await, [["DP-10"], ["DP-1"]]);
+
+        // A single external monitor is not enough
+        fake.set_monitors(vec![laptop(), acer()]).await;
+        let chosen = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap();
+        assert!(matches!(chosen.mode, DisplayMode::Internal));
+    }
+
+    #[tokio::test]
+    async fn determine_mode_checks_rule_conditions() {
+        let rules = [
+            DisplayRule::parse(DisplayMode::Join, "count(external) == 2").unwrap(),
+            DisplayRule::parse(DisplayMode::External, "all(vendor=ACR, not(product=LG TV))")
+                .unwrap(),
+            rule(DisplayMode::Internal, ""),
+        ];
+
+        let fake = FakeMutter::new(vec![laptop(), acer(), tv()]);
+        let backend = fake.connect().await.unwrap();
+        let mode = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::Join));
+
+        fake.set_monitors(vec![laptop(), acer()]).await;
+        let mode = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::External));
+
+        fake.set_monitors(vec![laptop(), tv()]).await;
+        let mode = CurrentState::determine_rule(backend.as_ref(), &rules)
+            .await
+            .unwrap()
+            .mode;
+        assert!(matches!(mode, DisplayMode::Internal));
+    }
+
+    #[tokio::test]
+    async fn explain_shows_why_each_rule_matches() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let state = CurrentState::current(backend.as_ref(), 1).await.unwrap();
+        let rules = [
+            rule(DisplayMode::Mirror, "product=LG TV"),
+            DisplayRule::parse(DisplayMode::Join, "count(external) >= 2").unwrap(),
+            rule(DisplayMode::External, "connector~^DP-"),
+            rule(DisplayMode::Internal, ""),
+        ];
+
+        let explanation = state.explain(&rules, false);
+        let lines: Vec<&str> = explanation.lines().map(str::trim_end).collect();
+        for expected in [
+            "  1. eDP-1: Built-in display (internal)",
+            "Rule 1: Mirror when product=LG TV",
+            "  Monitor  Field    Pattern  Value     Match",
+            "  DP-1     product  =LG TV   \"ET430K\"  ✗",
+            "  ✗ Does not match: no monitor matches the pattern",
+            "  Condition count(external) >= 2: does not hold",
+            "  ✗ Does not match: the condition does not hold",
+            "  DP-1     connector  ~^DP-    \"DP-1\"  ✓",
+            "  ✓ Matches",
+            "  Monitors used: DP-1",
+            "  ✓ Would match, but rule 3 comes first",
+            "Result: External (rule 3)",
+        ] {
+            assert!(lines.contains(&expected), "missing {expected:?}");
+        }
+
+        let explanation = state.explain(&rules[..1], false);
+        assert!(
+            explanation.ends_with("Result: no rule matches, the configuration would not change\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn join_patterns_are_matched_against_external_monitors_only() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let state = CurrentState::current(backend.as_ref(), 1).await.unwrap();
+        let rules = [rule(DisplayMode::Join, "connector=eDP-1")];
+
+        assert!(rules[0].participants(&state.monitors).len() < 2);
+        let result = CurrentState::determine_rule(backend.as_ref(), &rules).await;
+        assert!(matches!(result, Err(Error::NoMonitorsMatch(_))));
+
+        let explanation = state.explain(&rules, false);
+        assert!(!explanation.contains("eDP-1    connector"));
+        assert!(explanation.contains("✗ Does not match: no monitor matches the pattern"));
+    }
+
+    #[tokio::test]
+    async fn determine_mode_reports_unmatched_and_insufficient_monitors() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+
+        let result =
+            CurrentState::determine_rule(backend.as_ref(), &[rule(DisplayMode::External, "LG")])
+                .await;
+        assert!(matches!(result, Err(Error::NoMonitorsMatch(_))));
+
+        fake.set_monitors(vec![acer()]).await;
+        let result =
+            CurrentState::determine_rule(backend.as_ref(), &[rule(DisplayMode::Join, "")]).await;
+        assert!(matches!(
+            result,
+            Err(Error::InsufficientMonitorsAvailable {
+                available: 1,
+                required: 2,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_applies_each_mode() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::External),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(connectors(&fake), [["DP-1"]]);
+
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::Internal),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(connectors(&fake), [["eDP-1"]]);
+
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::Join),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(connectors(&fake), [["eDP-1"], ["DP-1"]]);
+        // The laptop panel is 2256 pixels wide at scale 1.5
+        assert_eq!(fake.state().logical_monitors[1].0, 1504);
+
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::Mirror),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(connectors(&fake), [["eDP-1", "DP-1"]]);
+        let state = fake.state();
+        let (_, _, _, _, _, assigned) = &state.logical_monitors[0];
+        assert!(
+            assigned
+                .iter()
+                .all(|(_, mode_id, _)| mode_id == "1920x1080@60.000")
+        );
+
+        assert_eq!(state.applied.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_turns_on_only_the_selected_monitors() {
+        let projector =
+            FakeMonitor::external("HDMI-2", "EPS", "Projector").with_modes(vec![(1280, 800, 60.0)]);
+        let fake = FakeMutter::new(vec![laptop(), projector, acer()]);
+        let backend = fake.connect().await.unwrap();
+        let enable = async |rule: DisplayRule| {
+            CurrentState::enable_monitors(
+                backend.as_ref(),
+                &rule,
+                Trigger::Command,
+                ApplyOptions::default(),
+                &Feedback::default(),
+            )
+            .await
+            .unwrap();
+            connectors(&fake)
+        };
+
+        assert_eq!(
+            enable(DisplayRule::always(DisplayMode::External)).await,
+            [["HDMI-2"], ["DP-1"]]
+        );
+        assert_eq!(
+            enable(rule(DisplayMode::External, "product=ET430K")).await,
+            [["DP-1"]]
+        );
+        let best = DisplayRule {
+            select: Selection::Best,
+            ..DisplayRule::always(DisplayMode::External)
+        };
+        assert_eq!(enable(best).await, [["DP-1"]]);
+        assert_eq!(
+            enable(rule(DisplayMode::Join, "product=Projector")).await,
+            [["eDP-1"], ["HDMI-2"]]
+        );
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_uses_requested_modes_and_scales() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let settings = |pattern: &str, mode: &str, scale| MonitorSettings {
+            pattern: pattern.parse().unwrap(),
+            mode: Some(mode.parse().unwrap()),
+            refresh: None,
+            scale,
+            transform: None,
+            position: None,
+        };
+
+        let rule = DisplayRule {
+            monitors: vec![
+                settings("connector=eDP-1", "2256x1504", Some(1.0)),
+                settings("product=ET430K", "1920x1080@60", None),
+            ],
+            ..DisplayRule::always(DisplayMode::Join)
+        };
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &rule,
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        let state = fake.state().logical_monitors.clone();
+        assert_eq!((state[0].2, state[1].0), (1.0, 2256));
+        assert_eq!(state[1].5[0].1, "1920x1080@60.000");
+
+        let rule = DisplayRule {
+            monitors: vec![settings("product=ET430K", "1280x720", None)],
+            ..DisplayRule::always(DisplayMode::External)
+        };
+        let result = CurrentState::enable_monitors(
+            backend.as_ref(),
+            &rule,
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(Error::ModeUnavailable {
+                closest: Some(_),
+                ..
+            })
+        ));
+        assert_eq!(fake.state().applied.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_places_monitors_relative_to_each_other() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+
+        let rule = DisplayRule {
+            monitors: vec![MonitorSettings {
+                pattern: "connector=eDP-1".parse().unwrap(),
+                position: Some(Placement {
+                    relation: Relation::Below,
+                    target: "product=ET430K".parse().unwrap(),
+                    align: Align::Center,
+                }),
+                ..Default::default()
+            }],
+            ..DisplayRule::always(DisplayMode::Join)
+        };
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &rule,
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        // The laptop is 1504x1003 logical pixels at scale 1.5, centered under 3840x2160
+        let state = fake.state().logical_monitors.clone();
+        assert_eq!((state[0].0, state[0].1), (1168, 2160));
+        assert_eq!((state[1].0, state[1].1), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn primary_monitor_is_chosen_by_pattern_or_size() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let primaries = |fake: &FakeMutter| -> Vec<bool> {
+            let state = fake.state();
+            state
+                .logical_monitors
+                .iter()
+                .map(|logical| logical.4)
+                .collect()
+        };
+
+        // The largest external monitor, although the laptop comes first
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::Join),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(primaries(&fake), [false, true]);
+
+        let rule = DisplayRule {
+            primary: Some("connector=eDP-1".parse().unwrap()),
+            ..DisplayRule::always(DisplayMode::Join)
+        };
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &rule,
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(primaries(&fake), [true, false]);
+
+        // Only the primary flag changes
+        let state = CurrentState::current(backend.as_ref(), 10).await.unwrap();
+        let layout = state
+            .with_primary(&"product=ET430K".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            layout.iter().map(|logical| logical.4).collect::<Vec<_>>(),
+            [false, true]
+        );
+        let mut unchanged = layout.clone();
+        unchanged
+            .iter_mut()
+            .for_each(|logical| logical.4 = !logical.4);
+        assert_eq!(unchanged, state.layout());
+        assert!(matches!(
+            state.with_primary(&"product=LG TV".parse().unwrap()),
+            Err(Error::NoMatchingMonitor(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rotating_a_monitor_moves_its_neighbours() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::Join),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+
+        // The laptop, 1504x1003 logical pixels, stands up as 1003x1504
+        let state = CurrentState::current(backend.as_ref(), 10).await.unwrap();
+        let pattern = "connector=eDP-1".parse().unwrap();
+        let layout = state.rotated(&pattern, Transform::Rotate90).unwrap();
+        assert_eq!((layout[0].0, layout[0].3), (0, 1));
+        assert_eq!((layout[1].0, layout[1].3), (1003, 0));
+        CurrentState::restore_layout(
+            backend.as_ref(),
+            &layout,
+            Trigger::Rotate,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fake.state().logical_monitors, layout);
+
+        let pattern = "product=LG TV".parse().unwrap();
+        assert!(matches!(
+            state.rotated(&pattern, Transform::Normal),
+            Err(Error::NoMatchingMonitor(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_dry_run_changes_nothing() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::External),
+            Trigger::Command,
+            ApplyOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert!(fake.state().applied.is_empty());
+        assert_eq!(fake.state().verified, 1);
+        assert_eq!(connectors(&fake), [["eDP-1"], ["DP-1"]]);
+    }
+
+    #[tokio::test]
+    async fn layouts_refused_by_the_compositor_are_not_applied() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        fake.state().reject = Some("Logical monitors not adjacent".to_string());
+
+        for dry_run in [true, false] {
+            let result = CurrentState::determine_and_execute_mode(
+                backend.as_ref(),
+                &[DisplayRule::always(DisplayMode::Join)],
+                Trigger::Command,
+                ApplyOptions {
+                    dry_run,
+                    ..Default::default()
+                },
+                &Feedback::default(),
+            )
+            .await;
+            let Err(Error::ConfigurationRejected {
+                backend: "Mutter",
+                error,
+                reason,
+            }) = result
+            else {
+                panic!("expected the layout to be refused, got {result:?}");
+            };
+            assert_eq!(error, "org.freedesktop.DBus.Error.InvalidArgs");
+            assert_eq!(reason, "Logical monitors not adjacent");
+        }
+        assert!(fake.state().applied.is_empty());
+        assert_eq!(connectors(&fake), [["eDP-1"], ["DP-1"]]);
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_restores_the_previous_layout_unless_confirmed() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let before = fake.state().logical_monitors.clone();
+
+        let result = CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::External),
+            Trigger::Command,
+            ApplyOptions {
+                confirm: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+            &Feedback::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::NotConfirmed(0))));
+
+        let state = fake.state();
+        assert_eq!(state.applied.len(), 2);
+        assert_eq!(state.applied[0].len(), 1);
+        assert_eq!(state.logical_monitors, before);
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_stores_persistent_layouts() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+
+        let persistent = ApplyOptions {
+            persistent: true,
+            ..Default::default()
+        };
+        let rule = DisplayRule::always(DisplayMode::External);
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &rule,
+            Trigger::Command,
+            persistent,
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fake.state().persisted, 1);
+
+        let rule = DisplayRule::always(DisplayMode::Internal);
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &rule,
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await
+        .unwrap();
+        let state = fake.state();
+        assert_eq!((state.applied.len(), state.persisted), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_speaks_changes_and_reverts() {
+        let directory = tempfile::tempdir().unwrap();
+        let socket = directory.path().join("speechd.sock");
+        let speechd = FakeSpeechDispatcher::listen(&socket);
+        let feedback = Feedback::default().with_speech(SpeechDispatcher::new(socket));
+
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::External),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &feedback,
+        )
+        .await
+        .unwrap();
+        let result = CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::Join),
+            Trigger::Command,
+            ApplyOptions {
+                confirm: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+            &feedback,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::NotConfirmed(0))));
+
+        assert_eq!(
+            speechd.spoken(),
+            [
+                "Switched to external: ACR ET430K, 3840 by 2160",
+                "Switched to join: Built-in display, 2256 by 1504 and ACR ET430K, 3840 by 2160",
+                "Keep this layout? Confirm within 0 seconds.",
+                "Not confirmed. The previous layout was restored.",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn applied_layouts_are_journaled_and_can_be_restored() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = Journal::new(directory.path().join("history.jsonl"));
+        let feedback = Feedback::default().with_journal(journal.clone());
+
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let before = fake.state().logical_monitors.clone();
+
+        CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::External),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &feedback,
+        )
+        .await
+        .unwrap();
+        let entry = journal.entry(1).unwrap();
+        assert_eq!(entry.trigger, Trigger::Command);
+        assert_eq!(entry.rule.as_deref(), Some("External (always matches)"));
+        assert_eq!(entry.applied_layout(), fake.state().logical_monitors);
+
+        // Undo
+        CurrentState::restore_layout(
+            backend.as_ref(),
+            &entry.previous_layout(),
+            Trigger::Undo,
+            ApplyOptions::default(),
+            &feedback,
+        )
+        .await
+        .unwrap();
+        assert_eq!(fake.state().logical_monitors, before);
+        assert_eq!(journal.entry(1).unwrap().trigger, Trigger::Undo);
+
+        // The external monitor is gone
+        fake.set_monitors(vec![laptop()]).await;
+        let result = CurrentState::restore_layout(
+            backend.as_ref(),
+            &entry.applied_layout(),
+            Trigger::History,
+            ApplyOptions::default(),
+            &feedback,
+        )
+        .await;
+        assert!(
+            matches!(result, Err(Error::MonitorNotConnected(connector)) if connector == "DP-1")
+        );
+        assert_eq!(journal.entries().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn enable_monitors_fails_verification_when_ignored() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        fake.state().ignore_apply = true;
+        let backend = fake.connect().await.unwrap();
+
+        let result = CurrentState::enable_monitors(
+            backend.as_ref(),
+            &DisplayRule::always(DisplayMode::External),
+            Trigger::Command,
+            ApplyOptions::default(),
+            &Feedback::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::FailedVerification("Mutter"))));
+        assert_eq!(fake.state().applied.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn verify_applied_config_compares_layout_and_modes() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+        let state = CurrentState::current(backend.as_ref(), 1).await.unwrap();
+        let current = fake.state().logical_monitors.clone();
+
+        assert!(
+            state
+                .verify_applied_config(&current, backend.capabilities())
+                .unwrap()
+        );
+
+        // Different position
+        let mut moved = current.clone();
+        moved[1].0 += 10;
+        assert!(
+            !state
+                .verify_applied_config(&moved, backend.capabilities())
+                .unwrap()
+        );
+
+        // Different mode
+        let mut remoded = current.clone();
+        remoded[1].5[0].1 = acer().mode_id(1);
+        assert!(
+            !state
+                .verify_applied_config(&remoded, backend.capabilities())
+                .unwrap()
+        );
+
+        // Missing logical monitor
+        assert!(
+            !state
+                .verify_applied_config(&current[..1], backend.capabilities())
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn watch_and_execute_reapplies_rules_on_hotplug() {
+        let fake = FakeMutter::new(vec![laptop()]);
+        let rules = [
+            rule(DisplayMode::External, "vendor=ACR"),
+            rule(DisplayMode::Internal, ""),
+        ];
+
+        let feedback = Feedback::default();
+        let watch = CurrentState::watch_and_execute(
+            async || fake.connect().await,
+            &rules,
+            None,
+            stream::pending().boxed(),
+            stream::pending().boxed(),
+            ApplyOptions::default(),
+            &feedback,
+        );
+
+        let script = async {
+            // Initial run: only the laptop is connected
+            wait_for_applied(&fake, 1).await;
+            assert_eq!(connectors(&fake), [["eDP-1"]]);
+
+            fake.set_monitors(vec![laptop(), acer()]).await;
+            wait_for_applied(&fake, 2).await;
+            assert_eq!(connectors(&fake), [["DP-1"]]);
+
+            fake.set_monitors(vec![laptop()]).await;
+            wait_for_applied(&fake, 3).await;
+            assert_eq!(connectors(&fake), [["eDP-1"]]);
+        };
+
+        timeout(Duration::from_secs(10), async {
+            tokio::select! {
+                result = watch => panic!("watch ended: {result:?}"),
+                _ = script => {}
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn watch_and_execute_loads_saved_layouts_by_fingerprint() {
+        let directory = tempfile::tempdir().unwrap();
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let backend = fake.connect().await.unwrap();
+
+        // The Acer on the left of the laptop, as hand-tuned in GNOME Settings
+        let state = CurrentState::current(backend.as_ref(), 1).await.unwrap();
+        let mut desk = SavedLayout::snapshot(&state);
+        desk.logical_monitors[0].x = 3840;
+        desk.logical_monitors[1].x = 0;
+        desk.save(directory.path(), "desk").unwrap();
+
+        let rules = [
+            rule(DisplayMode::External, "vendor=ACR"),
+            rule(DisplayMode::Internal, ""),
+        ];
+        let feedback = Feedback::default();
+        let watch = CurrentState::watch_and_execute(
+            async || fake.connect().await,
+            &rules,
+            Some(directory.path()),
+            stream::pending().boxed(),
+            stream::pending().boxed(),
+            ApplyOptions::default(),
+            &feedback,
+        );
+
+        let positions = || -> Vec<(String, i32)> {
+            fake.state()
+                .logical_monitors
+                .iter()
+                .map(|(x, _, _, _, _, assigned)| (assigned[0].0.clone(), *x))
+                .collect()
+        };
+        let script = async {
+            wait_for_applied(&fake, 1).await;
+            assert_eq!(positions(), [("eDP-1".into(), 3840), ("DP-1".into(), 0)]);
+
+            // No saved layout for the laptop alone: the rules apply
+            fake.set_monitors(vec![laptop()]).await;
+            wait_for_applied(&fake, 2).await;
+            assert_eq!(connectors(&fake), [["eDP-1"]]);
+
+            // The Acer is back, on another port
+            let moved = FakeMonitor::external("DP-3", "ACR", "ET430K");
+            fake.set_monitors(vec![laptop(), moved]).await;
+            wait_for_applied(&fake, 3).await;
+            assert_eq!(positions(), [("eDP-1".into(), 3840), ("DP-3".into(), 0)]);
+        };
+
+        timeout(Duration::from_secs(10), async {
+            tokio::select! {
+                result = watch => panic!("watch ended: {result:?}"),
+                _ = script => {}
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn watch_and_execute_applies_reloaded_rules() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let rules = [rule(DisplayMode::External, "")];
+        let (reload, reloads) = mpsc::unbounded();
+
+        let feedback = Feedback::default();
+        let watch = CurrentState::watch_and_execute(
+            async || fake.connect().await,
+            &rules,
+            None,
+            reloads.boxed(),
+            stream::pending().boxed(),
+            ApplyOptions::default(),
+            &feedback,
+        );
+
+        let script = async {
+            wait_for_applied(&fake, 1).await;
+            assert_eq!(connectors(&fake), [["DP-1"]]);
+
+            reload
+                .unbounded_send(vec![rule(DisplayMode::Join, "vendor=ACR")])
+                .unwrap();
+            wait_for_applied(&fake, 2).await;
+            assert_eq!(connectors(&fake), [["eDP-1"], ["DP-1"]]);
+
+            // Applying the reloaded rules must not trigger yet another run
+            assert_eq!(fake.state().applied.len(), 2);
+        };
+
+        timeout(Duration::from_secs(10), async {
+            tokio::select! {
+                result = watch => panic!("watch ended: {result:?}"),
+                _ = script => {}
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn watch_and_execute_restores_reverted_layouts() {
+        let fake = FakeMutter::new(vec![laptop(), acer()]);
+        let before = fake.state().logical_monitors.clone();
+        let rules = [rule(DisplayMode::External, "")];
+        let (revert, reverts) = mpsc::unbounded();
+
+        let feedback = Feedback::default();
+        let watch = CurrentState::watch_and_execute(
+            async || fake.connect().await,
+            &rules,
+            None,
+            stream::pending().boxed(),
+            reverts.boxed(),
+            ApplyOptions::default(),
+            &feedback,
+        );
+
+        let script = async {
+            wait_for_applied(&fake, 1).await;
+            assert_eq!(connectors(&fake), [["DP-1"]]);
+
+            revert.unbounded_send(before.clone()).unwrap();
+            wait_for_applied(&fake, 2).await;
+            assert_eq!(fake.state().logical_monitors, before);
+
+            // The reverted layout stays until the monitors change
+            sleep(Duration::from_millis(200)).await;
+            assert_eq!(fake.state().applied.len(), 2);
+        };
+
+        timeout(Duration::from_secs(10), async {
+            tokio::select! {
+                result = watch => panic!("watch ended: {result:?}"),
+                _ = script => {}
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
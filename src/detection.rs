@@ -1,26 +1,30 @@
 use std::{env, sync::Arc};
 
-use crate::{Error, Result};
-
-#[derive(Debug)]
-pub struct DbusConfig {
-    pub service: &'static str,
-    pub path: &'static str,
-    pub interface: &'static str,
-    pub method: &'static str,
-}
+use crate::{Result, generated::upower_proxy::UPowerProxy};
+
+/// Compositors implementing wlr-output-management
+const WLROOTS_DESKTOPS: [&str; 7] = [
+    "sway", "hyprland", "river", "labwc", "niri", "wayfire", "wlroots",
+];
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum DesktopEnvironment {
     Gnome,
     Cinnamon,
+    Kde,
+    Wlroots,
     Unknown(Arc<str>),
 }
 
 impl DesktopEnvironment {
     pub fn detect() -> Self {
-        let xdg_desktop = env::var("XDG_SESSION_DESKTOP")
-            .unwrap_or_default()
+        // Many wlroots compositors only set XDG_CURRENT_DESKTOP
+        let xdg_desktop = ["XDG_SESSION_DESKTOP", "XDG_CURRENT_DESKTOP"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>()
+            .join(":")
             .to_lowercase();
 
         if xdg_desktop.contains("cinnamon") {
@@ -31,24 +35,29 @@ impl DesktopEnvironment {
             return DesktopEnvironment::Gnome;
         }
 
-        DesktopEnvironment::Unknown(xdg_desktop.into())
-    }
+        if xdg_desktop.contains("kde") || xdg_desktop.contains("plasma") {
+            return DesktopEnvironment::Kde;
+        }
 
-    pub fn dbus_config(&self) -> Result<DbusConfig> {
-        match self {
-            Self::Gnome => Ok(DbusConfig {
-                service: "org.gnome.Mutter.DisplayConfig",
-                path: "/org/gnome/Mutter/DisplayConfig",
-                interface: "org.gnome.Mutter.DisplayConfig",
-                method: "ApplyMonitorsConfig",
-            }),
-            Self::Cinnamon => Ok(DbusConfig {
-                service: "org.cinnamon.Muffin.DisplayConfig",
-                path: "/org/cinnamon/Muffin/DisplayConfig",
-                interface: "org.cinnamon.Muffin.DisplayConfig",
-                method: "ApplyMonitorsConfig",
-            }),
-            Self::Unknown(desktop) => Err(Error::UnsupportedDesktop(desktop.clone())),
+        if WLROOTS_DESKTOPS
+            .iter()
+            .any(|desktop| xdg_desktop.contains(desktop))
+        {
+            return DesktopEnvironment::Wlroots;
         }
+
+        // Any other Wayland compositor may still implement wlr-output-management
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            return DesktopEnvironment::Wlroots;
+        }
+
+        DesktopEnvironment::Unknown(xdg_desktop.into())
     }
 }
+
+/// Whether the laptop lid is closed, according to UPower (`false` without a lid)
+pub async fn lid_is_closed() -> Result<bool> {
+    let connection = zbus::Connection::system().await?;
+    let upower = UPowerProxy::new(&connection).await?;
+    Ok(upower.lid_is_present().await? && upower.lid_is_closed().await?)
+}
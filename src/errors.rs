@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{io, path::PathBuf, sync::Arc};
 
 use thiserror::Error;
 
@@ -10,6 +10,84 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     #[error("Invalid pattern: {0}")]
     InvalidPattern(String),
+    #[error("Invalid monitor setting: {0}")]
+    InvalidMonitorSetting(String),
+    #[error("✗ Monitor {0} reports no modes")]
+    NoModes(String),
+    #[error("✗ {connector} does not support scale {scale} at {mode}; the closest are {closest}")]
+    ScaleNotSupported {
+        connector: String,
+        mode: String,
+        scale: f64,
+        closest: String,
+    },
+    #[error("✗ {connector} is placed next to {target}, which matches no other monitor")]
+    PlacementTargetMissing { connector: String, target: String },
+    #[error("✗ Monitors {0} are each placed next to another in a circle")]
+    PlacementCycle(String),
+    #[error("✗ {first} and {second} would overlap")]
+    LayoutOverlap { first: String, second: String },
+    #[error("✗ {0} would not touch any other monitor, and Mutter refuses gaps between monitors")]
+    LayoutGap(String),
+    #[error("✗ Failed to read configuration file {}: {error}", path.display())]
+    ConfigRead { path: PathBuf, error: io::Error },
+    #[error("✗ Failed to watch configuration file {}: {error}", path.display())]
+    ConfigWatch { path: PathBuf, error: io::Error },
+    #[error("✗ Invalid configuration {}, line {line}: {message}", path.display())]
+    InvalidConfig {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    #[error("✗ Failed to read the display history {}: {error}", path.display())]
+    JournalRead { path: PathBuf, error: io::Error },
+    #[error("✗ Failed to record the display change in {}: {error}", path.display())]
+    JournalWrite { path: PathBuf, error: io::Error },
+    #[error("✗ Invalid display history {}, line {line}: {message}", path.display())]
+    InvalidJournal {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    #[error("No entry {0} in the display history")]
+    NoHistoryEntry(usize),
+    #[error("No display change to undo")]
+    NothingToUndo,
+    #[error("✗ No connected monitor matches {0}")]
+    NoMatchingMonitor(String),
+    #[error("✗ Monitor {0} is not connected")]
+    MonitorNotConnected(String),
+    #[error("✗ Monitor {connector} has no mode {mode}{}", .closest.as_ref().map_or(String::new(), |closest| format!("; the closest are {closest}")))]
+    ModeUnavailable {
+        connector: String,
+        mode: String,
+        /// Modes of the monitor nearest to `mode`, when it was given as a size and rate
+        closest: Option<String>,
+    },
+    #[error("✗ Failed to read saved layout {}: {error}", path.display())]
+    LayoutRead { path: PathBuf, error: io::Error },
+    #[error("✗ Failed to save layout {}: {error}", path.display())]
+    LayoutWrite { path: PathBuf, error: io::Error },
+    #[error("✗ Invalid saved layout {}: {message}", path.display())]
+    InvalidLayout { path: PathBuf, message: String },
+    #[error("No saved layout named '{0}'")]
+    UnknownLayout(String),
+    #[error("Invalid layout name '{0}'")]
+    InvalidLayoutName(String),
+    #[error("✗ Failed to read {}: {error}", path.display())]
+    MonitorsXmlRead { path: PathBuf, error: io::Error },
+    #[error("✗ Failed to write {}: {error}", path.display())]
+    MonitorsXmlWrite { path: PathBuf, error: io::Error },
+    #[error("✗ Invalid monitors.xml {} (line {line}): {message}", path.display())]
+    InvalidMonitorsXml {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    #[error("No profile named '{0}' in the configuration file")]
+    UnknownProfile(String),
+    #[error("No profile given and the configuration file has no default_profile")]
+    NoDefaultProfile,
     #[error("Max attempts ({0}) reached, aborting.")]
     MaxAttempts(usize),
     #[error("No monitors available for display mode: {0}")]
@@ -26,12 +104,42 @@ pub enum Error {
     NoCommonResolutionsAvailable,
     #[error("No monitors match the provided rules: {0:#?}")]
     NoMonitorsMatch(Vec<DisplayRule>),
-    #[error("✗ Monitor configuration was attempted but failed verification. Reply message: {0:#?}")]
-    FailedVerification(zbus::Message),
+    #[error("✗ Monitor configuration was applied through {0} but failed verification.")]
+    FailedVerification(&'static str),
+    #[error(
+        "✗ The new layout was not confirmed within {0} seconds; the previous layout was restored."
+    )]
+    NotConfirmed(u64),
+    #[error("Speech dispatcher error: {0}")]
+    Speech(String),
+    #[error("No display change is waiting for confirmation")]
+    NothingToConfirm,
+    #[error("✗ {0} cancelled the monitor configuration, as the monitors changed meanwhile")]
+    ConfigurationCancelled(&'static str),
+    #[error("✗ {backend} refused the monitor configuration: {reason}")]
+    ConfigurationRejected {
+        backend: &'static str,
+        /// D-Bus error name, e.g. `org.freedesktop.DBus.Error.InvalidArgs`
+        error: String,
+        /// Explanation given by the compositor
+        reason: String,
+    },
     #[error("Unsupported desktop: {0}")]
     UnsupportedDesktop(Arc<str>),
+    #[error("Compositor does not support {0}")]
+    UnsupportedProtocol(&'static str),
+    #[error("Lost connection to the Wayland compositor")]
+    WaylandDisconnected,
     #[error("ZBus error: {0:#?}")]
     ZBus(#[from] zbus::Error),
     #[error("ZVariant error: {0:#?}")]
     ZVariant(#[from] zbus::zvariant::Error),
+    #[error("Wayland connection error: {0}")]
+    WaylandConnect(#[from] wayland_client::ConnectError),
+    #[error("Wayland registry error: {0}")]
+    WaylandGlobal(#[from] wayland_client::globals::GlobalError),
+    #[error("Wayland dispatch error: {0}")]
+    WaylandDispatch(#[from] wayland_client::DispatchError),
+    #[error("Wayland error: {0}")]
+    Wayland(#[from] wayland_client::backend::WaylandError),
 }
@@ -0,0 +1,138 @@
+mod notifications;
+mod speech;
+pub use notifications::{Notifications, RevertRequests, Urgency};
+pub use speech::SpeechDispatcher;
+#[cfg(test)]
+pub use speech::fake::FakeSpeechDispatcher;
+
+use std::time::Duration;
+
+use crate::{
+    ApplyLogicalMonitorTuple, Error, Monitor,
+    journal::{Change, Entry, Journal},
+};
+
+/// Announces display changes beyond the terminal, for users who can't see the screen that
+/// changed, and records them in the journal. Failing to announce or record is reported but
+/// never fails the change itself.
+#[derive(Debug, Clone, Default)]
+pub struct Feedback {
+    speech: Option<SpeechDispatcher>,
+    notifications: Option<Notifications>,
+    journal: Option<Journal>,
+}
+
+impl Feedback {
+    pub fn with_speech(mut self, speech: SpeechDispatcher) -> Self {
+        self.speech = Some(speech);
+        self
+    }
+
+    pub fn with_notifications(mut self, notifications: Notifications) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// A layout was applied, e.g. "Switched to external: Acer ET430K, 3840 by 2160". The
+    /// notification offers to revert to `previous`.
+    pub async fn applied(
+        &self,
+        change: Change<'_>,
+        logical_monitors: &[ApplyLogicalMonitorTuple],
+        monitors: &[Monitor],
+        previous: Vec<ApplyLogicalMonitorTuple>,
+    ) {
+        let described = logical_monitors
+            .iter()
+            .flat_map(|(_, _, _, _, _, assigned)| assigned)
+            .map(|(connector, mode_id, _)| {
+                let monitor = monitors
+                    .iter()
+                    .find(|monitor| monitor.connector_info.connector == *connector);
+                let name = monitor.map_or(connector.as_str(), |monitor| &monitor.display_name);
+                match monitor.and_then(|monitor| monitor.modes.iter().find(|m| m.id == *mode_id)) {
+                    Some(mode) => format!("{name}, {} by {}", mode.width, mode.height),
+                    None => name.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" and ");
+        let summary = match change.rule {
+            Some(rule) => format!("Switched to {}", rule.mode.to_string().to_lowercase()),
+            None => "Restored an earlier layout".to_string(),
+        };
+        self.speak(&format!("{summary}: {described}")).await;
+        self.record(change, &previous, logical_monitors);
+        if let Some(notifications) = &self.notifications {
+            report(notifications.changed(&summary, &described, previous).await);
+        }
+    }
+
+    /// Add a change to the journal, if there is one
+    pub fn record(
+        &self,
+        change: Change<'_>,
+        previous: &[ApplyLogicalMonitorTuple],
+        applied: &[ApplyLogicalMonitorTuple],
+    ) {
+        if let Some(journal) = &self.journal
+            && let Err(error) = journal.record(&Entry::new(change, previous, applied))
+        {
+            eprintln!("{error}");
+        }
+    }
+
+    /// The user has `timeout` to keep the layout
+    pub async fn confirm_requested(&self, timeout: Duration) {
+        self.speak(&format!(
+            "Keep this layout? Confirm within {} seconds.",
+            timeout.as_secs()
+        ))
+        .await;
+    }
+
+    /// The layout wasn't confirmed and the previous one is back
+    pub async fn reverted(&self) {
+        self.speak("Not confirmed. The previous layout was restored.")
+            .await;
+        if let Some(notifications) = &self.notifications {
+            report(
+                notifications
+                    .reverted(
+                        "Layout reverted",
+                        "The new layout was not confirmed in time.",
+                    )
+                    .await,
+            );
+        }
+    }
+
+    /// Changing the display failed for good
+    pub async fn failed(&self, error: &Error) {
+        let error = error.to_string();
+        let error = error.trim_start_matches("✗ ");
+        self.speak(&format!("Display change failed: {error}")).await;
+        if let Some(notifications) = &self.notifications {
+            report(notifications.failed("Display change failed", error).await);
+        }
+    }
+
+    async fn speak(&self, message: &str) {
+        if let Some(speech) = &self.speech
+            && let Err(error) = speech.speak(message).await
+        {
+            eprintln!("Failed to speak \"{message}\": {error}");
+        }
+    }
+}
+
+fn report(result: crate::Result<()>) {
+    if let Err(error) = result {
+        eprintln!("Failed to send a desktop notification: {error}");
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures::{StreamExt as _, channel::mpsc, stream::BoxStream};
+use serde::Deserialize;
+use zbus::{Connection, zvariant::Value};
+
+use crate::{ApplyLogicalMonitorTuple, Result, generated::notifications_proxy::NotificationsProxy};
+
+const APP_NAME: &str = "set-display";
+const APP_ICON: &str = "video-display";
+const REVERT_ACTION: &str = "revert";
+
+/// Urgency hint of the desktop notifications spec
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low = 0,
+    #[default]
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Layouts to restore, requested through the "Revert" action of a notification
+pub type RevertRequests = BoxStream<'static, Vec<ApplyLogicalMonitorTuple>>;
+
+/// Change notifications so far, and the latest one with the layout its "Revert" action
+/// restores
+#[derive(Debug, Default)]
+struct Changes {
+    count: u64,
+    last: Option<LastChange>,
+}
+
+#[derive(Debug)]
+struct LastChange {
+    id: u32,
+    /// Key of the "Revert" action, unique to the change: servers reuse the id of a notification
+    /// they replace, so the id alone can't tell a click on an earlier change from the latest
+    action: String,
+    previous: Vec<ApplyLogicalMonitorTuple>,
+}
+
+/// Desktop notifications through `org.freedesktop.Notifications`
+#[derive(Debug, Clone)]
+pub struct Notifications {
+    proxy: NotificationsProxy<'static>,
+    urgency: Urgency,
+    /// Whether change notifications offer "Revert": only while someone consumes the requests
+    revertible: bool,
+    changes: Arc<Mutex<Changes>>,
+}
+
+impl Notifications {
+    /// Notifications on the session bus
+    pub async fn connect(urgency: Urgency, revertible: bool) -> Result<(Self, RevertRequests)> {
+        Self::new(&Connection::session().await?, urgency, revertible).await
+    }
+
+    /// Notifications through `connection`, and the layouts users ask to revert to. Without
+    /// `revertible`, as when the process exits right after applying, there is no "Revert".
+    pub async fn new(
+        connection: &Connection,
+        urgency: Urgency,
+        revertible: bool,
+    ) -> Result<(Self, RevertRequests)> {
+        let proxy = NotificationsProxy::new(connection).await?;
+        let changes = Arc::new(Mutex::new(Changes::default()));
+
+        let mut invoked = proxy.receive_action_invoked().await?;
+        let (sender, reverts) = mpsc::unbounded();
+        let pending = changes.clone();
+        tokio::spawn(async move {
+            while let Some(signal) = invoked.next().await {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+
+                // Only the latest change can be reverted
+                let mut pending = pending.lock().unwrap();
+                if let Some(last) = &pending.last
+                    && last.id == args.id
+                    && last.action == args.action_key
+                    && let Some(last) = pending.last.take()
+                    && sender.unbounded_send(last.previous).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                proxy,
+                urgency,
+                revertible,
+                changes,
+            },
+            reverts.boxed(),
+        ))
+    }
+
+    /// A new layout is in place; the "Revert" action, if offered, brings back `previous`
+    pub async fn changed(
+        &self,
+        summary: &str,
+        body: &str,
+        previous: Vec<ApplyLogicalMonitorTuple>,
+    ) -> Result<()> {
+        let action = {
+            let mut changes = self.changes.lock().unwrap();
+            changes.count += 1;
+            format!("{REVERT_ACTION}-{}", changes.count)
+        };
+        let actions: &[&str] = match self.revertible {
+            true => &[&action, "Revert"],
+            false => &[],
+        };
+        let id = self
+            .notify(self.replaces_id(), summary, body, actions, self.urgency)
+            .await?;
+        self.changes.lock().unwrap().last = Some(LastChange {
+            id,
+            action,
+            previous,
+        });
+        Ok(())
+    }
+
+    /// The latest change was undone; replaces its notification
+    pub async fn reverted(&self, summary: &str, body: &str) -> Result<()> {
+        let replaces_id = self.replaces_id();
+        self.changes.lock().unwrap().last = None;
+        self.notify(replaces_id, summary, body, &[], self.urgency)
+            .await?;
+        Ok(())
+    }
+
+    /// Something went wrong; always critical, so that it stays until dismissed
+    pub async fn failed(&self, summary: &str, body: &str) -> Result<()> {
+        self.notify(0, summary, body, &[], Urgency::Critical)
+            .await?;
+        Ok(())
+    }
+
+    fn replaces_id(&self) -> u32 {
+        self.changes
+            .lock()
+            .unwrap()
+            .last
+            .as_ref()
+            .map_or(0, |last| last.id)
+    }
+
+    async fn notify(
+        &self,
+        replaces_id: u32,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        urgency: Urgency,
+    ) -> Result<u32> {
+        let urgency = Value::U8(urgency as u8);
+        let hints = HashMap::from([("urgency", &urgency)]);
+        Ok(self
+            .proxy
+            .notify(
+                APP_NAME,
+                replaces_id,
+                APP_ICON,
+                summary,
+                body,
+                actions,
+                hints,
+                -1,
+            )
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use zbus::{interface, names::BusName, zvariant::OwnedValue};
+
+    use super::*;
+    use crate::connection::peer_to_peer;
+
+    const PATH: &str = "/org/freedesktop/Notifications";
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Sent {
+        replaces_id: u32,
+        summary: String,
+        actions: Vec<String>,
+        urgency: u8,
+    }
+
+    #[derive(Default, Clone)]
+    struct FakeNotifications(Arc<Mutex<Vec<Sent>>>);
+
+    #[interface(name = "org.freedesktop.Notifications")]
+    impl FakeNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: String,
+            replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            _body: String,
+            actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut sent = self.0.lock().unwrap();
+            sent.push(Sent {
+                replaces_id,
+                summary,
+                actions,
+                urgency: hints["urgency"].downcast_ref().unwrap(),
+            });
+            // A replaced notification keeps its id
+            match replaces_id {
+                0 => sent.len() as u32,
+                id => id,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn one_off_changes_offer_no_revert() {
+        let fake = FakeNotifications::default();
+        let (_server, client) = peer_to_peer(PATH, fake.clone()).await.unwrap();
+
+        let (notifications, _) = Notifications::new(&client, Urgency::Normal, false)
+            .await
+            .unwrap();
+        notifications
+            .changed("Switched to external", "", vec![])
+            .await
+            .unwrap();
+
+        let sent = fake.0.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].actions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn revert_action_requests_the_previous_layout() {
+        let fake = FakeNotifications::default();
+        let (server, client) = peer_to_peer(PATH, fake.clone()).await.unwrap();
+
+        let (notifications, mut reverts) = Notifications::new(&client, Urgency::Low, true)
+            .await
+            .unwrap();
+        let layout = |x| vec![(x, 0, 1.0, 0, true, vec![])];
+        notifications
+            .changed("Switched to external", "", layout(1))
+            .await
+            .unwrap();
+        notifications
+            .changed("Switched to join", "", layout(2))
+            .await
+            .unwrap();
+        notifications
+            .failed("Display change failed", "")
+            .await
+            .unwrap();
+
+        let sent = fake.0.lock().unwrap().clone();
+        let revert = |change: u32| vec![format!("revert-{change}"), "Revert".to_string()];
+        assert_eq!(
+            sent,
+            [
+                Sent {
+                    replaces_id: 0,
+                    summary: "Switched to external".into(),
+                    actions: revert(1),
+                    urgency: 0,
+                },
+                Sent {
+                    replaces_id: 1,
+                    summary: "Switched to join".into(),
+                    actions: revert(2),
+                    urgency: 0,
+                },
+                Sent {
+                    replaces_id: 0,
+                    summary: "Display change failed".into(),
+                    actions: vec![],
+                    urgency: 2,
+                },
+            ]
+        );
+
+        // The second change replaced the first notification, keeping its id; clicking the
+        // first change's "Revert" (delivered late) does nothing
+        let invoke = async |id: u32, action: &str| {
+            server
+                .emit_signal(
+                    None::<BusName<'_>>,
+                    PATH,
+                    "org.freedesktop.Notifications",
+                    "ActionInvoked",
+                    &(id, action),
+                )
+                .await
+                .unwrap();
+        };
+        invoke(1, "revert-1").await;
+        invoke(3, "revert-2").await;
+        invoke(1, "revert-2").await;
+        let reverted = tokio::time::timeout(Duration::from_secs(5), reverts.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reverted, layout(2));
+
+        // Once reverted, the change can't be reverted again
+        notifications.reverted("Reverted", "").await.unwrap();
+        invoke(1, "revert-2").await;
+        notifications
+            .changed("Switched to mirror", "", layout(3))
+            .await
+            .unwrap();
+        invoke(5, "revert-3").await;
+        let reverted = tokio::time::timeout(Duration::from_secs(5), reverts.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reverted, layout(3));
+    }
+}
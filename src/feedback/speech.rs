@@ -0,0 +1,183 @@
+use std::{env, path::PathBuf};
+
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader, Lines},
+    net::{
+        UnixStream,
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+    },
+};
+
+use crate::{Error, Result};
+
+/// Client of speech-dispatcher's SSIP socket. Connects for each announcement, so that restarts
+/// of speech-dispatcher don't matter.
+#[derive(Debug, Clone)]
+pub struct SpeechDispatcher {
+    socket: PathBuf,
+}
+
+impl SpeechDispatcher {
+    pub fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    /// `$SPEECHD_ADDRESS` (`unix_socket:PATH`), or speech-dispatcher's default socket in
+    /// `$XDG_RUNTIME_DIR`
+    pub fn from_env() -> Self {
+        let socket = env::var("SPEECHD_ADDRESS")
+            .ok()
+            .and_then(|address| address.strip_prefix("unix_socket:").map(PathBuf::from))
+            .unwrap_or_else(|| {
+                PathBuf::from(env::var_os("XDG_RUNTIME_DIR").unwrap_or_default())
+                    .join("speech-dispatcher/speechd.sock")
+            });
+        Self::new(socket)
+    }
+
+    /// Queue `text` to be spoken
+    pub async fn speak(&self, text: &str) -> Result<()> {
+        let stream = UnixStream::connect(&self.socket)
+            .await
+            .map_err(|error| Error::Speech(format!("{}: {error}", self.socket.display())))?;
+        let (reader, mut writer) = stream.into_split();
+        let mut replies = BufReader::new(reader).lines();
+
+        let mut command =
+            async |command: &str| ssip_command(&mut writer, &mut replies, command).await;
+        command("SET SELF CLIENT_NAME user:set-display:main").await?;
+        command("SET SELF PRIORITY message").await?;
+        command("SPEAK").await?;
+
+        // A line holding a single dot ends the message, so dots starting a line are doubled
+        let message = text
+            .lines()
+            .map(|line| match line.starts_with('.') {
+                true => format!(".{line}"),
+                false => line.to_string(),
+            })
+            .chain([".".to_string()])
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        command(&message).await?;
+        command("QUIT").await?;
+        Ok(())
+    }
+}
+
+/// Send `command` and read its reply, which ends with a `NNN text` line; codes 2xx are success
+async fn ssip_command(
+    writer: &mut OwnedWriteHalf,
+    replies: &mut Lines<BufReader<OwnedReadHalf>>,
+    command: &str,
+) -> Result<()> {
+    let io_error = |error: std::io::Error| Error::Speech(error.to_string());
+    writer
+        .write_all(format!("{command}\r\n").as_bytes())
+        .await
+        .map_err(io_error)?;
+
+    loop {
+        let reply = replies
+            .next_line()
+            .await
+            .map_err(io_error)?
+            .ok_or_else(|| Error::Speech("connection closed".to_string()))?;
+
+        // `NNN-text` lines continue the reply
+        if reply.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        return match reply.starts_with('2') {
+            true => Ok(()),
+            false => Err(Error::Speech(reply)),
+        };
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{
+        io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+        net::UnixListener,
+    };
+
+    /// speech-dispatcher stand-in that records the messages it is asked to speak
+    pub struct FakeSpeechDispatcher {
+        pub spoken: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FakeSpeechDispatcher {
+        pub fn listen(socket: &Path) -> Self {
+            let listener = UnixListener::bind(socket).unwrap();
+            let spoken = Arc::new(Mutex::new(vec![]));
+            let messages = spoken.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    let mut message: Option<Vec<String>> = None;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let reply = match (&mut message, line.as_str()) {
+                            (Some(text), ".") => {
+                                messages.lock().unwrap().push(text.join("\n"));
+                                message = None;
+                                "225-1\r\n225 OK MESSAGE QUEUED"
+                            }
+                            (Some(text), line) => {
+                                text.push(line.strip_prefix('.').unwrap_or(line).to_string());
+                                continue;
+                            }
+                            (None, "SPEAK") => {
+                                message = Some(vec![]);
+                                "230 OK RECEIVING DATA"
+                            }
+                            (None, "QUIT") => "231 HAPPY HACKING",
+                            (None, line) if line.starts_with("SET SELF ") => "208 OK SET",
+                            (None, _) => "300 ERR UNKNOWN COMMAND",
+                        };
+                        let _ = writer.write_all(format!("{reply}\r\n").as_bytes()).await;
+                    }
+                }
+            });
+            Self { spoken }
+        }
+
+        pub fn spoken(&self) -> Vec<String> {
+            self.spoken.lock().unwrap().clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fake::FakeSpeechDispatcher, *};
+
+    #[tokio::test]
+    async fn speaks_through_the_ssip_socket() {
+        let directory = tempfile::tempdir().unwrap();
+        let socket = directory.path().join("speechd.sock");
+        let fake = FakeSpeechDispatcher::listen(&socket);
+
+        let speech = SpeechDispatcher::new(socket);
+        speech.speak("Switched to external").await.unwrap();
+        speech.speak(".hidden\nsecond line").await.unwrap();
+
+        assert_eq!(
+            fake.spoken(),
+            ["Switched to external", ".hidden\nsecond line"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_a_missing_socket() {
+        let directory = tempfile::tempdir().unwrap();
+        let speech = SpeechDispatcher::new(directory.path().join("missing.sock"));
+        assert!(matches!(speech.speak("hello").await, Err(Error::Speech(_))));
+    }
+}
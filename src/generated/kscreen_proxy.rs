@@ -0,0 +1,56 @@
+//! # D-Bus interface proxy for: `org.kde.kscreen.Backend`
+//!
+//! This code was generated by `zbus-xmlgen` `5.1.0` from D-Bus introspection data.
+//! Source: `Interface '/backend' from service 'org.kde.KScreen' on session bus`.
+//!
+//! You may prefer to adapt it, instead of using it verbatim.
+//!
+//! More information can be found in the [Writing a client proxy] section of the zbus
+//! documentation.
+//!
+//! This type implements the [D-Bus standard interfaces], (`org.freedesktop.DBus.*`) for which the
+//! following zbus API can be used:
+//!
+//! * [`zbus::fdo::PropertiesProxy`]
+//! * [`zbus::fdo::IntrospectableProxy`]
+//! * [`zbus::fdo::PeerProxy`]
+//!
+//! Consequently `zbus-xmlgen` did not generate code for the above interfaces.
+//!
+//! [Writing a client proxy]: https://dbus2.github.io/zbus/client.html
+//! [D-Bus standard interfaces]: https://dbus.freedesktop.org/doc/dbus-specification.html#standard-interfaces,
+use zbus::proxy;
+#[proxy(
+    interface = "org.kde.kscreen.Backend",
+    default_service = "org.kde.KScreen",
+    default_path = "/backend"
+)]
+pub trait Backend {
+    /// getConfig method
+    #[zbus(name = "getConfig")]
+    fn get_config(
+        &self,
+    ) -> zbus::Result<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>;
+
+    /// getEdid method
+    #[zbus(name = "getEdid")]
+    fn get_edid(&self, output_id: i32) -> zbus::Result<Vec<u8>>;
+
+    /// quit method
+    #[zbus(name = "quit")]
+    fn quit(&self) -> zbus::Result<()>;
+
+    /// setConfig method
+    #[zbus(name = "setConfig")]
+    fn set_config(
+        &self,
+        config: std::collections::HashMap<&str, &zbus::zvariant::Value<'_>>,
+    ) -> zbus::Result<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>;
+
+    /// configChanged signal
+    #[zbus(signal, name = "configChanged")]
+    fn config_changed(
+        &self,
+        config: std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+}
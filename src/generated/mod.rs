@@ -1,3 +1,6 @@
 // Auto-generated module declarations
 pub mod cinnamon_proxy;
 pub mod gnome_proxy;
+pub mod kscreen_proxy;
+pub mod notifications_proxy;
+pub mod upower_proxy;
@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::{ApplyLogicalMonitorTuple, Error, Result, cli::DisplayRule};
+
+const JOURNAL_FILE: &str = "set-display/history.jsonl";
+
+/// Entries kept in the journal; older ones are dropped as new ones are recorded
+const MAX_ENTRIES: usize = 500;
+
+/// What made set-display apply a layout
+#[derive(Debug, Clone, Copy, PartialEq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Trigger {
+    /// A one-off command such as `set-display external`
+    Command,
+    /// Watching started (or reconnected to the compositor)
+    Watch,
+    /// Monitors were plugged or unplugged while watching
+    MonitorsChanged,
+    /// The configuration file was edited while watching
+    ConfigReloaded,
+    /// The previous layout came back: not confirmed in time, or "Revert" in a notification
+    Revert,
+    /// `set-display undo`
+    Undo,
+    /// `set-display history N`
+    History,
+    /// `set-display load NAME`
+    Load,
+    /// `set-display rotate PATTERN ANGLE`
+    Rotate,
+    /// `set-display primary PATTERN`
+    Primary,
+}
+
+/// Why a layout is being applied
+#[derive(Debug, Clone, Copy)]
+pub struct Change<'a> {
+    pub trigger: Trigger,
+    /// Rule that picked the layout; `None` when an earlier layout is restored
+    pub rule: Option<&'a DisplayRule>,
+}
+
+/// One applied configuration, as a line of the journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub trigger: Trigger,
+    /// The matched rule, as printed in test mode
+    pub rule: Option<String>,
+    pub previous: Vec<LoggedLogicalMonitor>,
+    pub applied: Vec<LoggedLogicalMonitor>,
+}
+
+/// A logical monitor of a journal entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggedLogicalMonitor {
+    pub x: i32,
+    pub y: i32,
+    pub scale: f64,
+    pub transform: u32,
+    pub primary: bool,
+    pub monitors: Vec<LoggedMonitor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggedMonitor {
+    pub connector: String,
+    pub mode: String,
+}
+
+impl Entry {
+    pub fn new(
+        change: Change<'_>,
+        previous: &[ApplyLogicalMonitorTuple],
+        applied: &[ApplyLogicalMonitorTuple],
+    ) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            trigger: change.trigger,
+            rule: change.rule.map(ToString::to_string),
+            previous: previous.iter().map(LoggedLogicalMonitor::from).collect(),
+            applied: applied.iter().map(LoggedLogicalMonitor::from).collect(),
+        }
+    }
+
+    /// The layout before this change, as arguments to apply it again
+    pub fn previous_layout(&self) -> Vec<ApplyLogicalMonitorTuple> {
+        self.previous.iter().map(Into::into).collect()
+    }
+
+    /// The layout this change applied, as arguments to apply it again
+    pub fn applied_layout(&self) -> Vec<ApplyLogicalMonitorTuple> {
+        self.applied.iter().map(Into::into).collect()
+    }
+
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            humantime::format_rfc3339_seconds(self.time()),
+            self.trigger
+        )?;
+        if let Some(rule) = &self.rule {
+            write!(f, ": {rule}")?;
+        }
+        write!(f, "\n     Before: {}", describe(&self.previous))?;
+        write!(f, "\n     After:  {}", describe(&self.applied))
+    }
+}
+
+/// One line per layout, e.g. `eDP-1 2256x1504@59.999 at (0, 0) primary; DP-1 ...`
+fn describe(layout: &[LoggedLogicalMonitor]) -> String {
+    if layout.is_empty() {
+        return "nothing enabled".to_string();
+    }
+    layout
+        .iter()
+        .map(|logical| {
+            let monitors = logical
+                .monitors
+                .iter()
+                .map(|monitor| format!("{} {}", monitor.connector, monitor.mode))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            let primary = if logical.primary { " primary" } else { "" };
+            format!(
+                "{monitors} at ({}, {}) scale {}{primary}",
+                logical.x, logical.y, logical.scale
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl From<&ApplyLogicalMonitorTuple> for LoggedLogicalMonitor {
+    fn from((x, y, scale, transform, primary, assigned): &ApplyLogicalMonitorTuple) -> Self {
+        Self {
+            x: *x,
+            y: *y,
+            scale: *scale,
+            transform: *transform,
+            primary: *primary,
+            monitors: assigned
+                .iter()
+                .map(|(connector, mode_id, _)| LoggedMonitor {
+                    connector: connector.clone(),
+                    mode: mode_id.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&LoggedLogicalMonitor> for ApplyLogicalMonitorTuple {
+    fn from(logical: &LoggedLogicalMonitor) -> Self {
+        (
+            logical.x,
+            logical.y,
+            logical.scale,
+            logical.transform,
+            logical.primary,
+            logical
+                .monitors
+                .iter()
+                .map(|monitor| {
+                    (
+                        monitor.connector.clone(),
+                        monitor.mode.clone(),
+                        HashMap::new(),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Applied configurations, one JSON object per line in
+/// `$XDG_STATE_HOME/set-display/history.jsonl`, oldest first
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+    max_entries: usize,
+}
+
+impl Journal {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            max_entries: MAX_ENTRIES,
+        }
+    }
+
+    /// `$XDG_STATE_HOME/set-display/history.jsonl`, falling back to `~/.local/state`
+    pub fn default_path() -> PathBuf {
+        env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".local/state")
+            })
+            .join(JOURNAL_FILE)
+    }
+
+    /// Append `entry`, creating the journal and its directory if needed, and drop the oldest
+    /// entries beyond the last `max_entries`
+    pub fn record(&self, entry: &Entry) -> Result<()> {
+        let write_error = |error| Error::JournalWrite {
+            path: self.path.clone(),
+            error,
+        };
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory).map_err(write_error)?;
+        }
+        let mut line = serde_json::to_string(entry).expect("journal entries serialize");
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(write_error)?;
+
+        let content = fs::read_to_string(&self.path).map_err(write_error)?;
+        let lines: Vec<&str> = content.lines().collect();
+        if lines.len() > self.max_entries {
+            let kept = lines[lines.len() - self.max_entries..].join("\n") + "\n";
+            fs::write(&self.path, kept).map_err(write_error)?;
+        }
+        Ok(())
+    }
+
+    /// Every recorded entry, oldest first; none if nothing was recorded yet. Lines that are not
+    /// entries, e.g. cut short by a crash, are skipped with a warning.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => {
+                return Err(Error::JournalRead {
+                    path: self.path.clone(),
+                    error,
+                });
+            }
+        };
+
+        Ok(content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(error) => {
+                    let error = Error::InvalidJournal {
+                        path: self.path.clone(),
+                        line: i + 1,
+                        message: error.to_string(),
+                    };
+                    eprintln!("{error}; skipping it");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Entry `number` of the history, as numbered by `set-display history`: 1 is the latest change,
+    /// 2 the one before it, and so on
+    pub fn entry(&self, number: usize) -> Result<Entry> {
+        let entries = self.entries()?;
+        number
+            .checked_sub(1)
+            .and_then(|index| entries.iter().rev().nth(index))
+            .cloned()
+            .ok_or(Error::NoHistoryEntry(number))
+    }
+
+    /// Print every entry, latest first and numbered as for `set-display history N`
+    pub fn print(&self) -> Result<()> {
+        let entries = self.entries()?;
+        println!("=== Display History ===");
+        println!("Journal: {}\n", self.path.display());
+        if entries.is_empty() {
+            println!("No display changes recorded yet.");
+        }
+        for (i, entry) in entries.iter().rev().enumerate() {
+            println!("  {}. {entry}", i + 1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::DisplayMode;
+
+    fn layout(connector: &str, x: i32) -> Vec<ApplyLogicalMonitorTuple> {
+        vec![(
+            x,
+            0,
+            1.0,
+            0,
+            true,
+            vec![(
+                connector.to_string(),
+                "1920x1080@60.000".to_string(),
+                HashMap::new(),
+            )],
+        )]
+    }
+
+    #[test]
+    fn records_and_reads_back_entries() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = Journal::new(directory.path().join("state/history.jsonl"));
+        assert!(journal.entries().unwrap().is_empty());
+
+        let rule = DisplayRule::always(DisplayMode::External);
+        let first = Entry::new(
+            Change {
+                trigger: Trigger::Command,
+                rule: Some(&rule),
+            },
+            &layout("eDP-1", 0),
+            &layout("DP-1", 0),
+        );
+        let second = Entry::new(
+            Change {
+                trigger: Trigger::Undo,
+                rule: None,
+            },
+            &layout("DP-1", 0),
+            &layout("eDP-1", 0),
+        );
+        journal.record(&first).unwrap();
+        journal.record(&second).unwrap();
+
+        assert_eq!(journal.entries().unwrap(), [first.clone(), second]);
+        let latest = journal.entry(2).unwrap();
+        assert_eq!(latest.rule.as_deref(), Some("External (always matches)"));
+        assert_eq!(latest.previous_layout(), layout("eDP-1", 0));
+        assert_eq!(latest.applied_layout(), layout("DP-1", 0));
+        assert!(matches!(journal.entry(3), Err(Error::NoHistoryEntry(3))));
+        assert!(matches!(journal.entry(0), Err(Error::NoHistoryEntry(0))));
+    }
+
+    fn entry(x: i32) -> Entry {
+        Entry::new(
+            Change {
+                trigger: Trigger::Watch,
+                rule: None,
+            },
+            &[],
+            &layout("DP-1", x),
+        )
+    }
+
+    #[test]
+    fn skips_corrupt_lines() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("history.jsonl");
+        let journal = Journal::new(path.clone());
+        let (first, second) = (entry(0), entry(1920));
+        journal.record(&first).unwrap();
+        fs::write(
+            &path,
+            fs::read_to_string(&path).unwrap() + "{\"timestamp\":\n",
+        )
+        .unwrap();
+        journal.record(&second).unwrap();
+
+        assert_eq!(journal.entries().unwrap(), [first, second]);
+    }
+
+    #[test]
+    fn keeps_the_latest_entries() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = Journal {
+            path: directory.path().join("history.jsonl"),
+            max_entries: 3,
+        };
+        for x in 0..5 {
+            journal.record(&entry(x)).unwrap();
+        }
+
+        let kept: Vec<i32> = journal
+            .entries()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.applied[0].x)
+            .collect();
+        assert_eq!(kept, [2, 3, 4]);
+    }
+}
@@ -5,8 +5,10 @@ mod cli;
 mod errors;
 pub use errors::{Error, Result};
 
+mod backend;
+
 mod current_state;
-pub use current_state::{CurrentState, CurrentStateTuple};
+pub use current_state::{ApplyOptions, CurrentState, CurrentStateTuple};
 
 mod monitor;
 pub use monitor::{Monitor, MonitorTuple};
@@ -16,49 +18,294 @@ mod printable_monitor;
 mod property_map_ext;
 pub use property_map_ext::PropertyMapExt;
 
+mod config;
+use config::{Config, Profile};
+
+mod confirmation;
+
 mod connection;
 pub use connection::connect;
 
 mod detection;
 
+mod feedback;
+use feedback::{Feedback, Notifications, RevertRequests, SpeechDispatcher, Urgency};
+
 mod generated;
 
+mod monitors_xml;
+
+mod journal;
+use journal::{Journal, Trigger};
+
+mod saved_layout;
+use saved_layout::SavedLayout;
+
 mod structs;
 pub use structs::*;
 
-use clap::Parser as _;
-use cli::{Cli, DisplayCommand};
+use std::time::Duration;
+
+use clap::{CommandFactory as _, FromArgMatches as _};
+use cli::{Cli, DisplayCommand, RuleCommand};
+use futures::{StreamExt as _, stream};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let args = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    // Keep a layout that is waiting for confirmation
+    if let DisplayCommand::Confirm = &args.command {
+        confirmation::confirm().await?;
+        println!("✓ Layout confirmed.");
+        return Ok(());
+    }
 
     // Handle status
     if let DisplayCommand::Status { modes } = &args.command {
-        CurrentState::current(10)
+        let backend = backend::connect_backend(10).await?;
+        CurrentState::current(backend.as_ref(), 10)
             .await?
-            .print_status(*modes)
+            .print_status(backend.as_ref(), *modes)
+            .await?;
+        return Ok(());
+    }
+
+    let journal = Journal::new(Journal::default_path());
+    let config_path = args.config.clone().unwrap_or_else(Config::default_path);
+    let layouts = SavedLayout::directory(&config_path);
+
+    // List the recorded display changes
+    if let DisplayCommand::History { entry: None } = &args.command {
+        journal.print()?;
+        return Ok(());
+    }
+
+    // Snapshot the current layout
+    if let DisplayCommand::Save { name } = &args.command {
+        let backend = backend::connect_backend(10).await?;
+        let state = CurrentState::current(backend.as_ref(), 10).await?;
+        let path = SavedLayout::snapshot(&state).save(&layouts, name)?;
+        println!(
+            "✓ Saved the current layout as '{name}' in {}",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    // Identify the connected monitors and the saved layout made for them
+    if let DisplayCommand::Detect = &args.command {
+        let backend = backend::connect_backend(10).await?;
+        let state = CurrentState::current(backend.as_ref(), 10).await?;
+        saved_layout::print_detection(&state, &layouts)?;
+        return Ok(());
+    }
+
+    // Turn GNOME's per-monitor-set configurations into saved layouts
+    if let DisplayCommand::ImportMonitorsXml { path, prefix } = &args.command {
+        let path = path.clone().unwrap_or_else(monitors_xml::default_path);
+        let imported = monitors_xml::import(&path, &layouts, prefix)?;
+        println!(
+            "✓ Imported {} layout(s) from {} into {}",
+            imported.len(),
+            path.display(),
+            layouts.display()
+        );
+        for (name, layout) in imported {
+            println!("  {name}: {}", layout.fingerprint);
+        }
+        return Ok(());
+    }
+
+    // Have the login screen use the current layout
+    if let DisplayCommand::ExportGdm { path } = &args.command {
+        let path = path.clone().unwrap_or_else(monitors_xml::gdm_path);
+        let backend = backend::connect_backend(10).await?;
+        let state = CurrentState::current(backend.as_ref(), 10).await?;
+        let backup = monitors_xml::export(&path, &state)?;
+        println!("✓ Exported the current layout to {}", path.display());
+        if let Some(backup) = backup {
+            println!("  Previous file kept as {}", backup.display());
+        }
+        return Ok(());
+    }
+
+    if matches!(
+        args.command,
+        DisplayCommand::Undo
+            | DisplayCommand::History { .. }
+            | DisplayCommand::Load { .. }
+            | DisplayCommand::Rotate { .. }
+            | DisplayCommand::Primary { .. }
+    ) {
+        let backend = backend::connect_backend(10).await?;
+        let (layout, trigger) = match &args.command {
+            DisplayCommand::Undo => match journal.entry(1) {
+                Err(Error::NoHistoryEntry(_)) => return Err(Error::NothingToUndo),
+                entry => (entry?.previous_layout(), Trigger::Undo),
+            },
+            DisplayCommand::History {
+                entry: Some(number),
+            } => (journal.entry(*number)?.applied_layout(), Trigger::History),
+            DisplayCommand::Load { name } => {
+                let saved = SavedLayout::load(&layouts, name)?;
+                let state = CurrentState::current(backend.as_ref(), 10).await?;
+                (saved.resolve(name, &state)?, Trigger::Load)
+            }
+            DisplayCommand::Rotate { pattern, transform } => {
+                let state = CurrentState::current(backend.as_ref(), 10).await?;
+                (state.rotated(pattern, *transform)?, Trigger::Rotate)
+            }
+            DisplayCommand::Primary { pattern } => {
+                let state = CurrentState::current(backend.as_ref(), 10).await?;
+                (state.with_primary(pattern)?, Trigger::Primary)
+            }
+            _ => unreachable!(),
+        };
+        let options = RunOptions::new(&args, &Profile::default());
+        let (feedback, _) = feedback(options.feedback, journal, false).await;
+        CurrentState::restore_layout(backend.as_ref(), &layout, trigger, options.apply, &feedback)
             .await?;
         return Ok(());
     }
 
-    // Extract rules from command
-    let rules = args.command.rules()?;
+    // Explain rules against the current monitors
+    if let DisplayCommand::Rules(command) = &args.command
+        && let RuleCommand::Test { profile, .. } = command.as_ref()
+    {
+        let rules = match profile {
+            Some(name) => Config::load(&config_path)?
+                .profile(name.as_deref())?
+                .rules(),
+            None => command.rules(&matches)?,
+        };
+        let backend = backend::connect_backend(10).await?;
+        CurrentState::explain_rules(backend.as_ref(), &rules).await?;
+        return Ok(());
+    }
+
+    // Extract rules from command, or from a configuration profile
+    let (rules, options, reloads) = match &args.command {
+        DisplayCommand::Profile { name } => {
+            let config = Config::load(&config_path)?;
+            let profile = config.profile(name.as_deref())?;
+            let options = RunOptions::new(&args, profile);
+            // Pick up edits to the configuration while watching
+            let reloads = match options.watch {
+                true => config::watch_rules(config_path, name.clone(), profile.clone())?,
+                false => stream::pending().boxed(),
+            };
+            (profile.rules(), options, reloads)
+        }
+        DisplayCommand::Rules(command) => (
+            command.rules(&matches)?,
+            RunOptions::new(&args, &Profile::default()),
+            stream::pending().boxed(),
+        ),
+        _ => unreachable!(),
+    };
+    let (feedback, reverts) = feedback(options.feedback, journal, options.watch).await;
 
-    if args.test {
+    if options.apply.dry_run {
         println!("=== TEST MODE ===");
         println!("Changes will be previewed but not applied.\n");
+
+        println!("Rules in order of preference (first match wins):");
+        for (i, rule) in rules.iter().enumerate() {
+            println!("  {}. {rule}", i + 1);
+        }
+        println!();
     }
 
     // If watch flag is enabled
-    if args.watch {
+    if options.watch {
         // Start watching for monitor changes
-        CurrentState::watch_and_execute(&rules, args.test).await?;
+        CurrentState::watch_and_execute(
+            async || backend::connect_backend(10).await,
+            &rules,
+            options.saved_layouts.then_some(layouts.as_path()),
+            reloads,
+            reverts,
+            options.apply,
+            &feedback,
+        )
+        .await?;
         return Ok(());
     }
 
     // Execute the selected mode
-    CurrentState::determine_and_execute_mode(&rules, 10, args.test).await?;
+    let backend = backend::connect_backend(10).await?;
+    CurrentState::determine_and_execute_mode(
+        backend.as_ref(),
+        &rules,
+        Trigger::Command,
+        options.apply,
+        &feedback,
+    )
+    .await?;
 
     Ok(())
 }
+
+/// How to run rules and report display changes
+struct RunOptions {
+    watch: bool,
+    /// Load the saved layout matching the connected monitors instead of running the rules
+    saved_layouts: bool,
+    apply: ApplyOptions,
+    feedback: FeedbackOptions,
+}
+
+/// Which feedback to give on display changes
+#[derive(Debug, Clone, Copy)]
+struct FeedbackOptions {
+    speak: bool,
+    notify: bool,
+    urgency: Option<Urgency>,
+}
+
+impl RunOptions {
+    /// Options of `profile`, overridden by those given on the command line
+    fn new(args: &Cli, profile: &Profile) -> Self {
+        RunOptions {
+            watch: args.watch || profile.watch,
+            saved_layouts: !args.no_saved_layouts && profile.saved_layouts.unwrap_or(true),
+            apply: ApplyOptions {
+                dry_run: args.test || profile.test,
+                confirm: args.confirm.or(profile.confirm).map(Duration::from_secs),
+                persistent: args.persistent || profile.persistent,
+            },
+            feedback: FeedbackOptions {
+                speak: args.speak || profile.speak,
+                notify: args.notify || profile.notify,
+                urgency: args.urgency.or(profile.urgency),
+            },
+        }
+    }
+}
+
+/// Feedback on display changes, recorded in `journal`, and the layouts to go back to when a
+/// notification's "Revert" action is picked; notifications only offer it when `watch`ing, as
+/// one-off commands exit before anyone could pick it
+async fn feedback(
+    options: FeedbackOptions,
+    journal: Journal,
+    watch: bool,
+) -> (Feedback, RevertRequests) {
+    let mut feedback = Feedback::default().with_journal(journal);
+    if options.speak {
+        feedback = feedback.with_speech(SpeechDispatcher::from_env());
+    }
+    let mut reverts: RevertRequests = stream::pending().boxed();
+    if options.notify {
+        match Notifications::connect(options.urgency.unwrap_or_default(), watch).await {
+            Ok((notifications, requests)) => {
+                feedback = feedback.with_notifications(notifications);
+                reverts = requests;
+            }
+            Err(error) => eprintln!("Desktop notifications are unavailable: {error}"),
+        }
+    }
+    (feedback, reverts)
+}
@@ -23,6 +23,14 @@ impl Monitor {
         self.is_builtin
     }
 
+    /// The mode the monitor prefers, or its first one if it has no preference
+    pub fn preferred_mode(&self) -> Option<&Mode> {
+        self.modes
+            .iter()
+            .find(|mode| mode.is_preferred)
+            .or_else(|| self.modes.first())
+    }
+
     pub fn print_modes(&self) {
         println!("     Available Modes (* = current, P = preferred):");
 
@@ -0,0 +1,637 @@
+use std::{
+    env,
+    fmt::{self, Write as _},
+    fs,
+    os::unix::{self, fs::MetadataExt as _},
+    path::{Path, PathBuf},
+};
+
+use roxmltree::{Document, Node};
+
+use crate::{
+    ConnectorInfo, CurrentState, Error, Result,
+    saved_layout::{Fingerprint, SavedLayout, SavedLogicalMonitor, SavedMonitor},
+};
+
+// Mutter keeps the configuration users chose in GNOME Settings for each set of monitors in
+// `~/.config/monitors.xml`, one `<configuration>` per set:
+// https://gitlab.gnome.org/GNOME/mutter/-/blob/main/src/backends/meta-monitor-config-store.c
+
+/// `$XDG_CONFIG_HOME/monitors.xml`, falling back to `~/.config`
+pub fn default_path() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"))
+        .join("monitors.xml")
+}
+
+pub fn load(path: &Path) -> Result<Vec<SavedLayout>> {
+    let content = fs::read_to_string(path).map_err(|error| Error::MonitorsXmlRead {
+        path: path.into(),
+        error,
+    })?;
+    parse(path, &content)
+}
+
+/// Every `<configuration>` of `content` as a saved layout, fingerprinted with its enabled and
+/// disabled monitors
+///
+/// Saved layouts are in logical pixels, so configurations with scaled monitors in physical
+/// pixels are refused: their positions can't be converted without redoing the layout.
+pub fn parse(path: &Path, content: &str) -> Result<Vec<SavedLayout>> {
+    let document = document(path, content)?;
+    configurations(path, &document)?
+        .into_iter()
+        .map(|configuration| {
+            let scaled = configuration
+                .layout
+                .logical_monitors
+                .iter()
+                .any(|logical| logical.scale != 1.0);
+            if configuration.layout_mode == LayoutMode::Physical && scaled {
+                return Err(Error::InvalidMonitorsXml {
+                    path: path.into(),
+                    line: document.text_pos_at(configuration.node.range().start).row as usize,
+                    message: "scaled monitors positioned in physical pixels (<layoutmode> \
+                              physical or none); set-display can only import logical layouts"
+                        .into(),
+                });
+            }
+            Ok(configuration.layout)
+        })
+        .collect()
+}
+
+/// A `<configuration>` element and what it holds
+struct Configuration<'a, 'input> {
+    node: Node<'a, 'input>,
+    layout: SavedLayout,
+    layout_mode: LayoutMode,
+    /// Mutter picks the configuration whose monitors are exactly the connected ones
+    monitor_set: Vec<String>,
+}
+
+/// Whether `<x>` and `<y>` are logical pixels (as in set-display and Mutter's D-Bus API with
+/// fractional scaling) or physical ones, Mutter's default without `<layoutmode>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LayoutMode {
+    Logical,
+    Physical,
+}
+
+fn document<'input>(path: &Path, content: &'input str) -> Result<Document<'input>> {
+    Document::parse(content).map_err(|error| Error::InvalidMonitorsXml {
+        path: path.into(),
+        line: error.pos().row as usize,
+        message: error.to_string(),
+    })
+}
+
+fn configurations<'a, 'input>(
+    path: &Path,
+    document: &'a Document<'input>,
+) -> Result<Vec<Configuration<'a, 'input>>> {
+    let invalid = |node: Node<'_, '_>, message: String| Error::InvalidMonitorsXml {
+        path: path.into(),
+        line: document.text_pos_at(node.range().start).row as usize,
+        message,
+    };
+
+    let root = document.root_element();
+    if !root.has_tag_name("monitors") {
+        return Err(invalid(root, "expected a <monitors> document".into()));
+    }
+
+    elements(root, "configuration")
+        .map(|configuration| {
+            let layout_mode = match text(configuration, "layoutmode") {
+                Some("logical") => LayoutMode::Logical,
+                Some("physical") | None => LayoutMode::Physical,
+                Some(other) => {
+                    return Err(invalid(
+                        configuration,
+                        format!("<layoutmode> is neither logical nor physical: {other}"),
+                    ));
+                }
+            };
+            let mut identities = vec![];
+            let logical_monitors = elements(configuration, "logicalmonitor")
+                .map(|logical| {
+                    let number = |name: &str| -> Result<Option<f64>> {
+                        text(logical, name)
+                            .map(|value| {
+                                value.parse().map_err(|_| {
+                                    invalid(logical, format!("<{name}> is not a number: {value}"))
+                                })
+                            })
+                            .transpose()
+                    };
+                    let monitors = elements(logical, "monitor")
+                        .map(|monitor| {
+                            let spec = monitor_spec(monitor, &invalid)?;
+                            let mode = element(monitor, "mode").ok_or_else(|| {
+                                invalid(monitor, "<monitor> has no <mode>".into())
+                            })?;
+                            let dimension = |name: &str| -> Result<i32> {
+                                text(mode, name)
+                                    .and_then(|value| value.parse().ok())
+                                    .ok_or_else(|| {
+                                        invalid(mode, format!("<mode> has no valid <{name}>"))
+                                    })
+                            };
+                            let width = dimension("width")?;
+                            let height = dimension("height")?;
+                            let refresh_rate: f64 = text(mode, "rate")
+                                .and_then(|value| value.parse().ok())
+                                .ok_or_else(|| {
+                                    invalid(mode, "<mode> has no valid <rate>".into())
+                                })?;
+                            let saved = SavedMonitor {
+                                vendor: spec.vendor.clone(),
+                                product: spec.product.clone(),
+                                serial: spec.serial.clone(),
+                                connector: spec.connector.clone(),
+                                mode: format!("{width}x{height}@{refresh_rate:.3}"),
+                                width,
+                                height,
+                                refresh_rate,
+                            };
+                            identities.push(spec);
+                            Ok(saved)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    Ok(SavedLogicalMonitor {
+                        x: number("x")?.unwrap_or_default() as i32,
+                        y: number("y")?.unwrap_or_default() as i32,
+                        scale: number("scale")?.unwrap_or(1.0),
+                        transform: element(logical, "transform").map_or(0, transform),
+                        primary: text(logical, "primary") == Some("yes"),
+                        monitors,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            for disabled in elements(configuration, "disabled") {
+                for spec in elements(disabled, "monitorspec") {
+                    identities.push(connector_info(spec, &invalid)?);
+                }
+            }
+
+            Ok(Configuration {
+                node: configuration,
+                layout: SavedLayout {
+                    fingerprint: Fingerprint::of_connectors(&identities),
+                    logical_monitors,
+                },
+                layout_mode,
+                monitor_set: monitor_set(&identities),
+            })
+        })
+        .collect()
+}
+
+/// GDM's monitors.xml: `/var/lib/gdm3/.config/monitors.xml` on Debian and Ubuntu,
+/// `/var/lib/gdm/.config/monitors.xml` elsewhere
+pub fn gdm_path() -> PathBuf {
+    ["/var/lib/gdm3", "/var/lib/gdm"]
+        .into_iter()
+        .map(Path::new)
+        .find(|home| home.is_dir())
+        .unwrap_or(Path::new("/var/lib/gdm"))
+        .join(".config/monitors.xml")
+}
+
+/// Save every configuration of the monitors.xml at `path` into `directory` as `PREFIX-N`
+pub fn import(path: &Path, directory: &Path, prefix: &str) -> Result<Vec<(String, SavedLayout)>> {
+    load(path)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, layout)| {
+            let name = format!("{prefix}-{}", i + 1);
+            layout.save(directory, &name)?;
+            Ok((name, layout))
+        })
+        .collect()
+}
+
+/// Write the layout of `state` into the monitors.xml at `path` as the configuration for its
+/// monitors, keeping the configurations of other sets of monitors
+///
+/// The previous file is kept as `monitors.xml.bak`, which is returned, and the new one takes
+/// its owner and permissions (or the owner of its directory), so that GDM can still update it.
+pub fn export(path: &Path, state: &CurrentState) -> Result<Option<PathBuf>> {
+    let write_error = |error| Error::MonitorsXmlWrite {
+        path: path.into(),
+        error,
+    };
+    let existing = match fs::read_to_string(path) {
+        Ok(content) => Some(content),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+        Err(error) => {
+            return Err(Error::MonitorsXmlRead {
+                path: path.into(),
+                error,
+            });
+        }
+    };
+
+    let connected: Vec<ConnectorInfo> = state
+        .monitors
+        .iter()
+        .map(|monitor| monitor.connector_info.clone())
+        .collect();
+    let connected = monitor_set(&connected);
+    let mut content = String::from("<monitors version=\"2\">\n");
+    if let Some(existing) = &existing {
+        let document = document(path, existing)?;
+        for configuration in configurations(path, &document)? {
+            if configuration.monitor_set != connected {
+                content += "  ";
+                content += &existing[configuration.node.range()];
+                content += "\n";
+            }
+        }
+    }
+    write_configuration(&mut content, state).expect("writing to a String cannot fail");
+    content += "</monitors>\n";
+
+    let directory = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(directory).map_err(write_error)?;
+    let owner = fs::metadata(path)
+        .or_else(|_| fs::metadata(directory))
+        .map_err(write_error)?;
+
+    let backup = match existing {
+        Some(_) => {
+            let backup = path.with_extension("xml.bak");
+            fs::copy(path, &backup).map_err(write_error)?;
+            Some(backup)
+        }
+        None => None,
+    };
+
+    // Write next to the file and rename, so that a failed write leaves the old one intact
+    let partial = path.with_extension("xml.partial");
+    fs::write(&partial, content).map_err(write_error)?;
+    if owner.is_file() {
+        fs::set_permissions(&partial, owner.permissions()).map_err(write_error)?;
+    }
+    let written = fs::metadata(&partial).map_err(write_error)?;
+    if (written.uid(), written.gid()) != (owner.uid(), owner.gid()) {
+        unix::fs::chown(&partial, Some(owner.uid()), Some(owner.gid())).map_err(write_error)?;
+    }
+    fs::rename(&partial, path).map_err(write_error)?;
+    Ok(backup)
+}
+
+/// Append the `<configuration>` of `state`, listing unused monitors as `<disabled>`
+fn write_configuration(content: &mut String, state: &CurrentState) -> fmt::Result {
+    writeln!(content, "  <configuration>")?;
+    // Positions are logical pixels, while Mutter defaults to physical ones
+    writeln!(content, "    <layoutmode>logical</layoutmode>")?;
+    let layout = SavedLayout::snapshot(state);
+    for logical in &layout.logical_monitors {
+        writeln!(content, "    <logicalmonitor>")?;
+        writeln!(content, "      <x>{}</x>", logical.x)?;
+        writeln!(content, "      <y>{}</y>", logical.y)?;
+        writeln!(content, "      <scale>{}</scale>", logical.scale)?;
+        if logical.primary {
+            writeln!(content, "      <primary>yes</primary>")?;
+        }
+        if logical.transform != 0 {
+            let rotation =
+                ["normal", "left", "upside_down", "right"][logical.transform as usize % 4];
+            let flipped = if logical.transform >= 4 { "yes" } else { "no" };
+            writeln!(content, "      <transform>")?;
+            writeln!(content, "        <rotation>{rotation}</rotation>")?;
+            writeln!(content, "        <flipped>{flipped}</flipped>")?;
+            writeln!(content, "      </transform>")?;
+        }
+        for monitor in &logical.monitors {
+            let info = ConnectorInfo {
+                connector: monitor.connector.clone(),
+                vendor: monitor.vendor.clone(),
+                product: monitor.product.clone(),
+                serial: monitor.serial.clone(),
+            };
+            writeln!(content, "      <monitor>")?;
+            write_monitor_spec(content, "        ", &info)?;
+            writeln!(content, "        <mode>")?;
+            writeln!(content, "          <width>{}</width>", monitor.width)?;
+            writeln!(content, "          <height>{}</height>", monitor.height)?;
+            writeln!(content, "          <rate>{}</rate>", monitor.refresh_rate)?;
+            writeln!(content, "        </mode>")?;
+            writeln!(content, "      </monitor>")?;
+        }
+        writeln!(content, "    </logicalmonitor>")?;
+    }
+
+    let disabled = state.monitors.iter().filter(|monitor| {
+        !state.logical_monitors.iter().any(|logical| {
+            logical
+                .assigned_monitors
+                .iter()
+                .any(|info| info.connector == monitor.connector_info.connector)
+        })
+    });
+    for monitor in disabled {
+        writeln!(content, "    <disabled>")?;
+        write_monitor_spec(content, "      ", &monitor.connector_info)?;
+        writeln!(content, "    </disabled>")?;
+    }
+    writeln!(content, "  </configuration>")?;
+    Ok(())
+}
+
+fn write_monitor_spec(content: &mut String, indent: &str, info: &ConnectorInfo) -> fmt::Result {
+    writeln!(content, "{indent}<monitorspec>")?;
+    for (name, value) in [
+        ("connector", &info.connector),
+        ("vendor", &info.vendor),
+        ("product", &info.product),
+        ("serial", &info.serial),
+    ] {
+        writeln!(content, "{indent}  <{name}>{}</{name}>", escape_text(value))?;
+    }
+    writeln!(content, "{indent}</monitorspec>")
+}
+
+/// `text` escaped for element content; attribute values would also need their quotes escaped
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Sorted `connector/vendor/product/serial` of `connectors`
+fn monitor_set(connectors: &[ConnectorInfo]) -> Vec<String> {
+    let mut set: Vec<String> = connectors
+        .iter()
+        .map(|info| {
+            format!(
+                "{}/{}/{}/{}",
+                info.connector, info.vendor, info.product, info.serial
+            )
+        })
+        .collect();
+    set.sort();
+    set
+}
+
+fn monitor_spec(
+    monitor: Node<'_, '_>,
+    invalid: &impl Fn(Node<'_, '_>, String) -> Error,
+) -> Result<ConnectorInfo> {
+    let spec = element(monitor, "monitorspec")
+        .ok_or_else(|| invalid(monitor, "<monitor> has no <monitorspec>".into()))?;
+    connector_info(spec, invalid)
+}
+
+fn connector_info(
+    spec: Node<'_, '_>,
+    invalid: &impl Fn(Node<'_, '_>, String) -> Error,
+) -> Result<ConnectorInfo> {
+    let field = |name: &str| {
+        text(spec, name)
+            .map(ToString::to_string)
+            .ok_or_else(|| invalid(spec, format!("<monitorspec> has no <{name}>")))
+    };
+    Ok(ConnectorInfo {
+        connector: field("connector")?,
+        vendor: field("vendor")?,
+        product: field("product")?,
+        serial: field("serial")?,
+    })
+}
+
+/// `<rotation>` (counterclockwise) and `<flipped>` as a Mutter transform
+fn transform(node: Node<'_, '_>) -> u32 {
+    let rotation = match text(node, "rotation") {
+        Some("left") => 1,
+        Some("upside_down") => 2,
+        Some("right") => 3,
+        _ => 0,
+    };
+    let flipped = if text(node, "flipped") == Some("yes") {
+        4
+    } else {
+        0
+    };
+    rotation + flipped
+}
+
+fn elements<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.has_tag_name(name))
+}
+
+fn element<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    elements(node, name).next()
+}
+
+fn text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    element(node, name)?.text().map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake_mutter::{FakeMonitor, FakeMutter};
+
+    const MONITORS_XML: &str = r#"<monitors version="2">
+  <configuration>
+    <logicalmonitor>
+      <x>0</x>
+      <y>0</y>
+      <scale>1</scale>
+      <primary>yes</primary>
+      <monitor>
+        <monitorspec>
+          <connector>DP-1</connector>
+          <vendor>ACR</vendor>
+          <product>ET430K</product>
+          <serial>0x0000000a</serial>
+        </monitorspec>
+        <mode>
+          <width>3840</width>
+          <height>2160</height>
+          <rate>60.000</rate>
+        </mode>
+      </monitor>
+    </logicalmonitor>
+    <disabled>
+      <monitorspec>
+        <connector>eDP-1</connector>
+        <vendor>BOE</vendor>
+        <product>0x0bca</product>
+        <serial>0x00000000</serial>
+      </monitorspec>
+    </disabled>
+  </configuration>
+  <configuration>
+    <layoutmode>logical</layoutmode>
+    <logicalmonitor>
+      <x>0</x>
+      <y>0</y>
+      <scale>1.5</scale>
+      <primary>yes</primary>
+      <transform>
+        <rotation>left</rotation>
+        <flipped>no</flipped>
+      </transform>
+      <monitor>
+        <monitorspec>
+          <connector>eDP-1</connector>
+          <vendor>BOE</vendor>
+          <product>0x0bca</product>
+          <serial>0x00000000</serial>
+        </monitorspec>
+        <mode>
+          <width>2256</width>
+          <height>1504</height>
+          <rate>59.999</rate>
+        </mode>
+      </monitor>
+    </logicalmonitor>
+  </configuration>
+</monitors>
+"#;
+
+    fn parse(content: &str) -> Result<Vec<SavedLayout>> {
+        super::parse(Path::new("monitors.xml"), content)
+    }
+
+    #[test]
+    fn parses_each_configuration_as_a_layout() {
+        let layouts = parse(MONITORS_XML).unwrap();
+        assert_eq!(layouts.len(), 2);
+
+        let dock = &layouts[0];
+        assert_eq!(
+            dock.fingerprint.to_string(),
+            "ACR/ET430K/0x0000000a, BOE/0x0bca/0x00000000"
+        );
+        assert_eq!(dock.logical_monitors.len(), 1);
+        let monitor = &dock.logical_monitors[0].monitors[0];
+        assert_eq!(
+            (monitor.connector.as_str(), monitor.mode.as_str()),
+            ("DP-1", "3840x2160@60.000")
+        );
+        assert!(dock.logical_monitors[0].primary);
+
+        let laptop = &layouts[1].logical_monitors[0];
+        assert_eq!((laptop.scale, laptop.transform), (1.5, 1));
+        assert_eq!(layouts[1].fingerprint.to_string(), "BOE/0x0bca/0x00000000");
+    }
+
+    #[test]
+    fn imports_configurations_as_saved_layouts() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("monitors.xml");
+        fs::write(&path, MONITORS_XML).unwrap();
+        let layouts = directory.path().join("layouts");
+
+        let imported = import(&path, &layouts, "gnome").unwrap();
+        let names: Vec<_> = imported.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["gnome-1", "gnome-2"]);
+        assert_eq!(
+            SavedLayout::load(&layouts, "gnome-2").unwrap(),
+            imported[1].1
+        );
+    }
+
+    #[tokio::test]
+    async fn exports_the_current_layout_for_its_monitors() {
+        let fake = FakeMutter::new(vec![
+            FakeMonitor::builtin("eDP-1"),
+            FakeMonitor::external("DP-1", "ACR", "ET430K"),
+        ]);
+        let backend = fake.connect().await.unwrap();
+        fake.state().logical_monitors.truncate(1);
+        let state = CurrentState::current(backend.as_ref(), 10).await.unwrap();
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("gdm/.config/monitors.xml");
+        assert_eq!(export(&path, &state).unwrap(), None);
+        assert!(
+            fs::read_to_string(&path)
+                .unwrap()
+                .contains("<layoutmode>logical</layoutmode>")
+        );
+
+        let exported = super::load(&path).unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].fingerprint, Fingerprint::of(&state.monitors));
+        assert_eq!(exported[0].resolve("gdm", &state).unwrap(), state.layout());
+
+        // Other sets of monitors keep their configuration, this one is replaced
+        fs::write(&path, MONITORS_XML).unwrap();
+        let backup = export(&path, &state).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(backup).unwrap(), MONITORS_XML);
+        let exported = super::load(&path).unwrap();
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported[0], parse(MONITORS_XML).unwrap()[1]);
+        assert_eq!(exported[1].resolve("gdm", &state).unwrap(), state.layout());
+    }
+
+    #[test]
+    fn refuses_scaled_physical_configurations() {
+        // A 4K monitor at scale 2, with the laptop screen right of it at 3840 physical pixels:
+        // 1920 in logical ones
+        let physical = MONITORS_XML
+            .replacen("<scale>1</scale>", "<scale>2</scale>", 1)
+            .replacen(
+                "    </logicalmonitor>\n    <disabled>",
+                "    </logicalmonitor>\n    <logicalmonitor>\n      <x>3840</x>\n      \
+                 <y>0</y>\n      <scale>1</scale>\n      <monitor>\n        <monitorspec>\n          \
+                 <connector>eDP-1</connector>\n          <vendor>BOE</vendor>\n          \
+                 <product>0x0bca</product>\n          <serial>0x00000000</serial>\n        \
+                 </monitorspec>\n        <mode>\n          <width>2256</width>\n          \
+                 <height>1504</height>\n          <rate>59.999</rate>\n        </mode>\n      \
+                 </monitor>\n    </logicalmonitor>\n    <disabled>",
+                1,
+            );
+        let error = parse(&physical).unwrap_err();
+        assert!(
+            matches!(error, Error::InvalidMonitorsXml { line: 2, .. }),
+            "{error}"
+        );
+        assert!(error.to_string().contains("physical pixels"));
+
+        let logical = physical.replacen(
+            "  <configuration>\n",
+            "  <configuration>\n    <layoutmode>logical</layoutmode>\n",
+            1,
+        );
+        let layouts = parse(&logical).unwrap();
+        assert_eq!(layouts[0].logical_monitors[1].x, 3840);
+
+        let unknown = MONITORS_XML.replacen(">logical<", ">virtual<", 1);
+        assert!(matches!(
+            parse(&unknown),
+            Err(Error::InvalidMonitorsXml { .. })
+        ));
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let line = |content| match parse(content) {
+            Err(Error::InvalidMonitorsXml { line, .. }) => line,
+            result => panic!("expected an invalid monitors.xml, got {result:?}"),
+        };
+
+        assert_eq!(line("<monitors>\n<configuration>\n</monitors>"), 3);
+        assert_eq!(line("<settings/>"), 1);
+        assert_eq!(
+            line(
+                "<monitors>\n<configuration>\n<logicalmonitor>\n<monitor>\n<mode/>\n</monitor>\n</logicalmonitor>\n</configuration>\n</monitors>"
+            ),
+            4
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{ApplyLogicalMonitorTuple, ConnectorInfo, Monitor};
+use crate::{ApplyLogicalMonitorTuple, ConnectorInfo, Monitor, cli::Transform};
 
 /// TODO: Possible to unify this with [`crate::CurrentLogicalMonitor`]?
 /// Converts an ApplyLogicalMonitorTuple into a user-friendly structure for printing
@@ -98,7 +98,7 @@ impl PrintableLogicalMonitor {
         println!("  {}. Position: ({}, {})", index + 1, self.x, self.y);
         println!("     Scale: {}", self.scale);
         println!("     Primary: {}", self.primary);
-        println!("     Transform: {}", self.transform);
+        println!("     Transform: {}", Transform::describe(self.transform));
         println!("     Assigned Monitors:");
 
         for (i, monitor) in self.assigned_monitors.iter().enumerate() {
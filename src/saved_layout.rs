@@ -0,0 +1,423 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ApplyLogicalMonitorTuple, ConnectorInfo, CurrentState, Error, Monitor, Result};
+
+/// A layout saved with `set-display save NAME`, in
+/// `$XDG_CONFIG_HOME/set-display/layouts/NAME.toml`
+///
+/// Monitors are identified by vendor, product and serial rather than by connector, so the
+/// layout still applies when a monitor is plugged into another port.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedLayout {
+    /// Monitors that were connected when the layout was saved; watching loads the layout
+    /// whenever exactly these are connected again
+    #[serde(default, skip_serializing_if = "Fingerprint::is_empty")]
+    pub fingerprint: Fingerprint,
+    #[serde(default)]
+    pub logical_monitors: Vec<SavedLogicalMonitor>,
+}
+
+/// The connected monitors as `vendor/product/serial`, sorted, so that the same monitors have
+/// the same fingerprint whatever connectors they are on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Fingerprint(Vec<String>);
+
+impl Fingerprint {
+    pub fn of(monitors: &[Monitor]) -> Self {
+        let connectors: Vec<_> = monitors
+            .iter()
+            .map(|monitor| monitor.connector_info.clone())
+            .collect();
+        Self::of_connectors(&connectors)
+    }
+
+    pub fn of_connectors(connectors: &[ConnectorInfo]) -> Self {
+        let mut identities: Vec<String> = connectors
+            .iter()
+            .map(|info| format!("{}/{}/{}", info.vendor, info.product, info.serial))
+            .collect();
+        identities.sort();
+        Self(identities)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedLogicalMonitor {
+    pub x: i32,
+    pub y: i32,
+    pub scale: f64,
+    #[serde(default)]
+    pub transform: u32,
+    #[serde(default)]
+    pub primary: bool,
+    pub monitors: Vec<SavedMonitor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedMonitor {
+    pub vendor: String,
+    pub product: String,
+    pub serial: String,
+    /// Connector the monitor was on when saved; only breaks ties between identical monitors
+    pub connector: String,
+    /// Mode id, falling back to the mode of the same size and refresh rate
+    pub mode: String,
+    pub width: i32,
+    pub height: i32,
+    pub refresh_rate: f64,
+}
+
+impl SavedMonitor {
+    fn is(&self, info: &ConnectorInfo) -> bool {
+        self.vendor == info.vendor && self.product == info.product && self.serial == info.serial
+    }
+}
+
+impl SavedLayout {
+    /// `layouts` next to the configuration file at `config_path`, by default
+    /// `$XDG_CONFIG_HOME/set-display/layouts`
+    pub fn directory(config_path: &Path) -> PathBuf {
+        config_path.with_file_name("layouts")
+    }
+
+    /// Path of the layout called `name` in `directory`
+    pub fn path(directory: &Path, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(Error::InvalidLayoutName(name.to_string()));
+        }
+        Ok(directory.join(format!("{name}.toml")))
+    }
+
+    /// The logical monitors of `state`, with the mode each monitor currently uses
+    pub fn snapshot(state: &CurrentState) -> Self {
+        let logical_monitors = state
+            .logical_monitors
+            .iter()
+            .map(|logical| SavedLogicalMonitor {
+                x: logical.x,
+                y: logical.y,
+                scale: logical.scale,
+                transform: logical.transform,
+                primary: logical.primary,
+                monitors: logical
+                    .assigned_monitors
+                    .iter()
+                    .filter_map(|info| {
+                        let mode = state
+                            .monitors
+                            .iter()
+                            .find(|monitor| monitor.connector_info.connector == info.connector)?
+                            .modes
+                            .iter()
+                            .find(|mode| mode.is_current)?;
+                        Some(SavedMonitor {
+                            vendor: info.vendor.clone(),
+                            product: info.product.clone(),
+                            serial: info.serial.clone(),
+                            connector: info.connector.clone(),
+                            mode: mode.id.clone(),
+                            width: mode.width,
+                            height: mode.height,
+                            refresh_rate: mode.refresh_rate,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self {
+            fingerprint: Fingerprint::of(&state.monitors),
+            logical_monitors,
+        }
+    }
+
+    /// Every layout saved in `directory`, by name
+    pub fn all(directory: &Path) -> Result<Vec<(String, Self)>> {
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => {
+                return Err(Error::LayoutRead {
+                    path: directory.into(),
+                    error,
+                });
+            }
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "toml" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .collect();
+        names.sort();
+
+        // A broken layout shouldn't hide the others
+        Ok(names
+            .into_iter()
+            .filter_map(|name| match Self::load(directory, &name) {
+                Ok(layout) => Some((name, layout)),
+                Err(error) => {
+                    eprintln!("{error}");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// The first saved layout, by name, whose fingerprint is `fingerprint`
+    pub fn matching(directory: &Path, fingerprint: &Fingerprint) -> Result<Option<(String, Self)>> {
+        Ok(Self::all(directory)?.into_iter().find(|(_, layout)| {
+            !layout.fingerprint.is_empty() && layout.fingerprint == *fingerprint
+        }))
+    }
+
+    pub fn load(directory: &Path, name: &str) -> Result<Self> {
+        let path = Self::path(directory, name)?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::UnknownLayout(name.to_string()));
+            }
+            Err(error) => return Err(Error::LayoutRead { path, error }),
+        };
+        toml::from_str(&content).map_err(|error| Error::InvalidLayout {
+            path,
+            message: error.message().trim().to_string(),
+        })
+    }
+
+    /// Write the layout as `name`, replacing any earlier layout of that name
+    pub fn save(&self, directory: &Path, name: &str) -> Result<PathBuf> {
+        let path = Self::path(directory, name)?;
+        let write_error = |error| Error::LayoutWrite {
+            path: path.clone(),
+            error,
+        };
+
+        let content = toml::to_string(self).expect("saved layouts serialize");
+        fs::create_dir_all(directory).map_err(write_error)?;
+        // Write next to the layout and rename, so that a failed write leaves the old one intact
+        let partial = path.with_extension("toml.partial");
+        fs::write(&partial, content).map_err(write_error)?;
+        fs::rename(&partial, &path).map_err(write_error)?;
+        Ok(path)
+    }
+
+    /// The layout as arguments for the connected monitors of `state`, finding each saved monitor
+    /// by identity on whatever connector it is now
+    pub fn resolve(
+        &self,
+        name: &str,
+        state: &CurrentState,
+    ) -> Result<Vec<ApplyLogicalMonitorTuple>> {
+        let mut used: Vec<&str> = vec![];
+        let mut resolve = |saved: &SavedMonitor| -> Result<(String, String, _)> {
+            let candidates = state
+                .monitors
+                .iter()
+                .filter(|monitor| saved.is(&monitor.connector_info))
+                .filter(|monitor| !used.contains(&monitor.connector_info.connector.as_str()));
+            // Identical monitors (same serial) keep to their saved connectors if they can
+            let monitor = candidates
+                .min_by_key(|monitor| monitor.connector_info.connector != saved.connector)
+                .ok_or_else(|| {
+                    Error::MonitorNotConnected(format!(
+                        "{} {} {} of layout '{name}'",
+                        saved.vendor, saved.product, saved.serial
+                    ))
+                })?;
+            used.push(&monitor.connector_info.connector);
+
+            let mode = find_mode(monitor, saved).ok_or_else(|| Error::ModeUnavailable {
+                connector: monitor.connector_info.connector.clone(),
+                mode: saved.mode.clone(),
+                closest: None,
+            })?;
+            Ok((
+                monitor.connector_info.connector.clone(),
+                mode,
+                HashMap::new(),
+            ))
+        };
+
+        self.logical_monitors
+            .iter()
+            .map(|logical| {
+                let assigned = logical
+                    .monitors
+                    .iter()
+                    .map(&mut resolve)
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((
+                    logical.x,
+                    logical.y,
+                    logical.scale,
+                    logical.transform,
+                    logical.primary,
+                    assigned,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Print the fingerprint of the connected monitors and the saved layout it selects
+pub fn print_detection(state: &CurrentState, directory: &Path) -> Result<()> {
+    let fingerprint = Fingerprint::of(&state.monitors);
+    println!("=== Connected Monitors ===");
+    println!("Fingerprint: {fingerprint}\n");
+    match SavedLayout::matching(directory, &fingerprint)? {
+        Some((name, _)) => println!("Saved layout: {name}"),
+        None => println!("No saved layout matches these monitors; the rules apply."),
+    }
+    Ok(())
+}
+
+/// Id of the saved mode on `monitor`, or of a mode with the same size and refresh rate
+fn find_mode(monitor: &Monitor, saved: &SavedMonitor) -> Option<String> {
+    monitor
+        .modes
+        .iter()
+        .find(|mode| mode.id == saved.mode)
+        .or_else(|| {
+            monitor.modes.iter().find(|mode| {
+                mode.width == saved.width
+                    && mode.height == saved.height
+                    && (mode.refresh_rate - saved.refresh_rate).abs() < 0.01
+            })
+        })
+        .map(|mode| mode.id.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake_mutter::{FakeMonitor, FakeMutter};
+
+    async fn state(monitors: Vec<FakeMonitor>) -> CurrentState {
+        let fake = FakeMutter::new(monitors);
+        let backend = fake.connect().await.unwrap();
+        CurrentState::current(backend.as_ref(), 1).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn saves_and_loads_layouts() {
+        let directory = tempfile::tempdir().unwrap();
+        let state = state(vec![
+            FakeMonitor::builtin("eDP-1"),
+            FakeMonitor::external("DP-1", "ACR", "ET430K"),
+        ])
+        .await;
+
+        let layout = SavedLayout::snapshot(&state);
+        assert_eq!(layout.logical_monitors.len(), 2);
+        assert_eq!(layout.logical_monitors[1].monitors[0].product, "ET430K");
+
+        let path = layout.save(directory.path(), "desk").unwrap();
+        assert_eq!(path, directory.path().join("desk.toml"));
+        assert_eq!(SavedLayout::load(directory.path(), "desk").unwrap(), layout);
+
+        assert!(matches!(
+            SavedLayout::load(directory.path(), "couch"),
+            Err(Error::UnknownLayout(_))
+        ));
+        assert!(matches!(
+            layout.save(directory.path(), "../desk"),
+            Err(Error::InvalidLayoutName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn resolves_monitors_that_moved_to_another_connector() {
+        let before = state(vec![
+            FakeMonitor::builtin("eDP-1"),
+            FakeMonitor::external("DP-1", "ACR", "ET430K"),
+        ])
+        .await;
+        let layout = SavedLayout::snapshot(&before);
+
+        let after = state(vec![
+            FakeMonitor::builtin("eDP-1"),
+            FakeMonitor::external("HDMI-2", "GSM", "LG TV"),
+            FakeMonitor::external("DP-3", "ACR", "ET430K"),
+        ])
+        .await;
+        let resolved = layout.resolve("desk", &after).unwrap();
+        let connectors: Vec<_> = resolved
+            .iter()
+            .map(|(_, _, _, _, _, assigned)| assigned[0].0.as_str())
+            .collect();
+        assert_eq!(connectors, ["eDP-1", "DP-3"]);
+        assert_eq!(resolved[1].5[0].1, "3840x2160@60.000");
+
+        let unplugged = state(vec![FakeMonitor::builtin("eDP-1")]).await;
+        assert!(matches!(
+            layout.resolve("desk", &unplugged),
+            Err(Error::MonitorNotConnected(monitor)) if monitor.ends_with("of layout 'desk'")
+        ));
+    }
+
+    #[tokio::test]
+    async fn matches_layouts_by_fingerprint() {
+        let directory = tempfile::tempdir().unwrap();
+        let desk = state(vec![
+            FakeMonitor::builtin("eDP-1"),
+            FakeMonitor::external("DP-1", "ACR", "ET430K"),
+        ])
+        .await;
+        SavedLayout::snapshot(&desk)
+            .save(directory.path(), "desk")
+            .unwrap();
+
+        // Layouts without a fingerprint are only loaded by name
+        let mut manual = SavedLayout::snapshot(&desk);
+        manual.fingerprint = Fingerprint::default();
+        manual.save(directory.path(), "manual").unwrap();
+
+        // Same monitors on other connectors, in another order
+        let moved = state(vec![
+            FakeMonitor::external("HDMI-1", "ACR", "ET430K"),
+            FakeMonitor::builtin("eDP-1"),
+        ])
+        .await;
+        let fingerprint = Fingerprint::of(&moved.monitors);
+        assert_eq!(
+            fingerprint.to_string(),
+            "ACR/ET430K/0x0000000a, BOE/0x0bca/0x00000000"
+        );
+        let (name, _) = SavedLayout::matching(directory.path(), &fingerprint)
+            .unwrap()
+            .unwrap();
+        assert_eq!(name, "desk");
+
+        let laptop = state(vec![FakeMonitor::builtin("eDP-1")]).await;
+        assert!(
+            SavedLayout::matching(directory.path(), &Fingerprint::of(&laptop.monitors))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
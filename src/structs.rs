@@ -14,6 +14,19 @@ pub struct ConnectorInfo {
     pub serial: String,
 }
 
+impl ConnectorInfo {
+    /// Connector name prefixes used for built-in panels
+    const BUILTIN_CONNECTORS: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+    /// Guess from the connector name whether this is a built-in panel, for compositors that
+    /// don't report it themselves
+    pub fn is_builtin_connector(&self) -> bool {
+        Self::BUILTIN_CONNECTORS
+            .iter()
+            .any(|prefix| self.connector.starts_with(prefix))
+    }
+}
+
 pub type ConnectorInfoTuple = (String, String, String, String);
 
 impl From<ConnectorInfoTuple> for ConnectorInfo {